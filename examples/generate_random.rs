@@ -98,11 +98,7 @@ impl Distribution<ICalObject> for ObjectDistribution {
             }
         }
         let name = self.type_distribution.sample(rng);
-        ICalObject {
-            object_type: name,
-            properties,
-            sub_objects,
-        }
+        ICalObject::new(name, properties, sub_objects)
     }
 }
 
@@ -8,7 +8,7 @@ cargo run --release --example generate_random > private-test-icals/generated.ica
 
 # also run tests, clippy and fmt
 
-cargo test --all --locked
+cargo test --all --locked --all-features
 
 cargo clippy
 
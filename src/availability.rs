@@ -0,0 +1,335 @@
+// VAVAILABILITY and AVAILABLE components, see https://www.rfc-editor.org/rfc/rfc7953
+//
+// the time a VAVAILABILITY covers is busy, with its BUSYTYPE, except for the
+// occurrences of its AVAILABLE components, components with a higher PRIORITY
+// replace those with a lower one where they overlap
+
+use eyre::{eyre, Result};
+
+use crate::datetime::{parse_duration, DateTime};
+use crate::freebusy::{coalesce, subtract, FreeBusyType};
+use crate::recurrence::expand;
+use crate::timezone::TimeZones;
+use crate::value::same_address;
+use crate::ICalObject;
+
+/// a VAVAILABILITY
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Availability<'a> {
+    pub component: &'a ICalObject,
+    pub uid: Option<&'a str>,
+    pub organizer: Option<&'a str>,
+    pub summary: Option<&'a str>,
+    /// the time covered in UTC, None is unbounded
+    pub start: Option<DateTime>,
+    pub end: Option<DateTime>,
+    /// BUSY-UNAVAILABLE unless BUSYTYPE says otherwise
+    pub busy_type: FreeBusyType,
+    /// 1 is the highest and 9 the lowest, 0 (the default) is below 9
+    pub priority: u8,
+    pub available: Vec<Available<'a>>,
+}
+
+/// an AVAILABLE, its start and end are those of DTSTART, see
+/// [Availability::available] for its occurrences
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Available<'a> {
+    pub component: &'a ICalObject,
+    pub uid: Option<&'a str>,
+    pub summary: Option<&'a str>,
+    pub start: DateTime,
+    pub end: DateTime,
+    pub recurs: bool,
+}
+
+fn value<'a>(component: &'a ICalObject, name: &str) -> Option<&'a str> {
+    component.get_property(name).map(|line| line.value.as_str())
+}
+
+// the UTC times of DTSTART and of DTEND or DTSTART plus DURATION
+fn span(component: &ICalObject, zones: &TimeZones) -> Result<(Option<DateTime>, Option<DateTime>)> {
+    let start = match component.get_property("DTSTART") {
+        Some(line) => Some(zones.to_utc(line, &line.value)?),
+        None => None,
+    };
+    let end = match (
+        component.get_property("DTEND"),
+        value(component, "DURATION"),
+        start,
+    ) {
+        (Some(end), ..) => Some(zones.to_utc(end, &end.value)?),
+        (None, Some(duration), Some(start)) => Some(start.add_seconds(parse_duration(duration)?)),
+        _ => None,
+    };
+    Ok((start, end))
+}
+
+impl<'a> Availability<'a> {
+    pub fn from_component(component: &'a ICalObject, zones: &TimeZones) -> Result<Self> {
+        if !component.is_type("VAVAILABILITY") {
+            return Err(eyre!(
+                "expected a VAVAILABILITY, found {}",
+                component.object_type
+            ));
+        }
+        let busy_type = match value(component, "BUSYTYPE") {
+            Some(busy_type) => {
+                FreeBusyType::from_name(busy_type.trim()).unwrap_or(FreeBusyType::BusyUnavailable)
+            }
+            None => FreeBusyType::BusyUnavailable,
+        };
+        let priority = match value(component, "PRIORITY") {
+            Some(priority) => priority
+                .trim()
+                .parse()
+                .ok()
+                .filter(|priority| *priority <= 9)
+                .ok_or_else(|| eyre!("invalid PRIORITY: {}", priority))?,
+            None => 0,
+        };
+        let (start, end) = span(component, zones)?;
+        let mut available = Vec::new();
+        for sub_object in component.get_sub_objects("AVAILABLE") {
+            let (Some(start), Some(end)) = span(sub_object, zones)? else {
+                return Err(eyre!("AVAILABLE without DTSTART and DTEND or DURATION"));
+            };
+            available.push(Available {
+                component: sub_object,
+                uid: value(sub_object, "UID"),
+                summary: value(sub_object, "SUMMARY"),
+                start,
+                end,
+                recurs: sub_object.get_property("RRULE").is_some()
+                    || sub_object.get_property("RDATE").is_some(),
+            });
+        }
+        Ok(Availability {
+            component,
+            uid: value(component, "UID"),
+            organizer: value(component, "ORGANIZER"),
+            summary: value(component, "SUMMARY"),
+            start,
+            end,
+            busy_type,
+            priority,
+            available,
+        })
+    }
+
+    /// the VAVAILABILITY components of a calendar
+    pub fn all(calendar: &'a ICalObject, zones: &TimeZones) -> Result<Vec<Self>> {
+        calendar
+            .get_sub_objects("VAVAILABILITY")
+            .map(|component| Availability::from_component(component, zones))
+            .collect()
+    }
+
+    /// the part of the window from start to end the component covers
+    pub fn covered(&self, start: DateTime, end: DateTime) -> Option<(DateTime, DateTime)> {
+        let from = self.start.map_or(start, |covered| covered.max(start));
+        let to = self.end.map_or(end, |covered| covered.min(end));
+        (from < to).then_some((from, to))
+    }
+
+    /// the occurrences of the AVAILABLE components from start to end, coalesced
+    pub fn available(
+        &self,
+        zones: &TimeZones,
+        start: DateTime,
+        end: DateTime,
+    ) -> Result<Vec<(DateTime, DateTime)>> {
+        let components = self.available.iter().map(|available| available.component);
+        let instances = expand(components, zones, start, end)?;
+        Ok(coalesce(
+            instances
+                .into_iter()
+                .map(|instance| (instance.start.max(start), instance.end.min(end)))
+                .collect(),
+        ))
+    }
+
+    /// the busy time of the component alone from start to end
+    pub fn busy(
+        &self,
+        zones: &TimeZones,
+        start: DateTime,
+        end: DateTime,
+    ) -> Result<Vec<(DateTime, DateTime)>> {
+        let Some(covered) = self.covered(start, end) else {
+            return Ok(Vec::new());
+        };
+        Ok(subtract(vec![covered], &self.available(zones, start, end)?))
+    }
+
+    // the order of layering, the lowest priority first
+    fn rank(&self) -> u8 {
+        match self.priority {
+            0 => 0,
+            priority => 10 - priority,
+        }
+    }
+}
+
+/// the busy time from the VAVAILABILITY components of calendars from start
+/// to end, those with an ORGANIZER other than the attendee are skipped,
+/// components with the same PRIORITY combine their AVAILABLE time
+pub fn unavailable_time(
+    calendars: &[ICalObject],
+    attendee: &str,
+    start: DateTime,
+    end: DateTime,
+) -> Result<Vec<((DateTime, DateTime), FreeBusyType)>> {
+    let mut layers = Vec::new();
+    for calendar in calendars {
+        let zones = TimeZones::from_calendar(calendar)?;
+        for availability in Availability::all(calendar, &zones)? {
+            if availability
+                .organizer
+                .is_some_and(|organizer| !same_address(organizer, attendee))
+            {
+                continue;
+            }
+            let Some(covered) = availability.covered(start, end) else {
+                continue;
+            };
+            let available = availability.available(&zones, start, end)?;
+            layers.push((
+                availability.rank(),
+                covered,
+                available,
+                availability.busy_type,
+            ));
+        }
+    }
+    layers.sort_by_key(|(rank, ..)| *rank);
+
+    let mut busy: Vec<((DateTime, DateTime), FreeBusyType)> = Vec::new();
+    for group in layers.chunk_by(|a, b| a.0 == b.0) {
+        let covered = coalesce(group.iter().map(|(_, covered, ..)| *covered).collect());
+        let available = coalesce(
+            group
+                .iter()
+                .flat_map(|(_, _, available, _)| available.iter().copied())
+                .collect(),
+        );
+        busy = busy
+            .into_iter()
+            .flat_map(|(period, busy_type)| {
+                subtract(vec![period], &covered)
+                    .into_iter()
+                    .map(move |period| (period, busy_type))
+            })
+            .collect();
+        for (_, covered, _, busy_type) in group {
+            for period in subtract(vec![*covered], &available) {
+                busy.push((period, *busy_type));
+            }
+        }
+    }
+    busy.sort();
+    Ok(busy)
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::freebusy::free_busy;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+BEGIN:VTIMEZONE\r
+TZID:Europe/Berlin\r
+BEGIN:DAYLIGHT\r
+DTSTART:19810329T020000\r
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+DTSTART:19961027T030000\r
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VAVAILABILITY\r
+UID:working-hours\r
+ORGANIZER:mailto:room@example.com\r
+BEGIN:AVAILABLE\r
+UID:weekdays\r
+SUMMARY:Office hours\r
+DTSTART;TZID=Europe/Berlin:20240101T080000\r
+DTEND;TZID=Europe/Berlin:20240101T180000\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR\r
+END:AVAILABLE\r
+END:VAVAILABILITY\r
+BEGIN:VAVAILABILITY\r
+UID:maintenance\r
+PRIORITY:1\r
+BUSYTYPE:BUSY\r
+DTSTART;TZID=Europe/Berlin:20240328T000000\r
+DTEND;TZID=Europe/Berlin:20240329T000000\r
+END:VAVAILABILITY\r
+BEGIN:VAVAILABILITY\r
+UID:someone-else\r
+ORGANIZER:mailto:ben@example.com\r
+END:VAVAILABILITY\r
+BEGIN:VEVENT\r
+UID:meeting\r
+DTSTART;TZID=Europe/Berlin:20240327T090000\r
+DTEND;TZID=Europe/Berlin:20240327T100000\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn layers_availability() {
+        let calendar: ICalObject = CALENDAR.parse().unwrap();
+        let zones = TimeZones::from_calendar(&calendar).unwrap();
+        let availabilities = Availability::all(&calendar, &zones).unwrap();
+        assert_eq!(availabilities.len(), 3);
+        let working_hours = &availabilities[0];
+        assert_eq!(working_hours.busy_type, FreeBusyType::BusyUnavailable);
+        assert_eq!((working_hours.start, working_hours.priority), (None, 0));
+        assert_eq!(working_hours.available[0].summary, Some("Office hours"));
+        assert_eq!(
+            working_hours.available[0].start.to_string(),
+            "20240101T070000"
+        );
+        assert!(working_hours.available[0].recurs);
+        assert_eq!(availabilities[1].busy_type, FreeBusyType::Busy);
+
+        // the maintenance day replaces the working hours, the meeting is busy
+        let vfreebusy = free_busy(
+            std::slice::from_ref(&calendar),
+            "mailto:room@example.com",
+            DateTime::parse("20240327T000000").unwrap(),
+            DateTime::parse("20240329T000000").unwrap(),
+        )
+        .unwrap();
+        let periods: Vec<_> = vfreebusy
+            .get_properties("FREEBUSY")
+            .map(|line| {
+                format!(
+                    "{} {}",
+                    line.param("FBTYPE").unwrap().values()[0],
+                    line.value
+                )
+            })
+            .collect();
+        assert_eq!(
+            periods,
+            [
+                "BUSY-UNAVAILABLE 20240327T000000Z/20240327T070000Z",
+                "BUSY 20240327T080000Z/20240327T090000Z",
+                "BUSY-UNAVAILABLE 20240327T170000Z/20240327T230000Z",
+                "BUSY 20240327T230000Z/20240328T230000Z",
+                "BUSY-UNAVAILABLE 20240328T230000Z/20240329T000000Z",
+            ]
+        );
+
+        let mut invalid = calendar;
+        invalid.sub_objects[1].sub_objects[0].properties.remove(3);
+        assert!(Availability::all(&invalid, &zones).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+// a local CalDAV server over a directory of .ics files, for testing clients
+//
+// usage: caldav-server [DIRECTORY] [ADDRESS], by default the current
+// directory on 127.0.0.1:5232, every subdirectory is a calendar
+
+use std::panic::catch_unwind;
+
+use elikoga_ical_rs::caldav_server::{self, Request, Store};
+use eyre::{eyre, Result};
+use tiny_http::{Header, Response, Server};
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let directory = args.next().unwrap_or_else(|| ".".to_string());
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:5232".to_string());
+    let store = Store::new(&directory);
+    let server = Server::http(&address).map_err(|error| eyre!("{}", error))?;
+    eprintln!("serving {} on http://{}/", directory, address);
+
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+        if let Err(error) = request.as_reader().read_to_string(&mut body) {
+            eprintln!("{} {}: {}", request.method(), request.url(), error);
+            continue;
+        }
+        let handled = Request {
+            method: request.method().as_str().to_string(),
+            path: request.url().to_string(),
+            headers: request
+                .headers()
+                .iter()
+                .map(|header| (header.field.to_string(), header.value.to_string()))
+                .collect(),
+            body,
+        };
+        // a panic fails the request, not the server, the hook has printed it
+        let handled = catch_unwind(|| store.handle(&handled)).unwrap_or(caldav_server::Response {
+            status: 500,
+            ..Default::default()
+        });
+
+        let mut response = Response::from_string(handled.body).with_status_code(handled.status);
+        for (name, value) in &handled.headers {
+            match Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+                Ok(header) => response.add_header(header),
+                Err(_) => {
+                    eprintln!(
+                        "{} {}: invalid header {}",
+                        request.method(),
+                        request.url(),
+                        name
+                    );
+                    response = Response::from_string(String::new()).with_status_code(500);
+                    break;
+                }
+            }
+        }
+        eprintln!(
+            "{} {} {}",
+            request.method(),
+            request.url(),
+            response.status_code().0
+        );
+        if let Err(error) = request.respond(response) {
+            eprintln!("{}", error);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,439 @@
+// CalDAV calendar-data requests, see https://www.rfc-editor.org/rfc/rfc4791#section-9.6
+//
+// the components and properties of a calendar object resource can be limited
+// with comp and prop, recurring components can be returned as their instances
+// in a time range (expand) or with only the overrides that affect it
+// (limit-recurrence-set)
+
+use eyre::{eyre, Result};
+
+use crate::caldav_filter::{matching_instances, parse_time_range, TimeRange};
+use crate::datetime::DateTime;
+use crate::recurrence::Instance;
+use crate::timezone::TimeZones;
+use crate::xml::Element;
+use crate::{ChildRef, ContentLine, ICalObject, Param};
+
+const RECURRING: [&str; 3] = ["VEVENT", "VTODO", "VJOURNAL"];
+
+/// a CALDAV:calendar-data request, the default returns the calendar as it is
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CalendarData {
+    /// the components and properties to return, None is all of them
+    pub comp: Option<Comp>,
+    /// return the instances in the range instead of recurring components
+    pub expand: Option<TimeRange>,
+    /// return only the overrides that affect the range
+    pub limit_recurrence_set: Option<TimeRange>,
+}
+
+/// a CALDAV:comp, a component and what to return of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comp {
+    pub name: String,
+    /// None is allprop
+    pub props: Option<Vec<Prop>>,
+    /// None is allcomp
+    pub comps: Option<Vec<Comp>>,
+}
+
+/// a CALDAV:prop, with novalue its value is left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prop {
+    pub name: String,
+    pub novalue: bool,
+}
+
+impl CalendarData {
+    /// parses a CALDAV:calendar-data element, or a request with one
+    /// like a calendar-query or a calendar-multiget
+    pub fn parse(xml: &str) -> Result<CalendarData> {
+        let root = Element::parse(xml)?;
+        let element = find(&root, "calendar-data")
+            .ok_or_else(|| eyre!("expected a calendar-data element, found {}", root.name))?;
+        match element.attribute("content-type") {
+            None | Some("text/calendar") => {}
+            Some(other) => return Err(eyre!("unsupported content-type: {}", other)),
+        }
+        let mut data = CalendarData::default();
+        for child in &element.children {
+            match child.name.as_str() {
+                "comp" => data.comp = Some(parse_comp(child)?),
+                "expand" => data.expand = Some(parse_range(child)?),
+                "limit-recurrence-set" => data.limit_recurrence_set = Some(parse_range(child)?),
+                other => return Err(eyre!("unsupported {} in calendar-data", other)),
+            }
+        }
+        if data.expand.is_some() && data.limit_recurrence_set.is_some() {
+            return Err(eyre!("expand and limit-recurrence-set exclude each other"));
+        }
+        Ok(data)
+    }
+
+    /// the calendar as requested
+    pub fn apply(&self, calendar: &ICalObject) -> Result<ICalObject> {
+        let calendar = match (self.expand, self.limit_recurrence_set) {
+            (Some(range), _) => expand_recurrences(calendar, range)?,
+            (None, Some(range)) => limit_recurrence_set(calendar, range)?,
+            (None, None) => calendar.clone(),
+        };
+        Ok(match &self.comp {
+            Some(comp) => prune(&calendar, comp),
+            None => calendar,
+        })
+    }
+}
+
+// the first element with a name, depth first
+fn find<'a>(element: &'a Element, name: &str) -> Option<&'a Element> {
+    if element.is(name) {
+        return Some(element);
+    }
+    element.children.iter().find_map(|child| find(child, name))
+}
+
+fn name(element: &Element) -> Result<String> {
+    element
+        .attribute("name")
+        .map(str::to_ascii_uppercase)
+        .ok_or_else(|| eyre!("{} without a name", element.name))
+}
+
+fn parse_comp(element: &Element) -> Result<Comp> {
+    let mut comp = Comp {
+        name: name(element)?,
+        props: Some(Vec::new()),
+        comps: Some(Vec::new()),
+    };
+    for child in &element.children {
+        match (child.name.as_str(), &mut comp.props, &mut comp.comps) {
+            ("allprop", ..) => comp.props = None,
+            ("allcomp", ..) => comp.comps = None,
+            ("prop", Some(props), _) => props.push(Prop {
+                name: name(child)?,
+                novalue: child.attribute("novalue") == Some("yes"),
+            }),
+            ("comp", _, Some(comps)) => comps.push(parse_comp(child)?),
+            ("prop" | "comp", ..) => {}
+            (other, ..) => return Err(eyre!("unexpected {} in a comp", other)),
+        }
+    }
+    Ok(comp)
+}
+
+// expand and limit-recurrence-set need both ends
+fn parse_range(element: &Element) -> Result<TimeRange> {
+    let range = parse_time_range(element)?;
+    if range.start.is_none() || range.end.is_none() {
+        return Err(eyre!("{} needs a start and an end", element.name));
+    }
+    Ok(range)
+}
+
+/// a copy of object with only the properties and sub objects of comp,
+/// object is taken to be of the type of comp
+pub fn prune(object: &ICalObject, comp: &Comp) -> ICalObject {
+    let mut pruned = ICalObject {
+        object_type: object.object_type.clone(),
+        ..Default::default()
+    };
+    for child in object.children() {
+        match child {
+            ChildRef::Property(line) => match &comp.props {
+                None => pruned.push_property(line.clone()),
+                Some(props) => {
+                    if let Some(prop) = props.iter().find(|prop| line.name_eq(&prop.name)) {
+                        let mut line = line.clone();
+                        if prop.novalue {
+                            line.value.clear();
+                        }
+                        pruned.push_property(line);
+                    }
+                }
+            },
+            ChildRef::SubObject(sub_object) => match &comp.comps {
+                None => pruned.push_sub_object(sub_object.clone()),
+                Some(comps) => {
+                    if let Some(comp) = comps.iter().find(|comp| sub_object.is_type(&comp.name)) {
+                        pruned.push_sub_object(prune(sub_object, comp));
+                    }
+                }
+            },
+        }
+    }
+    pruned
+}
+
+// a property for a UTC time, written like the line it replaces: DATE and
+// floating values stay so, TZID and RANGE are dropped
+fn time_line(name: &str, like: &ContentLine, time: DateTime) -> ContentLine {
+    let value = match (like.value.contains('T'), like.param("TZID").is_some()) {
+        (false, _) => time.to_date_string(),
+        (true, false) if !like.value.trim().ends_with('Z') => time.to_string(),
+        (true, _) => format!("{}Z", time),
+    };
+    let params: Vec<Param> = like
+        .params
+        .iter()
+        .filter(|param| !param.name_eq("TZID") && !param.name_eq("RANGE"))
+        .cloned()
+        .collect();
+    ContentLine::new(name.to_string(), params, value)
+}
+
+// an instance as a component of its own, with its times in UTC
+fn instance_component(instance: &Instance) -> ICalObject {
+    let mut component = instance.component.clone();
+    for i in (0..component.properties.len()).rev() {
+        let name = component.properties[i].name.to_ascii_uppercase();
+        if ["EXDATE", "EXRULE", "RDATE", "RRULE"].contains(&name.as_str()) {
+            component.remove_property(i);
+        }
+    }
+    let line = |name: &str| component.get_property(name).cloned();
+    let (start, due, end) = (line("DTSTART"), line("DUE"), line("DTEND"));
+    if let Some(start) = &start {
+        component.set_property(time_line("DTSTART", start, instance.start));
+        if let Some(recurrence_id) = instance.recurrence_id {
+            component.set_property(time_line("RECURRENCE-ID", start, recurrence_id));
+        }
+    }
+    if let Some(end) = end {
+        component.set_property(time_line("DTEND", &end, instance.end));
+    }
+    // a VTODO without DTSTART starts at its DUE
+    if let Some(due) = due {
+        let time = if start.is_some() {
+            instance.end
+        } else {
+            instance.start
+        };
+        component.set_property(time_line("DUE", &due, time));
+        if let (None, Some(recurrence_id)) = (&start, instance.recurrence_id) {
+            component.set_property(time_line("RECURRENCE-ID", &due, recurrence_id));
+        }
+    }
+    component
+}
+
+/// the calendar with the VEVENT, VTODO and VJOURNAL components replaced by
+/// their instances that match the time range, each with its own RECURRENCE-ID
+/// and with its times in UTC, VTIMEZONE components are left out and the
+/// instances of a component take its place
+pub fn expand_recurrences(calendar: &ICalObject, range: TimeRange) -> Result<ICalObject> {
+    let (start, end) = range.bounds();
+    let mut found = Vec::new();
+    for object_type in RECURRING {
+        found.extend(matching_instances(calendar, object_type, start, end)?);
+    }
+    found.sort_by_key(|instance| (instance.start, instance.end));
+
+    let mut expanded = ICalObject {
+        object_type: calendar.object_type.clone(),
+        ..Default::default()
+    };
+    for child in calendar.children() {
+        match child {
+            ChildRef::Property(line) => expanded.push_property(line.clone()),
+            ChildRef::SubObject(sub_object) if sub_object.is_type("VTIMEZONE") => {}
+            ChildRef::SubObject(sub_object)
+                if RECURRING.iter().any(|name| sub_object.is_type(name)) =>
+            {
+                for instance in &found {
+                    if std::ptr::eq(instance.component, sub_object) {
+                        expanded.push_sub_object(instance_component(instance));
+                    }
+                }
+            }
+            ChildRef::SubObject(sub_object) => expanded.push_sub_object(sub_object.clone()),
+        }
+    }
+    Ok(expanded)
+}
+
+/// the calendar without the overrides that do not affect the time range:
+/// those that neither move an instance into it nor one out of it, an
+/// override with RANGE=THISANDFUTURE also affects everything after it
+pub fn limit_recurrence_set(calendar: &ICalObject, range: TimeRange) -> Result<ICalObject> {
+    let (start, end) = range.bounds();
+    let zones = TimeZones::from_calendar(calendar)?;
+    let mut found = Vec::new();
+    for object_type in RECURRING {
+        found.extend(matching_instances(calendar, object_type, start, end)?);
+    }
+
+    let mut limited = calendar.clone();
+    for i in (0..calendar.sub_objects.len()).rev() {
+        let sub_object = &calendar.sub_objects[i];
+        let Some(line) = sub_object.get_property("RECURRENCE-ID") else {
+            continue;
+        };
+        if !RECURRING.iter().any(|name| sub_object.is_type(name)) {
+            continue;
+        }
+        let recurrence_id = zones.to_utc(line, &line.value)?;
+        let this_and_future = line.param("RANGE").is_some_and(|param| {
+            param
+                .values()
+                .iter()
+                .any(|value| value.eq_ignore_ascii_case("THISANDFUTURE"))
+        });
+        let affects = found
+            .iter()
+            .any(|instance| std::ptr::eq(instance.component, sub_object))
+            || (start <= recurrence_id && recurrence_id < end)
+            || (this_and_future && recurrence_id < end);
+        if !affects {
+            limited.remove_sub_object(i);
+        }
+    }
+    Ok(limited)
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//elikoga-ical-rs//EN\r
+BEGIN:VTIMEZONE\r
+TZID:Europe/Berlin\r
+BEGIN:STANDARD\r
+DTSTART:19700101T000000\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0100\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+UID:review@example.com\r
+DTSTAMP:20240101T000000Z\r
+DTSTART;TZID=Europe/Berlin:20240101T100000\r
+DTEND;TZID=Europe/Berlin:20240101T110000\r
+RRULE:FREQ=WEEKLY;COUNT=4\r
+SUMMARY:Review\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:review@example.com\r
+DTSTAMP:20240101T000000Z\r
+RECURRENCE-ID;TZID=Europe/Berlin:20240108T100000\r
+DTSTART;TZID=Europe/Berlin:20240108T140000\r
+DTEND;TZID=Europe/Berlin:20240108T150000\r
+SUMMARY:Review\\, moved\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:review@example.com\r
+DTSTAMP:20240101T000000Z\r
+RECURRENCE-ID;TZID=Europe/Berlin:20240122T100000\r
+DTSTART;TZID=Europe/Berlin:20240122T120000\r
+DTEND;TZID=Europe/Berlin:20240122T130000\r
+SUMMARY:Review\\, late\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn request(calendar_data: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8" ?>
+<C:calendar-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+  <D:prop>{}</D:prop>
+  <C:filter><C:comp-filter name="VCALENDAR"/></C:filter>
+</C:calendar-query>"#,
+            calendar_data
+        )
+    }
+
+    #[test]
+    fn returns_partial_calendar_data() {
+        let calendar: ICalObject = CALENDAR.parse().unwrap();
+        let apply = |calendar_data: &str| {
+            CalendarData::parse(&request(calendar_data))
+                .unwrap()
+                .apply(&calendar)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(apply("<C:calendar-data/>"), CALENDAR);
+
+        assert_eq!(
+            apply(
+                r#"<C:calendar-data>
+                  <C:comp name="VCALENDAR">
+                    <C:prop name="VERSION"/>
+                    <C:comp name="VEVENT">
+                      <C:prop name="SUMMARY"/>
+                      <C:prop name="DTSTART" novalue="yes"/>
+                    </C:comp>
+                  </C:comp>
+                </C:calendar-data>"#
+            ),
+            "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+DTSTART;TZID=Europe/Berlin:\r
+SUMMARY:Review\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART;TZID=Europe/Berlin:\r
+SUMMARY:Review\\, moved\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART;TZID=Europe/Berlin:\r
+SUMMARY:Review\\, late\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        );
+
+        // the instance of the 15th and the moved one of the 8th, in the order
+        // of their components
+        assert_eq!(
+            apply(
+                r#"<C:calendar-data>
+                  <C:expand start="20240105T000000Z" end="20240116T000000Z"/>
+                </C:calendar-data>"#
+            ),
+            "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//elikoga-ical-rs//EN\r
+BEGIN:VEVENT\r
+UID:review@example.com\r
+DTSTAMP:20240101T000000Z\r
+DTSTART:20240115T090000Z\r
+DTEND:20240115T100000Z\r
+SUMMARY:Review\r
+RECURRENCE-ID:20240115T090000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:review@example.com\r
+DTSTAMP:20240101T000000Z\r
+RECURRENCE-ID:20240108T090000Z\r
+DTSTART:20240108T130000Z\r
+DTEND:20240108T140000Z\r
+SUMMARY:Review\\, moved\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        );
+
+        let limited = CalendarData::parse(&request(
+            r#"<C:calendar-data>
+              <C:limit-recurrence-set start="20240120T000000Z" end="20240130T000000Z"/>
+            </C:calendar-data>"#,
+        ))
+        .unwrap()
+        .apply(&calendar)
+        .unwrap();
+        let summaries: Vec<_> = limited
+            .get_sub_objects("VEVENT")
+            .map(|event| event.get_property("SUMMARY").unwrap().value.as_str())
+            .collect();
+        assert_eq!(summaries, ["Review", "Review\\, late"]);
+
+        assert!(CalendarData::parse(&request(
+            r#"<C:calendar-data><C:expand start="20240105T000000Z"/></C:calendar-data>"#
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,562 @@
+// CalDAV calendar-query filters, see https://www.rfc-editor.org/rfc/rfc4791#section-9.7
+//
+// a comp-filter matches if a component of its name exists (or does not, with
+// is-not-defined) that passes its time-range and all of its nested filters,
+// prop-filters and param-filters work the same for properties and params
+
+use eyre::{eyre, Result};
+
+use crate::datetime::{parse_duration, DateTime};
+use crate::recurrence::{expand, instances, Instance};
+use crate::time_index::{alarm_margin, alarm_triggers, matches_time_range};
+use crate::timezone::TimeZones;
+use crate::value::{unescape_text, ValueType};
+use crate::xml::Element;
+use crate::{ContentLine, ICalObject};
+
+/// a CALDAV:filter, its comp-filter is for the VCALENDAR itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub comp_filter: CompFilter,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompFilter {
+    pub name: String,
+    pub is_not_defined: bool,
+    pub time_range: Option<TimeRange>,
+    pub prop_filters: Vec<PropFilter>,
+    pub comp_filters: Vec<CompFilter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropFilter {
+    pub name: String,
+    pub is_not_defined: bool,
+    pub time_range: Option<TimeRange>,
+    pub text_match: Option<TextMatch>,
+    pub param_filters: Vec<ParamFilter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamFilter {
+    pub name: String,
+    pub is_not_defined: bool,
+    pub text_match: Option<TextMatch>,
+}
+
+/// a substring match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextMatch {
+    pub text: String,
+    pub collation: Collation,
+    /// negate-condition="yes"
+    pub negate: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collation {
+    /// `i;ascii-casemap`, the default
+    AsciiCasemap,
+    /// `i;octet`
+    Octet,
+}
+
+/// UTC times, at least one of them is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: Option<DateTime>,
+    pub end: Option<DateTime>,
+}
+
+impl TimeRange {
+    pub(crate) fn bounds(self) -> (DateTime, DateTime) {
+        (
+            self.start.unwrap_or(DateTime::new(1, 1, 1, 0, 0, 0)),
+            self.end.unwrap_or(DateTime::new(9999, 12, 31, 23, 59, 59)),
+        )
+    }
+}
+
+impl TextMatch {
+    pub fn matches(&self, value: &str) -> bool {
+        let found = match self.collation {
+            Collation::Octet => value.contains(&self.text),
+            Collation::AsciiCasemap => value
+                .to_ascii_lowercase()
+                .contains(&self.text.to_ascii_lowercase()),
+        };
+        found != self.negate
+    }
+}
+
+impl Filter {
+    /// parses a CALDAV:filter element, or a calendar-query with one
+    pub fn parse(xml: &str) -> Result<Filter> {
+        let root = Element::parse(xml)?;
+        let filter = if root.is("filter") {
+            &root
+        } else {
+            root.child("filter")
+                .ok_or_else(|| eyre!("expected a filter element, found {}", root.name))?
+        };
+        let comp_filter = match filter.children.as_slice() {
+            [comp_filter] if comp_filter.is("comp-filter") => parse_comp_filter(comp_filter)?,
+            _ => return Err(eyre!("a filter must contain a single comp-filter")),
+        };
+        if !comp_filter.name.eq_ignore_ascii_case("VCALENDAR") {
+            return Err(eyre!(
+                "the comp-filter of a filter must be for VCALENDAR, found {}",
+                comp_filter.name
+            ));
+        }
+        Ok(Filter { comp_filter })
+    }
+
+    /// whether a calendar object resource matches
+    pub fn matches(&self, calendar: &ICalObject) -> Result<bool> {
+        let context = Context {
+            calendar,
+            zones: TimeZones::from_calendar(calendar)?,
+        };
+        context.comp_filter(&self.comp_filter, std::slice::from_ref(calendar), None)
+    }
+}
+
+fn name(element: &Element) -> Result<String> {
+    element
+        .attribute("name")
+        .map(str::to_ascii_uppercase)
+        .ok_or_else(|| eyre!("{} without a name", element.name))
+}
+
+fn parse_comp_filter(element: &Element) -> Result<CompFilter> {
+    let mut filter = CompFilter {
+        name: name(element)?,
+        is_not_defined: false,
+        time_range: None,
+        prop_filters: Vec::new(),
+        comp_filters: Vec::new(),
+    };
+    for child in &element.children {
+        match child.name.as_str() {
+            "is-not-defined" => filter.is_not_defined = true,
+            "time-range" => filter.time_range = Some(parse_time_range(child)?),
+            "prop-filter" => filter.prop_filters.push(parse_prop_filter(child)?),
+            "comp-filter" => filter.comp_filters.push(parse_comp_filter(child)?),
+            other => return Err(eyre!("unexpected {} in a comp-filter", other)),
+        }
+    }
+    let supports_time_range =
+        ["VEVENT", "VTODO", "VJOURNAL", "VFREEBUSY", "VALARM"].contains(&filter.name.as_str());
+    if filter.time_range.is_some() && !supports_time_range {
+        return Err(eyre!("a time-range is not supported for {}", filter.name));
+    }
+    Ok(filter)
+}
+
+fn parse_prop_filter(element: &Element) -> Result<PropFilter> {
+    let mut filter = PropFilter {
+        name: name(element)?,
+        is_not_defined: false,
+        time_range: None,
+        text_match: None,
+        param_filters: Vec::new(),
+    };
+    for child in &element.children {
+        match child.name.as_str() {
+            "is-not-defined" => filter.is_not_defined = true,
+            "time-range" => filter.time_range = Some(parse_time_range(child)?),
+            "text-match" => filter.text_match = Some(parse_text_match(child)?),
+            "param-filter" => filter.param_filters.push(parse_param_filter(child)?),
+            other => return Err(eyre!("unexpected {} in a prop-filter", other)),
+        }
+    }
+    Ok(filter)
+}
+
+fn parse_param_filter(element: &Element) -> Result<ParamFilter> {
+    let mut filter = ParamFilter {
+        name: name(element)?,
+        is_not_defined: false,
+        text_match: None,
+    };
+    for child in &element.children {
+        match child.name.as_str() {
+            "is-not-defined" => filter.is_not_defined = true,
+            "text-match" => filter.text_match = Some(parse_text_match(child)?),
+            other => return Err(eyre!("unexpected {} in a param-filter", other)),
+        }
+    }
+    Ok(filter)
+}
+
+fn parse_text_match(element: &Element) -> Result<TextMatch> {
+    let collation = match element.attribute("collation") {
+        None | Some("i;ascii-casemap") => Collation::AsciiCasemap,
+        Some("i;octet") => Collation::Octet,
+        Some(other) => return Err(eyre!("unsupported collation: {}", other)),
+    };
+    let negate = match element.attribute("negate-condition") {
+        None | Some("no") => false,
+        Some("yes") => true,
+        Some(other) => return Err(eyre!("invalid negate-condition: {}", other)),
+    };
+    Ok(TextMatch {
+        text: element.text.clone(),
+        collation,
+        negate,
+    })
+}
+
+pub(crate) fn parse_time_range(element: &Element) -> Result<TimeRange> {
+    let time = |name: &str| -> Result<Option<DateTime>> {
+        match element.attribute(name) {
+            Some(value) if value.ends_with('Z') && value.contains('T') => {
+                Ok(Some(DateTime::parse(value)?))
+            }
+            Some(value) => Err(eyre!("a time-range needs UTC times, found {}", value)),
+            None => Ok(None),
+        }
+    };
+    let time_range = TimeRange {
+        start: time("start")?,
+        end: time("end")?,
+    };
+    if time_range.start.is_none() && time_range.end.is_none() {
+        return Err(eyre!("a time-range needs a start or an end"));
+    }
+    Ok(time_range)
+}
+
+// the calendar a filter is evaluated against
+struct Context<'a> {
+    calendar: &'a ICalObject,
+    zones: TimeZones,
+}
+
+impl Context<'_> {
+    // whether a comp-filter matches the components, the children of parent
+    fn comp_filter(
+        &self,
+        filter: &CompFilter,
+        components: &[ICalObject],
+        parent: Option<&ICalObject>,
+    ) -> Result<bool> {
+        let mut named = components
+            .iter()
+            .filter(|component| component.is_type(&filter.name));
+        if filter.is_not_defined {
+            return Ok(named.next().is_none());
+        }
+        for component in named {
+            if self.component(filter, component, parent)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn component(
+        &self,
+        filter: &CompFilter,
+        component: &ICalObject,
+        parent: Option<&ICalObject>,
+    ) -> Result<bool> {
+        if let Some(time_range) = filter.time_range {
+            if !self.component_in_range(component, parent, time_range)? {
+                return Ok(false);
+            }
+        }
+        for prop_filter in &filter.prop_filters {
+            if !self.prop_filter(prop_filter, component)? {
+                return Ok(false);
+            }
+        }
+        for comp_filter in &filter.comp_filters {
+            if !self.comp_filter(comp_filter, &component.sub_objects, Some(component))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // see https://www.rfc-editor.org/rfc/rfc4791#section-9.9
+    fn component_in_range(
+        &self,
+        component: &ICalObject,
+        parent: Option<&ICalObject>,
+        time_range: TimeRange,
+    ) -> Result<bool> {
+        let (start, end) = time_range.bounds();
+        let object_type = component.object_type.to_ascii_uppercase();
+        match object_type.as_str() {
+            "VALARM" => {
+                let Some(parent) = parent else {
+                    return Ok(false);
+                };
+                // the occurrences of the parent whose alarms can trigger in the range
+                let margin = alarm_margin(&[parent]) + 86400;
+                let siblings = self
+                    .calendar
+                    .sub_objects
+                    .iter()
+                    .filter(|sibling| sibling.is_type(&parent.object_type));
+                let window = (start.add_seconds(-margin), end.add_seconds(margin));
+                for instance in expand(siblings, &self.zones, window.0, window.1)? {
+                    if !std::ptr::eq(instance.component, parent) {
+                        continue;
+                    }
+                    let triggers = alarm_triggers(&instance, component)?;
+                    if triggers
+                        .iter()
+                        .any(|trigger| start <= *trigger && *trigger < end)
+                    {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            "VFREEBUSY" => self.free_busy_in_range(component, start, end),
+            _ => {
+                let found = matching_instances(self.calendar, &object_type, start, end)?;
+                Ok(found
+                    .iter()
+                    .any(|instance| std::ptr::eq(instance.component, component)))
+            }
+        }
+    }
+
+    fn free_busy_in_range(
+        &self,
+        component: &ICalObject,
+        start: DateTime,
+        end: DateTime,
+    ) -> Result<bool> {
+        let time = |name: &str| -> Result<Option<DateTime>> {
+            match component.get_property(name) {
+                Some(line) => Ok(Some(self.zones.to_utc(line, &line.value)?)),
+                None => Ok(None),
+            }
+        };
+        if let (Some(from), Some(to)) = (time("DTSTART")?, time("DTEND")?) {
+            return Ok(start <= to && end > from);
+        }
+        for line in component.get_properties("FREEBUSY") {
+            for period in line.value.split(',') {
+                let (from, to) = period
+                    .split_once('/')
+                    .ok_or_else(|| eyre!("invalid FREEBUSY period: {}", period))?;
+                let from = DateTime::parse(from)?;
+                let to = if to.starts_with('P') || to.starts_with("+P") {
+                    from.add_seconds(parse_duration(to)?)
+                } else {
+                    DateTime::parse(to)?
+                };
+                if start < to && end > from {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn prop_filter(&self, filter: &PropFilter, component: &ICalObject) -> Result<bool> {
+        let mut named = component
+            .properties
+            .iter()
+            .filter(|line| line.name_eq(&filter.name));
+        if filter.is_not_defined {
+            return Ok(named.next().is_none());
+        }
+        for line in named {
+            if self.property(filter, line)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn property(&self, filter: &PropFilter, line: &ContentLine) -> Result<bool> {
+        if let Some(time_range) = filter.time_range {
+            let (start, end) = time_range.bounds();
+            let mut in_range = false;
+            for value in line.value.split(',') {
+                let value = value.split('/').next().unwrap_or(value);
+                let time = self.zones.to_utc(line, value)?;
+                in_range |= start <= time && time < end;
+            }
+            if !in_range {
+                return Ok(false);
+            }
+        }
+        if let Some(text_match) = &filter.text_match {
+            let value = match ValueType::of(line) {
+                ValueType::Text => unescape_text(&line.value),
+                _ => line.value.clone(),
+            };
+            if !text_match.matches(&value) {
+                return Ok(false);
+            }
+        }
+        Ok(filter
+            .param_filters
+            .iter()
+            .all(|param_filter| param_matches(param_filter, line)))
+    }
+}
+
+/// the instances of the components of a type, like VEVENT, that match a
+/// time-range from start to end, a VTODO without DTSTART and DUE is one
+/// instance over the whole range
+pub(crate) fn matching_instances<'a>(
+    calendar: &'a ICalObject,
+    object_type: &str,
+    start: DateTime,
+    end: DateTime,
+) -> Result<Vec<Instance<'a>>> {
+    // the VTODO semantics include the ends of the range
+    let window = (start.add_seconds(-1), end.add_seconds(1));
+    let mut found = instances(calendar, object_type, window.0, window.1)?;
+    found.retain(|instance| matches_time_range(instance, start, end));
+    if !object_type.eq_ignore_ascii_case("VTODO") {
+        return Ok(found);
+    }
+    let undated = |component: &&ICalObject| {
+        component.get_property("DTSTART").is_none() && component.get_property("DUE").is_none()
+    };
+    for component in calendar.get_sub_objects("VTODO").filter(undated) {
+        let instance = Instance {
+            component,
+            recurrence_id: None,
+            start,
+            end,
+        };
+        if matches_time_range(&instance, start, end) {
+            found.push(instance);
+        }
+    }
+    Ok(found)
+}
+
+fn param_matches(filter: &ParamFilter, line: &ContentLine) -> bool {
+    let param = line.param(&filter.name);
+    match (param, &filter.text_match) {
+        (None, _) => filter.is_not_defined,
+        (Some(_), _) if filter.is_not_defined => false,
+        (Some(_), None) => true,
+        (Some(param), Some(text_match)) => {
+            param.values().iter().any(|value| text_match.matches(value))
+        }
+    }
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//elikoga-ical-rs//EN\r
+BEGIN:VEVENT\r
+UID:standup@example.com\r
+DTSTAMP:20240101T000000Z\r
+DTSTART:20240108T090000Z\r
+DURATION:PT15M\r
+RRULE:FREQ=WEEKLY;BYDAY=MO\r
+SUMMARY:Stand-up\\, daily\r
+ATTENDEE;PARTSTAT=NEEDS-ACTION:mailto:ana@example.com\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT10M\r
+END:VALARM\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn query(comp_filters: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8" ?>
+<C:calendar-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+  <D:prop><C:calendar-data/></D:prop>
+  <C:filter>
+    <C:comp-filter name="VCALENDAR">{}</C:comp-filter>
+  </C:filter>
+</C:calendar-query>"#,
+            comp_filters
+        )
+    }
+
+    #[test]
+    fn evaluates_filters() {
+        let calendar: ICalObject = CALENDAR.parse().unwrap();
+        let matches = |comp_filters: &str| {
+            Filter::parse(&query(comp_filters))
+                .unwrap()
+                .matches(&calendar)
+                .unwrap()
+        };
+        assert!(matches(""));
+        assert!(matches(r#"<C:comp-filter name="VEVENT"/>"#));
+        assert!(!matches(r#"<C:comp-filter name="VTODO"/>"#));
+        assert!(matches(
+            r#"<C:comp-filter name="VTODO"><C:is-not-defined/></C:comp-filter>"#
+        ));
+        // a later occurrence of the weekly event
+        assert!(matches(
+            r#"<C:comp-filter name="VEVENT">
+              <C:time-range start="20240212T090500Z" end="20240212T100000Z"/>
+            </C:comp-filter>"#
+        ));
+        assert!(!matches(
+            r#"<C:comp-filter name="VEVENT">
+              <C:time-range start="20240213T000000Z" end="20240219T000000Z"/>
+            </C:comp-filter>"#
+        ));
+        // the alarm of the occurrence on January 15th
+        assert!(matches(
+            r#"<C:comp-filter name="VEVENT"><C:comp-filter name="VALARM">
+              <C:time-range start="20240115T084500Z" end="20240115T085500Z"/>
+            </C:comp-filter></C:comp-filter>"#
+        ));
+        // text is unescaped and compared with the collation
+        assert!(matches(
+            r#"<C:comp-filter name="VEVENT"><C:prop-filter name="SUMMARY">
+              <C:text-match>up, DAILY</C:text-match>
+            </C:prop-filter></C:comp-filter>"#
+        ));
+        assert!(!matches(
+            r#"<C:comp-filter name="VEVENT"><C:prop-filter name="SUMMARY">
+              <C:text-match collation="i;octet">up, DAILY</C:text-match>
+            </C:prop-filter></C:comp-filter>"#
+        ));
+        assert!(matches(
+            r#"<C:comp-filter name="VEVENT"><C:prop-filter name="ATTENDEE">
+              <C:text-match>ana@example.com</C:text-match>
+              <C:param-filter name="PARTSTAT">
+                <C:text-match negate-condition="yes">ACCEPTED</C:text-match>
+              </C:param-filter>
+              <C:param-filter name="ROLE"><C:is-not-defined/></C:param-filter>
+            </C:prop-filter></C:comp-filter>"#
+        ));
+        assert!(matches(
+            r#"<C:comp-filter name="VEVENT"><C:prop-filter name="DTSTART">
+              <C:time-range start="20240108T000000Z" end="20240109T000000Z"/>
+            </C:prop-filter></C:comp-filter>"#
+        ));
+
+        let invalid = [
+            r#"<C:comp-filter name="VEVENT"><C:prop-filter name="SUMMARY">
+              <C:text-match collation="i;unknown">x</C:text-match>
+            </C:prop-filter></C:comp-filter>"#,
+            r#"<C:comp-filter name="VEVENT"><C:time-range start="20240101"/></C:comp-filter>"#,
+            r#"<C:comp-filter name="VTIMEZONE">
+              <C:time-range start="20240101T000000Z"/>
+            </C:comp-filter>"#,
+        ];
+        for comp_filters in invalid {
+            assert!(Filter::parse(&query(comp_filters)).is_err());
+        }
+    }
+}
@@ -0,0 +1,887 @@
+// a small CalDAV server over a directory, see https://www.rfc-editor.org/rfc/rfc4791
+//
+// every subdirectory of the root is a calendar collection and every .ics file
+// in it a calendar object resource, requests are handled without a network
+// stack so that the binary only has to move them to and from HTTP
+//
+// property names are matched by their local name like all XML in this crate,
+// properties we do not know are reported as not found in the DAV: namespace
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use eyre::Result;
+
+use crate::caldav_data::CalendarData;
+use crate::caldav_filter::Filter;
+use crate::datetime::DateTime;
+use crate::recurrence::instances;
+use crate::xml::{escape, Element};
+use crate::ICalObject;
+
+const DAV: &str = "DAV:";
+const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+const CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+
+/// the components calendars support, see supported-calendar-component-set
+const COMPONENTS: [&str; 3] = ["VEVENT", "VTODO", "VJOURNAL"];
+
+/// the properties reported for allprop, see [Store::property]
+const ALL_PROPERTIES: [&str; 9] = [
+    "resourcetype",
+    "displayname",
+    "getetag",
+    "getcontenttype",
+    "getcontentlength",
+    "current-user-principal",
+    "calendar-home-set",
+    "supported-calendar-component-set",
+    "getctag",
+];
+
+/// an HTTP request, the path may contain a query
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    /// header names are case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+    }
+}
+
+impl Response {
+    fn new(status: u16) -> Self {
+        Response {
+            status,
+            ..Default::default()
+        }
+    }
+
+    fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn with_body(mut self, content_type: &str, body: String) -> Self {
+        self.body = body;
+        self.with_header("Content-Type", content_type)
+    }
+}
+
+// what a path points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Root,
+    Calendar(String),
+    Resource(String, String),
+}
+
+impl Target {
+    fn parse(path: &str) -> Option<Target> {
+        let path = path.split('?').next().unwrap_or(path);
+        // absolute URLs, as in the hrefs of a calendar-multiget
+        let path = match path.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+            None => path,
+        };
+        let segments: Option<Vec<String>> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(decode)
+            .collect();
+        let segments = segments?;
+        let valid = |segment: &String| !segment.starts_with('.') && !segment.contains(['/', '\\']);
+        if !segments.iter().all(valid) {
+            return None;
+        }
+        match segments.as_slice() {
+            [] => Some(Target::Root),
+            [calendar] => Some(Target::Calendar(calendar.clone())),
+            [calendar, name] => Some(Target::Resource(calendar.clone(), name.clone())),
+            _ => None,
+        }
+    }
+
+    fn href(&self) -> String {
+        match self {
+            Target::Root => "/".to_string(),
+            Target::Calendar(calendar) => format!("/{}/", encode(calendar)),
+            Target::Resource(calendar, name) => format!("/{}/{}", encode(calendar), encode(name)),
+        }
+    }
+}
+
+// percent-decodes a path segment
+fn decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// a strong ETag from the content, FNV-1a so that it survives restarts
+fn etag(content: &str) -> String {
+    let hash = content.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("\"{:016x}\"", hash)
+}
+
+// whether an If-Match or If-None-Match header lists the ETag, * lists any,
+// without weak a listed weak ETag never matches, see RFC 7232 section 2.3.2
+fn lists(header: &str, etag: Option<&str>, weak: bool) -> bool {
+    header.split(',').map(str::trim).any(|listed| {
+        let listed = match listed.strip_prefix("W/") {
+            Some(opaque) if weak => opaque,
+            _ => listed,
+        };
+        etag.is_some_and(|etag| listed == "*" || listed == etag)
+    })
+}
+
+fn namespace(property: &str) -> &'static str {
+    match property {
+        "calendar-home-set" | "supported-calendar-component-set" | "calendar-data" => CALDAV,
+        "getctag" => CALENDARSERVER,
+        _ => DAV,
+    }
+}
+
+// an element of a property, in its namespace
+fn element(property: &str, content: &str) -> String {
+    let prefix = match namespace(property) {
+        CALDAV => "C",
+        CALENDARSERVER => "CS",
+        _ => "D",
+    };
+    if content.is_empty() {
+        format!("<{}:{}/>", prefix, property)
+    } else {
+        format!("<{0}:{1}>{2}</{0}:{1}>", prefix, property, content)
+    }
+}
+
+// a D:response with the found properties and the names of the others
+fn response(href: &str, found: &[String], missing: &[String]) -> String {
+    let mut xml = format!("<D:response><D:href>{}</D:href>", escape(href));
+    if !found.is_empty() {
+        xml.push_str(&format!(
+            "<D:propstat><D:prop>{}</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat>",
+            found.concat()
+        ));
+    }
+    if !missing.is_empty() {
+        let names: Vec<String> = missing.iter().map(|name| element(name, "")).collect();
+        xml.push_str(&format!(
+            "<D:propstat><D:prop>{}</D:prop><D:status>HTTP/1.1 404 Not Found</D:status></D:propstat>",
+            names.concat()
+        ));
+    }
+    xml.push_str("</D:response>");
+    xml
+}
+
+// a D:response with only a status and why, for an href without properties
+fn failure(href: &str, status: &str, description: Option<&str>) -> String {
+    let description = description
+        .map(|description| {
+            format!(
+                "<D:responsedescription>{}</D:responsedescription>",
+                escape(description)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "<D:response><D:href>{}</D:href><D:status>HTTP/1.1 {}</D:status>{}</D:response>",
+        escape(href),
+        status,
+        description
+    )
+}
+
+fn multistatus(responses: &[String]) -> Response {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <D:multistatus xmlns:D=\"{}\" xmlns:C=\"{}\" xmlns:CS=\"{}\">{}</D:multistatus>\n",
+        DAV,
+        CALDAV,
+        CALENDARSERVER,
+        responses.concat()
+    );
+    Response::new(207).with_body("application/xml; charset=utf-8", body)
+}
+
+fn error(status: u16, message: &str) -> Response {
+    Response::new(status).with_body("text/plain; charset=utf-8", format!("{}\n", message))
+}
+
+// a 403 for a failed CalDAV precondition, see RFC 4791 section 1.3
+fn forbidden(condition: &str, content: &str) -> Response {
+    let condition = if content.is_empty() {
+        format!("<C:{}/>", condition)
+    } else {
+        format!("<C:{0}>{1}</C:{0}>", condition, content)
+    };
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <D:error xmlns:D=\"{}\" xmlns:C=\"{}\">{}</D:error>\n",
+        DAV, CALDAV, condition
+    );
+    Response::new(403).with_body("application/xml; charset=utf-8", body)
+}
+
+// the UID of a calendar object resource, None if it breaks the restrictions
+// of RFC 4791 section 4.1: no METHOD, one type of component and one UID
+fn resource_uid(calendar: &ICalObject) -> Option<&str> {
+    if calendar.get_property("METHOD").is_some() {
+        return None;
+    }
+    let mut components = calendar
+        .sub_objects
+        .iter()
+        .filter(|component| !component.is_type("VTIMEZONE"));
+    let first = components.next()?;
+    let uid = first.get_property("UID")?.value.as_str();
+    components
+        .all(|component| {
+            component.is_type(&first.object_type)
+                && component
+                    .get_property("UID")
+                    .is_some_and(|line| line.value == uid)
+        })
+        .then_some(uid)
+}
+
+// the names of the properties in the prop of a PROPFIND or REPORT,
+// None for allprop, propname or an empty PROPFIND
+fn requested(root: Option<&Element>) -> Option<Vec<String>> {
+    let prop = root?.child("prop")?;
+    Some(
+        prop.children
+            .iter()
+            .map(|child| child.name.to_ascii_lowercase())
+            .collect(),
+    )
+}
+
+/// calendar collections as the subdirectories of a root directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Store {
+    root: PathBuf,
+}
+
+impl Store {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Store { root: root.into() }
+    }
+
+    fn path(&self, target: &Target) -> PathBuf {
+        match target {
+            Target::Root => self.root.clone(),
+            Target::Calendar(calendar) => self.root.join(calendar),
+            Target::Resource(calendar, name) => self.root.join(calendar).join(name),
+        }
+    }
+
+    fn exists(&self, target: &Target) -> bool {
+        let path = self.path(target);
+        match target {
+            Target::Resource(..) => path.is_file(),
+            _ => path.is_dir(),
+        }
+    }
+
+    fn read(&self, target: &Target) -> Result<Option<String>> {
+        if !matches!(target, Target::Resource(..)) || !self.exists(target) {
+            return Ok(None);
+        }
+        Ok(Some(std::fs::read_to_string(self.path(target))?))
+    }
+
+    // the directories or .ics files in a directory, sorted, hidden ones are skipped
+    fn list(&self, target: &Target) -> Result<Vec<Target>> {
+        let mut children = Vec::new();
+        if matches!(target, Target::Resource(..)) || !self.exists(target) {
+            return Ok(children);
+        }
+        for entry in std::fs::read_dir(self.path(target))? {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            let is_dir = entry.file_type()?.is_dir();
+            match target {
+                Target::Root if is_dir => children.push(Target::Calendar(name)),
+                Target::Calendar(calendar) if !is_dir && name.ends_with(".ics") => {
+                    children.push(Target::Resource(calendar.clone(), name))
+                }
+                _ => {}
+            }
+        }
+        children.sort_by_key(Target::href);
+        Ok(children)
+    }
+
+    /// handles a request, failures of the store are 500 responses
+    pub fn handle(&self, request: &Request) -> Response {
+        match self.respond(request) {
+            Ok(response) => response,
+            Err(report) => error(500, &report.to_string()),
+        }
+    }
+
+    fn respond(&self, request: &Request) -> Result<Response> {
+        let Some(target) = Target::parse(&request.path) else {
+            return Ok(error(404, "not found"));
+        };
+        match request.method.to_ascii_uppercase().as_str() {
+            "OPTIONS" => Ok(Response::new(200)
+                .with_header("DAV", "1, calendar-access")
+                .with_header(
+                    "Allow",
+                    "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, REPORT, MKCALENDAR",
+                )),
+            "GET" | "HEAD" => self.get(&target),
+            "PUT" => self.put(&target, request),
+            "DELETE" => self.delete(&target, request),
+            "MKCALENDAR" => self.make_calendar(&target),
+            "PROPFIND" => self.propfind(&target, request),
+            "REPORT" => self.report(&target, request),
+            _ => Ok(error(405, "method not allowed")),
+        }
+    }
+
+    fn get(&self, target: &Target) -> Result<Response> {
+        if !matches!(target, Target::Resource(..)) && self.exists(target) {
+            return Ok(error(405, "collections have no content"));
+        }
+        Ok(match self.read(target)? {
+            Some(content) => Response::new(200)
+                .with_header("ETag", &etag(&content))
+                .with_body("text/calendar; charset=utf-8", content),
+            None => error(404, "not found"),
+        })
+    }
+
+    fn put(&self, target: &Target, request: &Request) -> Result<Response> {
+        let Target::Resource(calendar, name) = target else {
+            return Ok(error(405, "only calendar object resources can be written"));
+        };
+        if !name.ends_with(".ics") {
+            return Ok(error(403, "resource names end with .ics"));
+        }
+        let collection = Target::Calendar(calendar.clone());
+        if !self.exists(&collection) {
+            return Ok(error(409, "the calendar collection does not exist"));
+        }
+        let current = self.read(target)?.map(|content| etag(&content));
+        if let Some(response) = precondition(request, current.as_deref()) {
+            return Ok(response);
+        }
+        let calendar = match ICalObject::from_str(&request.body) {
+            Ok(calendar) if calendar.is_type("VCALENDAR") => calendar,
+            Ok(object) => {
+                let message = format!("expected a VCALENDAR, found {}", object.object_type);
+                return Ok(error(400, &message));
+            }
+            Err(report) => return Ok(error(400, &format!("invalid calendar: {}", report))),
+        };
+        // an empty window still parses every date, rule and time zone
+        let never = DateTime::new(1, 1, 1, 0, 0, 0);
+        for object_type in COMPONENTS {
+            if let Err(report) = instances(&calendar, object_type, never, never) {
+                return Ok(error(400, &format!("invalid calendar: {}", report)));
+            }
+        }
+        let supported = |component: &ICalObject| {
+            component.is_type("VTIMEZONE") || COMPONENTS.iter().any(|name| component.is_type(name))
+        };
+        if !calendar.sub_objects.iter().all(supported) {
+            return Ok(forbidden("supported-calendar-component", ""));
+        }
+        let Some(uid) = resource_uid(&calendar) else {
+            return Ok(forbidden("valid-calendar-object-resource", ""));
+        };
+        for resource in self.list(&collection)? {
+            if &resource == target {
+                continue;
+            }
+            let content = self.read(&resource)?.unwrap_or_default();
+            // resources broken outside the server have no UID to conflict with
+            let Ok(other) = ICalObject::from_str(&content) else {
+                continue;
+            };
+            let conflicts = other.sub_objects.iter().any(|component| {
+                component
+                    .get_property("UID")
+                    .is_some_and(|line| line.value == uid)
+            });
+            if conflicts {
+                let href = format!("<D:href>{}</D:href>", escape(&resource.href()));
+                return Ok(forbidden("no-uid-conflict", &href));
+            }
+        }
+        // stored as sent, the ETag is that of the body, see RFC 4791 section 5.3.4
+        std::fs::write(self.path(target), &request.body)?;
+        let status = if current.is_some() { 204 } else { 201 };
+        Ok(Response::new(status).with_header("ETag", &etag(&request.body)))
+    }
+
+    fn delete(&self, target: &Target, request: &Request) -> Result<Response> {
+        if !matches!(target, Target::Resource(..)) {
+            return Ok(error(405, "collections cannot be deleted"));
+        }
+        let Some(content) = self.read(target)? else {
+            return Ok(error(404, "not found"));
+        };
+        if let Some(response) = precondition(request, Some(&etag(&content))) {
+            return Ok(response);
+        }
+        std::fs::remove_file(self.path(target))?;
+        Ok(Response::new(204))
+    }
+
+    fn make_calendar(&self, target: &Target) -> Result<Response> {
+        if !matches!(target, Target::Calendar(..)) {
+            return Ok(error(403, "calendars are created below the root"));
+        }
+        if self.exists(target) {
+            return Ok(error(405, "the calendar exists"));
+        }
+        std::fs::create_dir(self.path(target))?;
+        Ok(Response::new(201))
+    }
+
+    /// a property of a target as an element, None if it has none of the name
+    fn property(&self, target: &Target, name: &str) -> Result<Option<String>> {
+        let content = self.read(target)?;
+        let value = match (name, target) {
+            ("resourcetype", Target::Root) => "<D:collection/>".to_string(),
+            ("resourcetype", Target::Calendar(_)) => "<D:collection/><C:calendar/>".to_string(),
+            ("resourcetype", Target::Resource(..)) => String::new(),
+            ("displayname", Target::Calendar(name) | Target::Resource(_, name)) => {
+                escape(name).into_owned()
+            }
+            ("current-user-principal" | "calendar-home-set", _) => "<D:href>/</D:href>".to_string(),
+            ("supported-calendar-component-set", Target::Calendar(_)) => {
+                r#"<C:comp name="VEVENT"/><C:comp name="VTODO"/><C:comp name="VJOURNAL"/>"#
+                    .to_string()
+            }
+            // changes whenever a resource of the calendar does
+            ("getctag", Target::Calendar(_)) => {
+                let mut etags = String::new();
+                for resource in self.list(target)? {
+                    etags.push_str(&resource.href());
+                    etags.push_str(&etag(&self.read(&resource)?.unwrap_or_default()));
+                }
+                escape(&etag(&etags)).into_owned()
+            }
+            ("getetag", Target::Resource(..)) => match &content {
+                Some(content) => escape(&etag(content)).into_owned(),
+                None => return Ok(None),
+            },
+            ("getcontenttype", Target::Resource(..)) => "text/calendar; charset=utf-8".to_string(),
+            ("getcontentlength", Target::Resource(..)) => match &content {
+                Some(content) => content.len().to_string(),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(element(name, &value)))
+    }
+
+    // the requested properties of a target that it has and the names of the others
+    fn properties(
+        &self,
+        target: &Target,
+        requested: &Option<Vec<String>>,
+    ) -> Result<(Vec<String>, Vec<String>)> {
+        let names: Vec<String> = match requested {
+            Some(names) => names.clone(),
+            None => ALL_PROPERTIES.iter().map(|name| name.to_string()).collect(),
+        };
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+        for name in names {
+            match self.property(target, &name)? {
+                Some(property) => found.push(property),
+                // allprop only lists what there is
+                None if requested.is_some() => missing.push(name),
+                None => {}
+            }
+        }
+        Ok((found, missing))
+    }
+
+    fn describe(&self, target: &Target, requested: &Option<Vec<String>>) -> Result<String> {
+        let (found, missing) = self.properties(target, requested)?;
+        Ok(response(&target.href(), &found, &missing))
+    }
+
+    fn propfind(&self, target: &Target, request: &Request) -> Result<Response> {
+        if !self.exists(target) {
+            return Ok(error(404, "not found"));
+        }
+        let root = match request.body.trim() {
+            "" => None,
+            body => match Element::parse(body) {
+                Ok(root) => Some(root),
+                Err(report) => return Ok(error(400, &format!("invalid PROPFIND: {}", report))),
+            },
+        };
+        let requested = requested(root.as_ref());
+        let mut responses = vec![self.describe(target, &requested)?];
+        // Depth: infinity is answered like 1
+        if request.header("Depth") != Some("0") {
+            for child in self.list(target)? {
+                responses.push(self.describe(&child, &requested)?);
+            }
+        }
+        Ok(multistatus(&responses))
+    }
+
+    fn report(&self, target: &Target, request: &Request) -> Result<Response> {
+        if !self.exists(target) {
+            return Ok(error(404, "not found"));
+        }
+        let root = match Element::parse(&request.body) {
+            Ok(root) => root,
+            Err(report) => return Ok(error(400, &format!("invalid REPORT: {}", report))),
+        };
+        // the hrefs to report on, with their targets if they are resources,
+        // resources that cannot be read are reported on their own
+        let mut hrefs: Vec<(String, Option<Target>)> = Vec::new();
+        let mut responses = Vec::new();
+        if root.is("calendar-query") {
+            let filter = match Filter::parse(&request.body) {
+                Ok(filter) => filter,
+                Err(report) => return Ok(error(403, &format!("unsupported filter: {}", report))),
+            };
+            let candidates = match target {
+                Target::Resource(..) => vec![target.clone()],
+                _ => self.list(target)?,
+            };
+            for resource in candidates {
+                let Some(content) = self.read(&resource)? else {
+                    continue;
+                };
+                let matches =
+                    ICalObject::from_str(&content).and_then(|calendar| filter.matches(&calendar));
+                match matches {
+                    Ok(true) => hrefs.push((resource.href(), Some(resource))),
+                    Ok(false) => (),
+                    Err(report) => responses.push(failure(
+                        &resource.href(),
+                        "500 Internal Server Error",
+                        Some(&report.to_string()),
+                    )),
+                }
+            }
+        } else if root.is("calendar-multiget") {
+            for href in root.children.iter().filter(|child| child.is("href")) {
+                let href = href.text.trim().to_string();
+                let target =
+                    Target::parse(&href).filter(|target| matches!(target, Target::Resource(..)));
+                hrefs.push((href, target));
+            }
+        } else {
+            return Ok(error(403, &format!("unsupported report: {}", root.name)));
+        }
+
+        let requested = requested(Some(&root));
+        let calendar_data = match &requested {
+            Some(names) if names.iter().any(|name| name == "calendar-data") => {
+                match CalendarData::parse(&request.body) {
+                    Ok(calendar_data) => Some(calendar_data),
+                    Err(report) => {
+                        return Ok(error(
+                            403,
+                            &format!("unsupported calendar-data: {}", report),
+                        ))
+                    }
+                }
+            }
+            _ => None,
+        };
+        let others = requested.map(|names| {
+            names
+                .into_iter()
+                .filter(|name| name != "calendar-data")
+                .collect()
+        });
+        for (href, target) in hrefs {
+            let content = match &target {
+                Some(target) => self.read(target)?,
+                None => None,
+            };
+            let (Some(target), Some(content)) = (target, content) else {
+                responses.push(failure(&href, "404 Not Found", None));
+                continue;
+            };
+            let (mut found, missing) = self.properties(&target, &others)?;
+            if let Some(calendar_data) = &calendar_data {
+                let calendar = ICalObject::from_str(&content)
+                    .and_then(|calendar| calendar_data.apply(&calendar));
+                match calendar {
+                    Ok(calendar) => {
+                        found.push(element("calendar-data", &escape(&calendar.to_string())))
+                    }
+                    Err(report) => {
+                        let href = target.href();
+                        let status = "500 Internal Server Error";
+                        responses.push(failure(&href, status, Some(&report.to_string())));
+                        continue;
+                    }
+                }
+            }
+            responses.push(response(&target.href(), &found, &missing));
+        }
+        Ok(multistatus(&responses))
+    }
+}
+
+// checks If-Match and If-None-Match against the current ETag
+fn precondition(request: &Request, current: Option<&str>) -> Option<Response> {
+    let failed = request
+        .header("If-Match")
+        .is_some_and(|header| !lists(header, current, false))
+        || request
+            .header("If-None-Match")
+            .is_some_and(|header| lists(header, current, true));
+    failed.then(|| error(412, "precondition failed"))
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENT: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//elikoga-ical-rs//EN\r
+BEGIN:VEVENT\r
+UID:launch@example.com\r
+DTSTAMP:20240101T000000Z\r
+DTSTART:20240301T100000Z\r
+DTEND:20240301T110000Z\r
+SUMMARY:Launch\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn request(method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn serves_calendars() {
+        let root = std::env::temp_dir().join(format!("caldav-server-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let store = Store::new(&root);
+        let handle = |method: &str, path: &str, headers: &[(&str, &str)], body: &str| {
+            store.handle(&request(method, path, headers, body))
+        };
+
+        assert_eq!(handle("PUT", "/work/launch.ics", &[], EVENT).status, 409);
+        assert_eq!(handle("MKCALENDAR", "/work/", &[], "").status, 201);
+        let created = handle("PUT", "/work/launch.ics", &[("If-None-Match", "*")], EVENT);
+        assert_eq!(created.status, 201);
+        let etag = created.headers[0].1.clone();
+        assert_eq!(
+            handle("PUT", "/work/launch.ics", &[("If-None-Match", "*")], EVENT).status,
+            412
+        );
+        assert_eq!(
+            handle("PUT", "/work/bad.ics", &[], "BEGIN:VCALENDAR").status,
+            400
+        );
+        for (line, broken) in [
+            ("DTSTART:20240301T100000Z", "DTSTART:202é010"),
+            ("SUMMARY:Launch", "RRULE:FREQ=WEEKLY;BYDAY=aéM"),
+        ] {
+            let body = EVENT.replace(line, broken);
+            assert_eq!(handle("PUT", "/work/bad.ics", &[], &body).status, 400);
+        }
+        assert_eq!(handle("GET", "/work/../secret", &[], "").status, 404);
+        let got = handle("GET", "/work/launch.ics", &[], "");
+        assert_eq!((got.status, got.body.as_str()), (200, EVENT));
+        assert!(got.headers.contains(&("ETag".to_string(), etag.clone())));
+
+        let propfind = handle(
+            "PROPFIND",
+            "/work/",
+            &[("Depth", "1")],
+            r#"<D:propfind xmlns:D="DAV:"><D:prop><D:getetag/><D:resourcetype/></D:prop></D:propfind>"#,
+        );
+        assert_eq!(propfind.status, 207);
+        assert!(propfind.body.contains(
+            "<D:response><D:href>/work/</D:href><D:propstat><D:prop>\
+             <D:resourcetype><D:collection/><C:calendar/></D:resourcetype></D:prop>"
+        ));
+        assert!(propfind
+            .body
+            .contains(&format!("<D:getetag>{}</D:getetag>", escape(&etag))));
+
+        let query = |start: &str| {
+            handle(
+                "REPORT",
+                "/work/",
+                &[("Depth", "1")],
+                &format!(
+                    r#"<C:calendar-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+                      <D:prop><D:getetag/><C:calendar-data/></D:prop>
+                      <C:filter><C:comp-filter name="VCALENDAR"><C:comp-filter name="VEVENT">
+                        <C:time-range start="{}"/>
+                      </C:comp-filter></C:comp-filter></C:filter>
+                    </C:calendar-query>"#,
+                    start
+                ),
+            )
+            .body
+        };
+        assert!(query("20240301T000000Z").contains("SUMMARY:Launch"));
+        assert!(!query("20240302T000000Z").contains("<D:response>"));
+        // a resource broken outside the server does not fail the others
+        let broken = root.join("work").join("broken.ics");
+        std::fs::write(&broken, EVENT.replace("20240301T100000Z", "202é0301")).unwrap();
+        let report = query("20240301T000000Z");
+        assert!(report.contains("SUMMARY:Launch"));
+        assert!(report.contains(
+            "<D:href>/work/broken.ics</D:href>\
+             <D:status>HTTP/1.1 500 Internal Server Error</D:status>"
+        ));
+        std::fs::remove_file(&broken).unwrap();
+
+        let multiget = handle(
+            "REPORT",
+            "/work/",
+            &[],
+            r#"<C:calendar-multiget xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+              <D:prop><D:getetag/></D:prop>
+              <D:href>/work/launch.ics</D:href>
+              <D:href>/work/missing.ics</D:href>
+            </C:calendar-multiget>"#,
+        );
+        assert!(multiget
+            .body
+            .contains("<D:href>/work/launch.ics</D:href><D:propstat>"));
+        assert!(multiget.body.contains(
+            "<D:href>/work/missing.ics</D:href><D:status>HTTP/1.1 404 Not Found</D:status>"
+        ));
+
+        assert_eq!(
+            handle("DELETE", "/work/launch.ics", &[("If-Match", "\"0\"")], "").status,
+            412
+        );
+        let weak = format!("W/{}", etag);
+        assert_eq!(
+            handle("DELETE", "/work/launch.ics", &[("If-Match", &weak)], "").status,
+            412
+        );
+        assert_eq!(
+            handle("DELETE", "/work/launch.ics", &[("If-Match", &etag)], "").status,
+            204
+        );
+        assert_eq!(handle("GET", "/work/launch.ics", &[], "").status, 404);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn checks_calendar_object_resources() {
+        let root = std::env::temp_dir().join(format!("caldav-put-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("work")).unwrap();
+        let store = Store::new(&root);
+        let put = |path: &str, body: &str| store.handle(&request("PUT", path, &[], body));
+
+        // a line the serializer would fold is stored and tagged as sent
+        let long = EVENT.replace("SUMMARY:Launch", &format!("SUMMARY:{}", "x".repeat(120)));
+        let created = put("/work/launch.ics", &long);
+        assert_eq!(created.status, 201);
+        let got = store.handle(&request("GET", "/work/launch.ics", &[], ""));
+        assert_eq!(got.body, long);
+        assert_eq!(got.headers[0], created.headers[0]);
+        assert_eq!(put("/work/launch.ics", EVENT).status, 204);
+
+        let event = EVENT.split("BEGIN:VEVENT").nth(1).unwrap();
+        let event = event.split("END:VCALENDAR").next().unwrap();
+        let todo = event.replace("VEVENT", "VTODO");
+        let other = event.replace("launch@", "other@");
+        for (added, condition) in [
+            ("METHOD:REQUEST\r\n", "<C:valid-calendar-object-resource/>"),
+            (
+                &format!("BEGIN:VTODO{}", todo),
+                "<C:valid-calendar-object-resource/>",
+            ),
+            (
+                &format!("BEGIN:VEVENT{}", other),
+                "<C:valid-calendar-object-resource/>",
+            ),
+            (
+                "BEGIN:VFREEBUSY\r\nEND:VFREEBUSY\r\n",
+                "<C:supported-calendar-component/>",
+            ),
+        ] {
+            let body = EVENT.replace("END:VCALENDAR", &format!("{}END:VCALENDAR", added));
+            let refused = put("/work/bad.ics", &body);
+            assert_eq!(refused.status, 403);
+            assert!(refused.body.contains(condition));
+        }
+        let conflict = put("/work/copy.ics", EVENT);
+        assert_eq!(conflict.status, 403);
+        assert!(conflict
+            .body
+            .contains("<C:no-uid-conflict><D:href>/work/launch.ics</D:href></C:no-uid-conflict>"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+// canonical serialization, used for hashing and deduplication
+//
+// two calendars that only differ in the way they were written
+// (case of names, order and quoting of params, order of properties
+// and sub objects, folding) have the same canonical form
+
+use crate::{content_line::ContentLine, ical_object::ICalObject, Param};
+
+// params with enumerated values, these values are case-insensitive
+const ENUMERATED_PARAMS: &[&str] = &[
+    "CUTYPE", "ENCODING", "FBTYPE", "PARTSTAT", "RANGE", "RELATED", "RELTYPE", "ROLE", "RSVP",
+    "VALUE",
+];
+
+/// returns the canonical form of `object`:
+/// - component, property and param names are uppercased
+/// - values of enumerated params (e.g. `VALUE`, `PARTSTAT`) are uppercased
+/// - params are sorted by name
+/// - properties are sorted by name, then by their serialized form
+/// - sub objects are sorted by their canonical serialization
+pub fn canonicalize(object: &ICalObject) -> ICalObject {
+    let mut properties: Vec<ContentLine> = object.properties.iter().map(canonical_line).collect();
+    properties.sort_by_cached_key(|line| (line.name.clone(), line.to_string()));
+
+    let mut sub_objects: Vec<(String, ICalObject)> = object
+        .sub_objects
+        .iter()
+        .map(|sub_object| {
+            let sub_object = canonicalize(sub_object);
+            (sub_object.to_string(), sub_object)
+        })
+        .collect();
+    sub_objects.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    ICalObject {
+        object_type: object.object_type.to_ascii_uppercase(),
+        properties,
+        sub_objects: sub_objects
+            .into_iter()
+            .map(|(_, sub_object)| sub_object)
+            .collect(),
+        child_order: Vec::new(),
+    }
+}
+
+fn canonical_line(line: &ContentLine) -> ContentLine {
+    let mut params: Vec<Param> = line
+        .params
+        .iter()
+        .map(|param| {
+            let name = param.name().to_ascii_uppercase();
+            let values = if ENUMERATED_PARAMS.contains(&name.as_str()) {
+                param
+                    .values()
+                    .iter()
+                    .map(|value| value.to_ascii_uppercase())
+                    .collect()
+            } else {
+                param.values().to_vec()
+            };
+            Param::new(name, values)
+        })
+        .collect();
+    // stable, so params with the same name keep their relative order
+    params.sort_by(|a, b| a.name().cmp(b.name()));
+    ContentLine::new(line.name.to_ascii_uppercase(), params, line.value.clone())
+}
+
+/// the canonical serialization of `object`, folded at 75 octets
+pub fn to_canonical_string(object: &ICalObject) -> String {
+    canonicalize(object).to_string()
+}
+
+/// 64 bit FNV-1a hash of the canonical serialization
+///
+/// unlike [std::collections::hash_map::DefaultHasher] this is stable
+/// across rust versions and platforms, so it can be persisted
+pub fn content_hash(object: &ICalObject) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    to_canonical_string(object)
+        .bytes()
+        .fold(OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+        })
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::{content_hash, to_canonical_string};
+    use crate::ICalObject;
+
+    #[test]
+    fn differently_formatted_calendars_hash_identically() {
+        let a: ICalObject = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:1@example.com\r
+DTSTART;VALUE=DATE;X-FOO=\"bar\":20220101\r
+SUMMARY:A long summary that is folded by the producer at a rather unusual p\r
+ osition\r
+END:VEVENT\r
+BEGIN:VTODO\r
+UID:2@example.com\r
+END:VTODO\r
+END:VCALENDAR\r
+"
+        .parse()
+        .unwrap();
+        let b: ICalObject = "begin:vcalendar\r
+BEGIN:VTODO\r
+uid:2@example.com\r
+END:VTODO\r
+begin:vevent\r
+summary:A long summary that is folded by the producer at a rather unusual position\r
+dtstart;x-foo=bar;value=date:20220101\r
+uid:1@example.com\r
+end:vevent\r
+version:2.0\r
+end:vcalendar\r
+"
+        .parse()
+        .unwrap();
+        assert_ne!(a, b);
+        assert_eq!(to_canonical_string(&a), to_canonical_string(&b));
+        assert_eq!(content_hash(&a), content_hash(&b));
+        assert!(to_canonical_string(&a).starts_with(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nDTSTART;VALUE=DATE;X-FOO=bar:20220101\r\n"
+        ));
+
+        let mut c = a.clone();
+        c.sub_objects[0].properties[0].value = "2@example.com".to_string();
+        assert_ne!(content_hash(&a), content_hash(&c));
+    }
+}
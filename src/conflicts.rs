@@ -0,0 +1,222 @@
+// double bookings: occurrences of events that overlap in time
+
+use std::collections::HashSet;
+
+use eyre::Result;
+
+use crate::datetime::DateTime;
+use crate::interval_tree::IntervalTree;
+use crate::recurrence::{instances, Instance};
+use crate::ICalObject;
+
+/// how events with a DATE start take part in [conflicts]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllDay {
+    /// they conflict with everything they overlap
+    Include,
+    /// they only conflict with each other
+    Separate,
+    /// they never conflict
+    Ignore,
+}
+
+/// options for [conflicts]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictOptions {
+    /// skip events with TRANSP:TRANSPARENT
+    pub ignore_transparent: bool,
+    /// skip events with STATUS:CANCELLED
+    pub ignore_cancelled: bool,
+    pub all_day: AllDay,
+}
+
+impl Default for ConflictOptions {
+    fn default() -> Self {
+        ConflictOptions {
+            ignore_transparent: true,
+            ignore_cancelled: true,
+            all_day: AllDay::Include,
+        }
+    }
+}
+
+/// two overlapping occurrences, `first` starts no later than `second`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict<'a> {
+    pub first: Instance<'a>,
+    pub second: Instance<'a>,
+}
+
+fn is_all_day(instance: &Instance) -> bool {
+    instance
+        .component
+        .get_property("DTSTART")
+        .is_some_and(|line| !line.value.contains('T'))
+}
+
+fn is_skipped(instance: &Instance, options: &ConflictOptions) -> bool {
+    let is = |name: &str, value: &str| {
+        instance
+            .component
+            .get_property(name)
+            .is_some_and(|line| line.value.trim().eq_ignore_ascii_case(value))
+    };
+    (options.ignore_transparent && is("TRANSP", "TRANSPARENT"))
+        || (options.ignore_cancelled && is("STATUS", "CANCELLED"))
+        || (options.all_day == AllDay::Ignore && is_all_day(instance))
+        // an instant books nothing
+        || instance.start == instance.end
+}
+
+/// the pairs of VEVENT occurrences in calendars that overlap the window
+/// from start to end and each other, sorted by their starts
+///
+/// occurrences are found with an [IntervalTree] instead of comparing every
+/// pair, the same occurrence in several calendars (same UID and
+/// RECURRENCE-ID) is counted once
+pub fn conflicts<'a>(
+    calendars: &'a [ICalObject],
+    start: DateTime,
+    end: DateTime,
+    options: &ConflictOptions,
+) -> Result<Vec<Conflict<'a>>> {
+    let mut occurrences = Vec::new();
+    // the first calendar with an occurrence stands for the others
+    let mut seen = HashSet::new();
+    for calendar in calendars {
+        for instance in instances(calendar, "VEVENT", start, end)? {
+            if is_skipped(&instance, options) {
+                continue;
+            }
+            let uid = instance.component.get_property("UID");
+            if let Some(uid) = uid {
+                if !seen.insert((uid.value.as_str(), instance.recurrence_id)) {
+                    continue;
+                }
+            }
+            occurrences.push(instance);
+        }
+    }
+    occurrences.sort_by_key(|instance| (instance.start, instance.end));
+    let tree = IntervalTree::new(
+        occurrences
+            .iter()
+            .enumerate()
+            .map(|(index, instance)| (instance.start, instance.end, index))
+            .collect(),
+    );
+
+    let mut found = Vec::new();
+    for (index, instance) in occurrences.iter().enumerate() {
+        for (_, _, other) in tree.query(instance.start, instance.end) {
+            // each pair once, in the order of the sorted occurrences
+            if *other <= index {
+                continue;
+            }
+            let other = &occurrences[*other];
+            if other.start == other.end {
+                continue;
+            }
+            let separated =
+                options.all_day == AllDay::Separate && is_all_day(instance) != is_all_day(other);
+            if !separated {
+                found.push(Conflict {
+                    first: instance.clone(),
+                    second: other.clone(),
+                });
+            }
+        }
+    }
+    found.sort_by_key(|conflict| (conflict.first.start, conflict.second.start));
+    Ok(found)
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEAM: &str = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:standup\r
+DTSTART:20240325T090000Z\r
+DURATION:PT30M\r
+RRULE:FREQ=DAILY;COUNT=5\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:offsite\r
+DTSTART;VALUE=DATE:20240327\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:focus\r
+DTSTART:20240326T090000Z\r
+DTEND:20240326T120000Z\r
+TRANSP:TRANSPARENT\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    const PERSONAL: &str = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:dentist\r
+DTSTART:20240325T091500Z\r
+DTEND:20240325T100000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup\r
+RECURRENCE-ID:20240326T090000Z\r
+DTSTART:20240326T090000Z\r
+DURATION:PT30M\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:call\r
+DTSTART:20240326T093000Z\r
+DTEND:20240326T100000Z\r
+STATUS:CANCELLED\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn finds_double_bookings() {
+        let calendars = [TEAM.parse().unwrap(), PERSONAL.parse().unwrap()];
+        let find = |options: &ConflictOptions| {
+            conflicts(
+                &calendars,
+                DateTime::parse("20240325T000000").unwrap(),
+                DateTime::parse("20240401T000000").unwrap(),
+                options,
+            )
+            .unwrap()
+            .into_iter()
+            .map(|conflict| {
+                let uid = |instance: &Instance| {
+                    let uid = &instance.component.get_property("UID").unwrap().value;
+                    format!("{}@{}", uid, instance.start)
+                };
+                format!("{} {}", uid(&conflict.first), uid(&conflict.second))
+            })
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            find(&ConflictOptions::default()),
+            [
+                "standup@20240325T090000 dentist@20240325T091500",
+                "offsite@20240327T000000 standup@20240327T090000"
+            ]
+        );
+        let options = ConflictOptions {
+            ignore_transparent: false,
+            ignore_cancelled: false,
+            all_day: AllDay::Separate,
+        };
+        assert_eq!(
+            find(&options),
+            [
+                "standup@20240325T090000 dentist@20240325T091500",
+                "standup@20240326T090000 focus@20240326T090000",
+                "focus@20240326T090000 call@20240326T093000"
+            ]
+        );
+    }
+}
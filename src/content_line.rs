@@ -5,7 +5,11 @@ use memchr::{memchr, memchr2, memchr3};
 
 // parser for content lines
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// with the `serde` feature this is (de)serialized as
+/// `{"name": "DTSTART", "params": [...], "value": "20220101T090000Z"}`,
+/// the value is the raw, still escaped string
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ContentLine {
     pub name: String,
     pub params: Vec<Param>,
@@ -20,9 +24,36 @@ impl ContentLine {
             value,
         }
     }
+
+    /// a line without params
+    pub fn plain(name: &str, value: String) -> Self {
+        Self::new(name.to_string(), Vec::new(), value)
+    }
+
+    /// names are case-insensitive, see RFC 5545 section 2.1
+    pub fn name_eq(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    /// first param with the given name, compared case-insensitively
+    pub fn param(&self, name: &str) -> Option<&Param> {
+        self.params.iter().find(|param| param.name_eq(name))
+    }
+
+    /// uppercases the property name and all param names,
+    /// values are left untouched
+    pub fn normalize_case(&mut self) {
+        self.name.make_ascii_uppercase();
+        for param in &mut self.params {
+            param.normalize_case();
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// with the `serde` feature this is (de)serialized as
+/// `{"name": "TZID", "values": ["Europe/Berlin"]}`
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Param {
     name: String,
     values: Vec<String>, // assert that there is at least one value
@@ -32,6 +63,22 @@ impl Param {
     pub fn new(name: String, values: Vec<String>) -> Self {
         Self { name, values }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
+
+    pub fn name_eq(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    pub fn normalize_case(&mut self) {
+        self.name.make_ascii_uppercase();
+    }
 }
 
 impl Display for ContentLine {
@@ -39,8 +86,11 @@ impl Display for ContentLine {
         write!(f, "{}", self.name)?;
         for param in &self.params {
             write!(f, ";{}=", param.name)?;
-            for value in &param.values {
-                // check if param.name contains ';', ':' or ',', if so, it is a quoted param
+            for (i, value) in param.values.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                }
+                // check if value contains ';', ':' or ',', if so, it is a quoted param
                 let is_quoted = memchr3(b';', b':', b',', value.as_bytes()).is_some();
                 if is_quoted {
                     write!(f, "\"{}\"", value)?;
@@ -130,7 +180,7 @@ impl FromStr for ContentLine {
             cursor += 1;
             // find first '=' using memchr
             let param_name_end =
-                memchr(b'=', raw_line[cursor..].as_bytes()).ok_or(eyre!("no '=' found"))?;
+                memchr(b'=', &raw_line.as_bytes()[cursor..]).ok_or(eyre!("no '=' found"))?;
             // param name is everything before the first '='
             let param_name = build_name(&raw_line.as_bytes()[cursor..cursor + param_name_end])?;
             cursor += param_name_end;
@@ -141,7 +191,7 @@ impl FromStr for ContentLine {
                 if raw_line.as_bytes()[cursor] == b'"' {
                     cursor += 1;
                     // parse qsafe
-                    let param_value_end = memchr(b'"', raw_line[cursor..].as_bytes())
+                    let param_value_end = memchr(b'"', &raw_line.as_bytes()[cursor..])
                         .ok_or(eyre!("no '\"' found"))?;
                     let param_value =
                         build_qsafe(&raw_line.as_bytes()[cursor..cursor + param_value_end])?;
@@ -150,7 +200,7 @@ impl FromStr for ContentLine {
                     cursor += 1;
                 } else {
                     // parse safe
-                    let param_value_end = memchr3(b',', b';', b':', raw_line[cursor..].as_bytes())
+                    let param_value_end = memchr3(b',', b';', b':', &raw_line.as_bytes()[cursor..])
                         .ok_or(eyre!("no ',' or ';' or ':' found"))?;
                     let param_value =
                         build_safe(&raw_line.as_bytes()[cursor..cursor + param_value_end])?;
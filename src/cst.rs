@@ -0,0 +1,360 @@
+// lossless concrete syntax tree
+//
+// every content line remembers the physical lines it was parsed from,
+// serializing writes those raw lines back unless the content line was changed,
+// so a parse/modify/serialize cycle only touches the modified lines
+//
+// edit through an ICalObject and merge the result back with CstObject::update,
+// or edit the CstLines directly
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    io::{BufRead, Cursor},
+    str::FromStr,
+};
+
+use eyre::{eyre, Result};
+
+use crate::{
+    content_line::ContentLine,
+    fold::{fold_with_options, FoldOptions},
+    ical_object::{ChildRef, ICalObject},
+    unfold::Unfold,
+};
+
+/// a content line and the raw text it was parsed from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstLine {
+    pub line: ContentLine,
+    // the line as parsed and its physical lines, including line breaks
+    original: Option<(ContentLine, String)>,
+}
+
+impl CstLine {
+    /// a line without raw text, it will be folded when serialized
+    pub fn new(line: ContentLine) -> CstLine {
+        CstLine {
+            line,
+            original: None,
+        }
+    }
+
+    /// the physical lines this line was parsed from
+    pub fn raw(&self) -> Option<&str> {
+        self.original.as_ref().map(|(_, raw)| raw.as_str())
+    }
+
+    /// whether the line differs from what was parsed
+    pub fn is_modified(&self) -> bool {
+        match &self.original {
+            Some((original, _)) => *original != self.line,
+            None => true,
+        }
+    }
+
+    fn write_to(&self, out: &mut String, fold_options: &FoldOptions) {
+        match &self.original {
+            Some((original, raw)) if *original == self.line => out.push_str(raw),
+            _ => {
+                out.push_str(&fold_with_options(&self.line.to_string(), fold_options));
+                out.push_str("\r\n");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CstNode {
+    Line(CstLine),
+    Object(CstObject),
+}
+
+/// a component with its BEGIN and END lines and its children in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstObject {
+    pub begin: CstLine,
+    pub children: Vec<CstNode>,
+    pub end: CstLine,
+}
+
+impl CstObject {
+    pub fn object_type(&self) -> &str {
+        &self.begin.line.value
+    }
+
+    pub fn from_bufread(read: &mut impl BufRead) -> Result<CstObject> {
+        let mut unfold = Unfold::new(read);
+        let mut lines = std::iter::from_fn(|| unfold.next_with_raw()).map(|line| {
+            let (line, raw) = line?;
+            let line = line.parse::<ContentLine>()?;
+            Ok(CstLine {
+                line: line.clone(),
+                original: Some((line, raw)),
+            })
+        });
+        let object = CstObject::from_lines(&mut lines)?;
+        match lines.next() {
+            None => Ok(object),
+            Some(line) => Err(eyre!(
+                "unexpected content after END:{}: {}",
+                object.object_type(),
+                line?.line
+            )),
+        }
+    }
+
+    fn from_lines(lines: &mut impl Iterator<Item = Result<CstLine>>) -> Result<CstObject> {
+        let begin = lines.next().ok_or(eyre!("no line found"))??;
+        if !begin.line.name_eq("BEGIN") {
+            return Err(eyre!("expected BEGIN"));
+        }
+        Self::from_lines_after_begin(begin, lines)
+    }
+
+    fn from_lines_after_begin(
+        begin: CstLine,
+        lines: &mut impl Iterator<Item = Result<CstLine>>,
+    ) -> Result<CstObject> {
+        let mut children = Vec::new();
+        loop {
+            let line = lines.next().ok_or(eyre!(
+                "unexpected end of input, expected END:{}",
+                begin.line.value
+            ))??;
+            if line.line.name_eq("END") {
+                if !line.line.value.eq_ignore_ascii_case(&begin.line.value) {
+                    return Err(eyre!("expected END:{}", begin.line.value));
+                }
+                return Ok(CstObject {
+                    begin,
+                    children,
+                    end: line,
+                });
+            }
+            if line.line.name_eq("BEGIN") {
+                children.push(CstNode::Object(Self::from_lines_after_begin(line, lines)?));
+            } else {
+                children.push(CstNode::Line(line));
+            }
+        }
+    }
+
+    /// a tree without raw text, everything will be folded when serialized
+    pub fn from_ical_object(object: &ICalObject) -> CstObject {
+        let marker = |name: &str| {
+            CstLine::new(ContentLine::new(
+                name.to_string(),
+                Vec::new(),
+                object.object_type.clone(),
+            ))
+        };
+        CstObject {
+            begin: marker("BEGIN"),
+            children: object
+                .children()
+                .map(|child| match child {
+                    ChildRef::Property(line) => CstNode::Line(CstLine::new(line.clone())),
+                    ChildRef::SubObject(object) => {
+                        CstNode::Object(CstObject::from_ical_object(object))
+                    }
+                })
+                .collect(),
+            end: marker("END"),
+        }
+    }
+
+    pub fn to_ical_object(&self) -> ICalObject {
+        let mut object = ICalObject {
+            object_type: self.object_type().to_string(),
+            ..ICalObject::default()
+        };
+        for child in &self.children {
+            match child {
+                CstNode::Line(line) => object.push_property(line.line.clone()),
+                CstNode::Object(sub_object) => object.push_sub_object(sub_object.to_ical_object()),
+            }
+        }
+        object
+    }
+
+    /// makes this tree represent `object`, keeping the raw text of every
+    /// line that is unchanged
+    ///
+    /// lines are matched by equality, sub objects by type and UID,
+    /// falling back to the order in which they appear
+    pub fn update(&mut self, object: &ICalObject) {
+        if !self.object_type().eq_ignore_ascii_case(&object.object_type) {
+            self.begin.line.value = object.object_type.clone();
+            self.end.line.value = object.object_type.clone();
+        }
+
+        let mut old_lines: HashMap<ContentLine, VecDeque<CstLine>> = HashMap::new();
+        let mut old_objects: Vec<Option<CstObject>> = Vec::new();
+        let mut by_uid: HashMap<(String, String), VecDeque<usize>> = HashMap::new();
+        let mut by_type: HashMap<String, VecDeque<usize>> = HashMap::new();
+        for child in self.children.drain(..) {
+            match child {
+                CstNode::Line(line) => old_lines
+                    .entry(line.line.clone())
+                    .or_default()
+                    .push_back(line),
+                CstNode::Object(old_object) => {
+                    let object_type = old_object.object_type().to_ascii_uppercase();
+                    if let Some(uid) = old_object.uid() {
+                        by_uid
+                            .entry((object_type.clone(), uid.to_string()))
+                            .or_default()
+                            .push_back(old_objects.len());
+                    }
+                    by_type
+                        .entry(object_type)
+                        .or_default()
+                        .push_back(old_objects.len());
+                    old_objects.push(Some(old_object));
+                }
+            }
+        }
+
+        for child in object.children() {
+            match child {
+                ChildRef::Property(line) => {
+                    let old_line = old_lines.get_mut(line).and_then(VecDeque::pop_front);
+                    self.children.push(CstNode::Line(
+                        old_line.unwrap_or_else(|| CstLine::new(line.clone())),
+                    ));
+                }
+                ChildRef::SubObject(sub_object) => {
+                    let object_type = sub_object.object_type.to_ascii_uppercase();
+                    let uid = sub_object
+                        .get_property("UID")
+                        .map(|line| line.value.clone());
+                    let mut take = |queue: Option<&mut VecDeque<usize>>| {
+                        let queue = queue?;
+                        while let Some(i) = queue.pop_front() {
+                            if let Some(old_object) = old_objects[i].take() {
+                                return Some(old_object);
+                            }
+                        }
+                        None
+                    };
+                    let old_object = match uid {
+                        Some(uid) => take(by_uid.get_mut(&(object_type.clone(), uid))),
+                        None => None,
+                    }
+                    .or_else(|| take(by_type.get_mut(&object_type)));
+                    let new_object = match old_object {
+                        Some(mut old_object) => {
+                            old_object.update(sub_object);
+                            old_object
+                        }
+                        None => CstObject::from_ical_object(sub_object),
+                    };
+                    self.children.push(CstNode::Object(new_object));
+                }
+            }
+        }
+    }
+
+    fn uid(&self) -> Option<&str> {
+        self.children.iter().find_map(|child| match child {
+            CstNode::Line(line) if line.line.name_eq("UID") => Some(line.line.value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// serializes, modified lines are folded with `fold_options`
+    pub fn to_string_with(&self, fold_options: &FoldOptions) -> String {
+        let mut out = String::new();
+        self.write_to(&mut out, fold_options);
+        out
+    }
+
+    fn write_to(&self, out: &mut String, fold_options: &FoldOptions) {
+        self.begin.write_to(out, fold_options);
+        for child in &self.children {
+            match child {
+                CstNode::Line(line) => line.write_to(out, fold_options),
+                CstNode::Object(object) => object.write_to(out, fold_options),
+            }
+        }
+        self.end.write_to(out, fold_options);
+    }
+}
+
+impl FromStr for CstObject {
+    type Err = eyre::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        CstObject::from_bufread(&mut Cursor::new(s))
+    }
+}
+
+impl Display for CstObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_string_with(&FoldOptions::default()))
+    }
+}
+
+impl From<&CstObject> for ICalObject {
+    fn from(cst: &CstObject) -> Self {
+        cst.to_ical_object()
+    }
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::CstObject;
+
+    #[test]
+    fn only_modified_lines_change() {
+        let input = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:1@example.com\r
+SUMMARY;LANGUAGE=\"en\":A summary that was folded by its produc\r
+\ter with a tab\r
+DESCRIPTION:first\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:2@example.com\r
+LOCATION:Some place with a very long name that is folded somewhere in the mid\r
+ dle\r
+END:VEVENT\r
+END:VCALENDAR";
+        let mut cst: CstObject = input.parse().unwrap();
+        assert_eq!(cst.to_string(), input);
+
+        let mut ical = cst.to_ical_object();
+        ical.sub_objects[0]
+            .properties
+            .iter_mut()
+            .find(|line| line.name == "DESCRIPTION")
+            .unwrap()
+            .value = "second".to_string();
+        // and move the first event to the end
+        let first = ical.remove_sub_object(0);
+        ical.push_sub_object(first);
+        cst.update(&ical);
+        assert_eq!(cst.to_ical_object(), ical);
+
+        let output = cst.to_string();
+        assert_eq!(
+            output,
+            "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:2@example.com\r
+LOCATION:Some place with a very long name that is folded somewhere in the mid\r
+ dle\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:1@example.com\r
+SUMMARY;LANGUAGE=\"en\":A summary that was folded by its produc\r
+\ter with a tab\r
+DESCRIPTION:second\r
+END:VEVENT\r
+END:VCALENDAR"
+        );
+    }
+}
@@ -0,0 +1,291 @@
+// date and time arithmetic for the values of RFC 5545
+//
+// a DateTime has no time zone, callers apply UTC offsets in seconds,
+// durations are plain seconds, which is exact for everything but DST
+// transitions within nominal durations like P1D
+
+use std::fmt::Display;
+
+use eyre::{eyre, Result};
+
+/// a date and time in the proleptic Gregorian calendar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    pub fn new(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> Self {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    /// parses a DATE or a DATE-TIME in the basic format, a trailing `Z` is ignored
+    pub fn parse(value: &str) -> Result<DateTime> {
+        let invalid = || eyre!("invalid DATE or DATE-TIME: {}", value);
+        let digits = |s: &str| -> Result<u32> {
+            if s.bytes().all(|b| b.is_ascii_digit()) {
+                s.parse().map_err(|_| invalid())
+            } else {
+                Err(invalid())
+            }
+        };
+        // the lengths below are in bytes
+        if !value.is_ascii() {
+            return Err(invalid());
+        }
+        let (date, time) = value.split_once('T').unwrap_or((value, "000000"));
+        let time = time.strip_suffix('Z').unwrap_or(time);
+        if date.len() != 8 || time.len() != 6 {
+            return Err(invalid());
+        }
+        let date_time = DateTime::new(
+            digits(&date[0..4])? as i64,
+            digits(&date[4..6])?,
+            digits(&date[6..8])?,
+            digits(&time[0..2])?,
+            digits(&time[2..4])?,
+            digits(&time[4..6])?,
+        );
+        // 60 is a leap second
+        if !(1..=12).contains(&date_time.month)
+            || date_time.day == 0
+            || date_time.day > days_in_month(date_time.year, date_time.month)
+            || date_time.hour > 23
+            || date_time.minute > 59
+            || date_time.second > 60
+        {
+            return Err(invalid());
+        }
+        Ok(date_time)
+    }
+
+    /// seconds since 1970-01-01T00:00:00
+    pub fn timestamp(self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) * 86400
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+    }
+
+    pub fn from_timestamp(timestamp: i64) -> DateTime {
+        let (days, seconds) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+        DateTime::new(
+            year,
+            month,
+            day,
+            (seconds / 3600) as u32,
+            (seconds % 3600 / 60) as u32,
+            (seconds % 60) as u32,
+        )
+    }
+
+    pub fn add_seconds(self, seconds: i64) -> DateTime {
+        DateTime::from_timestamp(self.timestamp() + seconds)
+    }
+
+    /// the day of the week, 0 is Monday and 6 is Sunday
+    pub fn weekday(self) -> u32 {
+        // 1970-01-01 was a Thursday
+        (days_from_civil(self.year, self.month, self.day) + 3).rem_euclid(7) as u32
+    }
+
+    /// the day of the year, starting at 1
+    pub fn day_of_year(self) -> u32 {
+        (days_from_civil(self.year, self.month, self.day) - days_from_civil(self.year, 1, 1)) as u32
+            + 1
+    }
+
+    /// the date in the basic format, `YYYYMMDD`
+    pub fn to_date_string(self) -> String {
+        format!("{:04}{:02}{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}T{:02}{:02}{:02}",
+            self.to_date_string(),
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+pub fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let (month, day) = (month as i64, day as i64);
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// parses a UTC-OFFSET like `+0100` or `-053000` into seconds
+pub fn parse_utc_offset(value: &str) -> Result<i64> {
+    let invalid = || eyre!("invalid UTC-OFFSET: {}", value);
+    let sign = match value.as_bytes().first() {
+        Some(b'+') => 1,
+        Some(b'-') => -1,
+        _ => return Err(invalid()),
+    };
+    let digits = &value[1..];
+    if !(digits.len() == 4 || digits.len() == 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let part = |range: std::ops::Range<usize>| digits.get(range).map_or(Ok(0), str::parse::<i64>);
+    Ok(sign * (part(0..2)? * 3600 + part(2..4)? * 60 + part(4..6)?))
+}
+
+/// parses a DURATION like `-P1DT2H` or `P2W` into seconds
+pub fn parse_duration(value: &str) -> Result<i64> {
+    let invalid = || eyre!("invalid DURATION: {}", value);
+    let (sign, rest) = match value.as_bytes().first() {
+        Some(b'-') => (-1, &value[1..]),
+        Some(b'+') => (1, &value[1..]),
+        _ => (1, value),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+    let mut seconds = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in rest.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            }
+            'T' if !in_time && number.is_empty() => {
+                in_time = true;
+                continue;
+            }
+            'W' if !in_time => 7 * 86400,
+            'D' if !in_time => 86400,
+            'H' if in_time => 3600,
+            'M' if in_time => 60,
+            'S' if in_time => 1,
+            _ => return Err(invalid()),
+        };
+        seconds = number
+            .parse::<i64>()
+            .ok()
+            .and_then(|number| number.checked_mul(unit))
+            .and_then(|part| part.checked_add(seconds))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || rest.is_empty() || rest.ends_with('T') {
+        return Err(invalid());
+    }
+    // longer than any two DATE-TIME values are apart, so that adding it can not overflow
+    if seconds > 10000 * 366 * 86400 {
+        return Err(invalid());
+    }
+    Ok(sign * seconds)
+}
+
+/// formats seconds as a DURATION like `P1DT1H30M`
+pub fn format_duration(seconds: i64) -> String {
+    let mut out = String::from(if seconds < 0 { "-P" } else { "P" });
+    let seconds = seconds.abs();
+    let (days, rest) = (seconds / 86400, seconds % 86400);
+    if days > 0 {
+        out.push_str(&format!("{}D", days));
+    }
+    if rest > 0 || days == 0 {
+        out.push('T');
+        let (hours, minutes, seconds) = (rest / 3600, rest % 3600 / 60, rest % 60);
+        if hours > 0 {
+            out.push_str(&format!("{}H", hours));
+        }
+        if minutes > 0 {
+            out.push_str(&format!("{}M", minutes));
+        }
+        if seconds > 0 || rest == 0 {
+            out.push_str(&format!("{}S", seconds));
+        }
+    }
+    out
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_math() {
+        let date_time = DateTime::parse("20240229T233000Z").unwrap();
+        assert_eq!(date_time.to_string(), "20240229T233000");
+        assert_eq!(date_time.add_seconds(3600).to_string(), "20240301T003000");
+        assert_eq!(DateTime::parse("19700101").unwrap().timestamp(), 0);
+        assert_eq!(DateTime::from_timestamp(-1).to_string(), "19691231T235959");
+        assert!(DateTime::parse("20230229").is_err());
+        assert!(DateTime::parse("2023-01-01").is_err());
+        assert!(DateTime::parse("202é010").is_err());
+        assert!(DateTime::parse("20240101T12é000").is_err());
+
+        assert_eq!(date_time.weekday(), 3);
+        assert_eq!(date_time.day_of_year(), 60);
+        assert_eq!(parse_utc_offset("-0530").unwrap(), -(5 * 3600 + 30 * 60));
+        assert!(parse_utc_offset("0100").is_err());
+
+        assert_eq!(parse_duration("-P1DT2H").unwrap(), -(86400 + 7200));
+        assert_eq!(parse_duration("P2W").unwrap(), 14 * 86400);
+        assert_eq!(parse_duration("PT0S").unwrap(), 0);
+        assert!(parse_duration("P1H").is_err());
+        assert!(parse_duration("PT").is_err());
+        assert!(parse_duration("P999999999999999D").is_err());
+        assert!(parse_duration("P99999999999999999999W").is_err());
+        assert_eq!(format_duration(86400 + 5400), "P1DT1H30M");
+        assert_eq!(format_duration(0), "PT0S");
+    }
+}
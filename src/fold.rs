@@ -1,34 +1,183 @@
 // folds a single line
 
+use unicode_segmentation::UnicodeSegmentation;
+
 pub fn fold(line: &str) -> String {
     fold_with_max_length(line, 75)
 }
 
 pub fn fold_with_max_length(line: &str, max_length: usize) -> String {
-    let mut new_line_buf = String::new();
-
-    // add line characters to the new line buffer
-    // until the line is too long
-    let mut current_line_length = 0;
-    for c in line.chars() {
-        if current_line_length + c.len_utf8() > max_length {
-            // add a newline
-            new_line_buf.push('\r');
-            new_line_buf.push('\n');
-            // ad a whitespace (in our case " ")
-            new_line_buf.push(' ');
-            // add the current character
-            new_line_buf.push(c);
-            // update the current line length
-            current_line_length = ' '.len_utf8() + c.len_utf8();
-        } else {
-            new_line_buf.push(c);
-            current_line_length += c.len_utf8();
+    fold_with_options(
+        line,
+        &FoldOptions {
+            max_octets: max_length,
+            ..FoldOptions::default()
+        },
+    )
+}
+
+pub fn fold_with_options(line: &str, options: &FoldOptions) -> String {
+    let mut new_line_buf = String::with_capacity(line.len());
+    for (i, segment) in FoldSegments::with_options(line, options).enumerate() {
+        if i > 0 {
+            // add a newline and the continuation whitespace
+            new_line_buf.push_str("\r\n");
+            new_line_buf.push(options.continuation.as_char());
         }
+        new_line_buf.push_str(segment);
     }
     new_line_buf
 }
 
+/// the linear white-space that starts a continuation line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Continuation {
+    #[default]
+    Space,
+    Tab,
+}
+
+impl Continuation {
+    pub fn as_char(self) -> char {
+        match self {
+            Continuation::Space => ' ',
+            Continuation::Tab => '\t',
+        }
+    }
+}
+
+/// how content lines are folded when serializing
+///
+/// the default folds at 75 octets with a SPACE, like [fold]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoldOptions {
+    /// maximum octets of a physical line, excluding the CRLF
+    /// and including the continuation whitespace
+    pub max_octets: usize,
+    pub continuation: Continuation,
+    /// if false, lines are never folded
+    pub enabled: bool,
+    /// never split a backslash escape like `\n` or `\,` across two lines
+    pub keep_escapes: bool,
+    /// only split at extended grapheme cluster boundaries,
+    /// so combining sequences and emoji ZWJ sequences stay on one line
+    pub keep_grapheme_clusters: bool,
+}
+
+impl Default for FoldOptions {
+    fn default() -> Self {
+        FoldOptions {
+            max_octets: 75,
+            continuation: Continuation::Space,
+            enabled: true,
+            keep_escapes: false,
+            keep_grapheme_clusters: false,
+        }
+    }
+}
+
+impl FoldOptions {
+    /// folds at 75 octets and never splits escapes or grapheme clusters
+    pub fn safe() -> FoldOptions {
+        FoldOptions {
+            keep_escapes: true,
+            keep_grapheme_clusters: true,
+            ..FoldOptions::default()
+        }
+    }
+}
+
+/// iterator over the physical lines of a folded line, without the
+/// CRLF and the leading whitespace of the continuation lines
+///
+/// every segment is at most `max_octets` octets long, including
+/// the leading whitespace of continuation lines, unless a single
+/// character (or escape sequence, or grapheme cluster) does not fit
+#[derive(Debug, Clone)]
+pub struct FoldSegments<'a> {
+    rest: &'a str,
+    options: FoldOptions,
+    first: bool,
+}
+
+impl<'a> FoldSegments<'a> {
+    pub fn new(line: &'a str, max_length: usize) -> FoldSegments<'a> {
+        FoldSegments::with_options(
+            line,
+            &FoldOptions {
+                max_octets: max_length,
+                ..FoldOptions::default()
+            },
+        )
+    }
+
+    pub fn with_options(line: &'a str, options: &FoldOptions) -> FoldSegments<'a> {
+        FoldSegments {
+            rest: line,
+            options: options.clone(),
+            first: true,
+        }
+    }
+}
+
+impl<'a> Iterator for FoldSegments<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.rest.is_empty() && !self.first {
+            return None;
+        }
+        if !self.options.enabled {
+            self.first = false;
+            return Some(std::mem::take(&mut self.rest));
+        }
+        // continuation lines start with a whitespace
+        let max_length = if self.first {
+            self.options.max_octets
+        } else {
+            self.options
+                .max_octets
+                .saturating_sub(self.options.continuation.as_char().len_utf8())
+        };
+        self.first = false;
+        let end = if self.options.keep_grapheme_clusters {
+            self.segment_end(self.rest.graphemes(true), max_length)
+        } else {
+            self.segment_end(self.rest.split_inclusive(|_| true), max_length)
+        };
+        let (segment, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(segment)
+    }
+}
+
+impl FoldSegments<'_> {
+    // octet length of the next segment, `units` are the
+    // characters or grapheme clusters of the rest of the line
+    fn segment_end<'b>(&self, units: impl Iterator<Item = &'b str>, max_length: usize) -> usize {
+        // add line units to the segment until the segment is too long
+        let mut end = 0;
+        // start of the backslash, if the last unit starts an escape
+        let mut escape_start = None;
+        for unit in units {
+            if end + unit.len() > max_length && end > 0 {
+                // don't split between the backslash and the escaped character
+                if let Some(escape_start) = escape_start.filter(|start| *start > 0) {
+                    end = escape_start;
+                }
+                break;
+            }
+            escape_start = if self.options.keep_escapes && unit == "\\" && escape_start.is_none() {
+                Some(end)
+            } else {
+                None
+            };
+            end += unit.len();
+        }
+        end
+    }
+}
+
 #[cfg(test)]
 pub fn fold_and_join(lines: &[String]) -> String {
     // normal fold each line
@@ -78,5 +227,82 @@ mod tests {
         }
     }
 
-    // the next test is a bit more difficult:
+    #[test]
+    fn fold_options_are_respected() {
+        let line = format!("DESCRIPTION:{}\\nnext line", "a".repeat(60));
+        // 12 + 60 = 72 octets, the escape starts at octet 73
+        assert_eq!(
+            fold_with_max_length(&line, 73),
+            format!("DESCRIPTION:{}\\\r\n nnext line", "a".repeat(60))
+        );
+        let options = FoldOptions {
+            max_octets: 73,
+            continuation: Continuation::Tab,
+            keep_escapes: true,
+            ..FoldOptions::default()
+        };
+        assert_eq!(
+            fold_with_options(&line, &options),
+            format!("DESCRIPTION:{}\r\n\t\\nnext line", "a".repeat(60))
+        );
+        // an escaped backslash followed by an escape
+        let line = format!("{}\\\\\\n", "a".repeat(71));
+        assert_eq!(
+            fold_with_options(&line, &options),
+            format!("{}\\\\\r\n\t\\n", "a".repeat(71))
+        );
+        let options = FoldOptions {
+            enabled: false,
+            ..FoldOptions::default()
+        };
+        assert_eq!(fold_with_options(&line, &options), line);
+        for segment in FoldSegments::new(&"ä".repeat(100), 74).skip(1) {
+            assert!(segment.len() < 74);
+        }
+    }
+
+    #[test]
+    fn grapheme_clusters_are_never_split() {
+        let lines = [
+            // combining acute accents
+            format!("SUMMARY:{}", "e\u{301}".repeat(40)),
+            // devanagari with virama and vowel signs
+            format!("SUMMARY:{}", "नमस्ते क्षत्रिय ".repeat(10)),
+            // emoji ZWJ sequences, skin tones and flags
+            format!("DESCRIPTION:{}", "👩‍👩‍👧‍👦👍🏽🇩🇪".repeat(12)),
+            // hangul jamo and escapes
+            format!("LOCATION:{}", "\\n\\,각".repeat(20)),
+        ];
+        for line in &lines {
+            let boundaries: Vec<usize> = line
+                .grapheme_indices(true)
+                .map(|(i, _)| i)
+                .chain([line.len()])
+                .collect();
+            for max_octets in [20, 40, 74, 75] {
+                let options = FoldOptions {
+                    max_octets,
+                    ..FoldOptions::safe()
+                };
+                let segments: Vec<&str> = FoldSegments::with_options(line, &options).collect();
+                assert_eq!(segments.concat(), *line);
+                let mut offset = 0;
+                for (i, segment) in segments.iter().enumerate() {
+                    offset += segment.len();
+                    assert!(boundaries.contains(&offset), "{:?}", segments);
+                    assert!(!segment.ends_with('\\') || segment.ends_with("\\\\"));
+                    let length = if i == 0 { 0 } else { 1 } + segment.len();
+                    // every cluster in these lines is at most 25 octets
+                    assert!(length <= max_octets.max(26), "{:?}", segments);
+                }
+                // and unfolding restores the line
+                let folded = fold_with_options(line, &options) + "\r\n";
+                let unfolded = Unfold::new(std::io::Cursor::new(folded + "END:X\r\n"))
+                    .next()
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(unfolded, *line);
+            }
+        }
+    }
 }
@@ -0,0 +1,305 @@
+// busy time from calendars, for scheduling and room booking,
+// see https://icalendar.org/iCalendar-RFC-5545/3-6-4-free-busy-component.html
+
+use eyre::Result;
+
+use crate::availability::unavailable_time;
+use crate::datetime::DateTime;
+use crate::recurrence::instances;
+use crate::value::same_address;
+use crate::{ContentLine, ICalObject, Param};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FreeBusyType {
+    Busy,
+    BusyUnavailable,
+    BusyTentative,
+}
+
+impl FreeBusyType {
+    pub fn name(self) -> &'static str {
+        match self {
+            FreeBusyType::Busy => "BUSY",
+            FreeBusyType::BusyUnavailable => "BUSY-UNAVAILABLE",
+            FreeBusyType::BusyTentative => "BUSY-TENTATIVE",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FreeBusyType> {
+        [
+            FreeBusyType::Busy,
+            FreeBusyType::BusyUnavailable,
+            FreeBusyType::BusyTentative,
+        ]
+        .into_iter()
+        .find(|busy_type| busy_type.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// how an event occupies the time of an attendee, None if it does not
+///
+/// TRANSPARENT and CANCELLED events and those the attendee declined are
+/// free, TENTATIVE ones and those the attendee has not answered yet are
+/// BUSY-TENTATIVE, an event without ORGANIZER and ATTENDEE is the attendee's own
+pub fn busy_type(event: &ICalObject, attendee: &str) -> Option<FreeBusyType> {
+    let value = |name: &str| event.get_property(name).map(|line| line.value.trim());
+    if value("TRANSP").is_some_and(|transp| transp.eq_ignore_ascii_case("TRANSPARENT"))
+        || value("STATUS").is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED"))
+    {
+        return None;
+    }
+    let tentative = value("STATUS").is_some_and(|status| status.eq_ignore_ascii_case("TENTATIVE"));
+    let organizer = event.get_property("ORGANIZER");
+    let partstat = match event.get_attendee(attendee) {
+        Some(line) => line
+            .param("PARTSTAT")
+            .and_then(|param| param.values().first())
+            .map_or("NEEDS-ACTION".to_string(), |value| {
+                value.to_ascii_uppercase()
+            }),
+        // the organizer takes part unless listed otherwise
+        None if organizer.is_some_and(|line| same_address(&line.value, attendee)) => {
+            "ACCEPTED".to_string()
+        }
+        None if organizer.is_none() && event.get_property("ATTENDEE").is_none() => {
+            "ACCEPTED".to_string()
+        }
+        None => return None,
+    };
+    match partstat.as_str() {
+        "DECLINED" | "DELEGATED" => None,
+        "TENTATIVE" | "NEEDS-ACTION" => Some(FreeBusyType::BusyTentative),
+        _ if tentative => Some(FreeBusyType::BusyTentative),
+        _ => Some(FreeBusyType::Busy),
+    }
+}
+
+/// the busy time of an attendee from start to end in UTC, as a VFREEBUSY
+/// with DTSTART, DTEND, ATTENDEE and a FREEBUSY for each coalesced period,
+/// see [busy_type] for which events count, the time VAVAILABILITY components
+/// make unavailable is added, BUSY takes precedence over BUSY-UNAVAILABLE and
+/// that over BUSY-TENTATIVE where they overlap
+///
+/// the UID, DTSTAMP and ORGANIZER depend on how it is published, so they
+/// are left to the caller
+pub fn free_busy(
+    calendars: &[ICalObject],
+    attendee: &str,
+    start: DateTime,
+    end: DateTime,
+) -> Result<ICalObject> {
+    let mut busy = Vec::new();
+    let mut unavailable = Vec::new();
+    let mut tentative = Vec::new();
+    for calendar in calendars {
+        for instance in instances(calendar, "VEVENT", start, end)? {
+            let period = (instance.start.max(start), instance.end.min(end));
+            if period.0 >= period.1 {
+                continue;
+            }
+            match busy_type(instance.component, attendee) {
+                Some(FreeBusyType::Busy) => busy.push(period),
+                Some(FreeBusyType::BusyUnavailable) => unavailable.push(period),
+                Some(FreeBusyType::BusyTentative) => tentative.push(period),
+                None => {}
+            }
+        }
+    }
+    for (period, busy_type) in unavailable_time(calendars, attendee, start, end)? {
+        match busy_type {
+            FreeBusyType::Busy => busy.push(period),
+            FreeBusyType::BusyUnavailable => unavailable.push(period),
+            FreeBusyType::BusyTentative => tentative.push(period),
+        }
+    }
+    let busy = coalesce(busy);
+    let unavailable = subtract(coalesce(unavailable), &busy);
+    let taken = coalesce(busy.iter().chain(&unavailable).copied().collect());
+    let tentative = subtract(coalesce(tentative), &taken);
+    let mut periods: Vec<_> = busy
+        .into_iter()
+        .map(|period| (period, FreeBusyType::Busy))
+        .chain(
+            unavailable
+                .into_iter()
+                .map(|period| (period, FreeBusyType::BusyUnavailable)),
+        )
+        .chain(
+            tentative
+                .into_iter()
+                .map(|period| (period, FreeBusyType::BusyTentative)),
+        )
+        .collect();
+    periods.sort();
+
+    let mut vfreebusy = ICalObject {
+        object_type: "VFREEBUSY".to_string(),
+        ..Default::default()
+    };
+    let line = |name: &str, params, value| ContentLine::new(name.to_string(), params, value);
+    vfreebusy.push_property(line("DTSTART", Vec::new(), format!("{}Z", start)));
+    vfreebusy.push_property(line("DTEND", Vec::new(), format!("{}Z", end)));
+    vfreebusy.push_property(line("ATTENDEE", Vec::new(), attendee.to_string()));
+    for ((from, to), busy_type) in periods {
+        let fbtype = Param::new("FBTYPE".to_string(), vec![busy_type.name().to_string()]);
+        vfreebusy.push_property(line("FREEBUSY", vec![fbtype], format!("{}Z/{}Z", from, to)));
+    }
+    Ok(vfreebusy)
+}
+
+// merges overlapping and adjacent periods
+pub(crate) fn coalesce(mut periods: Vec<(DateTime, DateTime)>) -> Vec<(DateTime, DateTime)> {
+    periods.sort();
+    let mut merged: Vec<(DateTime, DateTime)> = Vec::new();
+    for (start, end) in periods {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// the parts of periods that are not covered by coalesced others
+pub(crate) fn subtract(
+    periods: Vec<(DateTime, DateTime)>,
+    covered: &[(DateTime, DateTime)],
+) -> Vec<(DateTime, DateTime)> {
+    let mut rest = Vec::new();
+    for (mut start, end) in periods {
+        for (covered_start, covered_end) in covered {
+            if *covered_end <= start || *covered_start >= end {
+                continue;
+            }
+            if *covered_start > start {
+                rest.push((start, *covered_start));
+            }
+            start = start.max(*covered_end);
+        }
+        if start < end {
+            rest.push((start, end));
+        }
+    }
+    rest
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOM: &str = "BEGIN:VCALENDAR\r
+BEGIN:VTIMEZONE\r
+TZID:Europe/Berlin\r
+BEGIN:DAYLIGHT\r
+DTSTART:19810329T020000\r
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+DTSTART:19961027T030000\r
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+UID:standup\r
+DTSTART;TZID=Europe/Berlin:20240325T090000\r
+DURATION:PT30M\r
+RRULE:FREQ=DAILY;BYDAY=MO,TU,WE,TH,FR\r
+EXDATE;TZID=Europe/Berlin:20240327T090000\r
+ORGANIZER:mailto:olga@example.com\r
+ATTENDEE;PARTSTAT=ACCEPTED:mailto:room@example.com\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup\r
+RECURRENCE-ID;TZID=Europe/Berlin:20240328T090000\r
+DTSTART;TZID=Europe/Berlin:20240328T091500\r
+DURATION:PT30M\r
+STATUS:TENTATIVE\r
+ORGANIZER:mailto:olga@example.com\r
+ATTENDEE;PARTSTAT=ACCEPTED:mailto:room@example.com\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:review\r
+DTSTART;TZID=Europe/Berlin:20240325T093000\r
+DTEND;TZID=Europe/Berlin:20240325T103000\r
+ORGANIZER:mailto:ben@example.com\r
+ATTENDEE;PARTSTAT=ACCEPTED:mailto:room@example.com\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:declined\r
+DTSTART;TZID=Europe/Berlin:20240326T120000\r
+DTEND;TZID=Europe/Berlin:20240326T130000\r
+ORGANIZER:mailto:ben@example.com\r
+ATTENDEE;PARTSTAT=DECLINED:mailto:room@example.com\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:cancelled\r
+DTSTART;TZID=Europe/Berlin:20240326T140000\r
+DTEND;TZID=Europe/Berlin:20240326T150000\r
+STATUS:CANCELLED\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    const CLEANING: &str = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:cleaning\r
+DTSTART:20240328T090000Z\r
+DTEND:20240328T100000Z\r
+STATUS:TENTATIVE\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:reminder\r
+DTSTART:20240326T100000Z\r
+DTEND:20240326T110000Z\r
+TRANSP:TRANSPARENT\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:other\r
+DTSTART:20240326T100000Z\r
+DTEND:20240326T110000Z\r
+ORGANIZER:mailto:olga@example.com\r
+ATTENDEE:mailto:ben@example.com\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn computes_busy_time() {
+        let calendars = [ROOM.parse().unwrap(), CLEANING.parse().unwrap()];
+        let vfreebusy = free_busy(
+            &calendars,
+            "mailto:room@example.com",
+            DateTime::parse("20240325T000000").unwrap(),
+            DateTime::parse("20240329T000000").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            vfreebusy.to_string(),
+            "BEGIN:VFREEBUSY\r
+DTSTART:20240325T000000Z\r
+DTEND:20240329T000000Z\r
+ATTENDEE:mailto:room@example.com\r
+FREEBUSY;FBTYPE=BUSY:20240325T080000Z/20240325T093000Z\r
+FREEBUSY;FBTYPE=BUSY:20240326T080000Z/20240326T083000Z\r
+FREEBUSY;FBTYPE=BUSY-TENTATIVE:20240328T081500Z/20240328T084500Z\r
+FREEBUSY;FBTYPE=BUSY-TENTATIVE:20240328T090000Z/20240328T100000Z\r
+END:VFREEBUSY\r
+"
+        );
+        // BUSY wins over BUSY-TENTATIVE
+        let tentative = vec![(
+            DateTime::parse("20240101T090000").unwrap(),
+            DateTime::parse("20240101T120000").unwrap(),
+        )];
+        let busy = [(
+            DateTime::parse("20240101T100000").unwrap(),
+            DateTime::parse("20240101T110000").unwrap(),
+        )];
+        assert_eq!(subtract(tentative, &busy).len(), 2);
+    }
+}
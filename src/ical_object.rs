@@ -1,56 +1,169 @@
 use std::{
+    borrow::Cow,
     fmt::Display,
     io::{BufRead, Cursor},
     iter::Peekable,
     str::FromStr,
 };
 
-use crate::{content_line::ContentLine, fold::fold, unfold::Unfold};
+use crate::{
+    content_line::ContentLine,
+    fold::{fold_with_options, FoldOptions},
+    unfold::Unfold,
+    value::same_address,
+};
 use eyre::{eyre, Result};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// options for [ICalObject::from_bufread_with_options]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// uppercase all component, property and param names while parsing
+    pub normalize_case: bool,
+    /// fail if the last line is not terminated by a CRLF
+    pub require_trailing_crlf: bool,
+}
+
+/// with the `serde` feature this is (de)serialized as
+/// `{"object_type": "VEVENT", "properties": [...], "sub_objects": [...], "child_order": [...]}`,
+/// `child_order` may be left out when deserializing and its entries are
+/// `{"property": 0}` or `{"sub_object": 0}`
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ICalObject {
     pub object_type: String,
     pub properties: Vec<ContentLine>,
     pub sub_objects: Vec<ICalObject>,
+    // the order in which properties and sub objects are interleaved, as
+    // indices into `properties` and `sub_objects`, kept by the parser and the
+    // methods that add and remove children, see [ICalObject::children]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) child_order: Vec<Child>,
+}
+
+/// an entry of [ICalObject::effective_child_order]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Child {
+    Property(usize),
+    SubObject(usize),
+}
+
+/// a child of an [ICalObject], see [ICalObject::children]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildRef<'a> {
+    Property(&'a ContentLine),
+    SubObject(&'a ICalObject),
+}
+
+// two objects are equal if their children are equal and in the same order,
+// no matter if the order is explicit or implied
+impl PartialEq for ICalObject {
+    fn eq(&self, other: &Self) -> bool {
+        self.object_type == other.object_type
+            && self.properties == other.properties
+            && self.sub_objects == other.sub_objects
+            && self.effective_child_order() == other.effective_child_order()
+    }
+}
+
+impl Eq for ICalObject {}
+
+/// see [ICalObject::children]
+#[derive(Debug, Clone)]
+pub struct Children<'a> {
+    object: &'a ICalObject,
+    order: Cow<'a, [Child]>,
+    position: usize,
+    // the children after the order, properties first
+    properties: usize,
+    sub_objects: usize,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = ChildRef<'a>;
+
+    fn next(&mut self) -> Option<ChildRef<'a>> {
+        let object = self.object;
+        if let Some(child) = self.order.get(self.position) {
+            self.position += 1;
+            return Some(match *child {
+                Child::Property(i) => ChildRef::Property(&object.properties[i]),
+                Child::SubObject(i) => ChildRef::SubObject(&object.sub_objects[i]),
+            });
+        }
+        if let Some(line) = object.properties.get(self.properties) {
+            self.properties += 1;
+            return Some(ChildRef::Property(line));
+        }
+        let sub_object = object.sub_objects.get(self.sub_objects)?;
+        self.sub_objects += 1;
+        Some(ChildRef::SubObject(sub_object))
+    }
 }
 
 impl ICalObject {
+    /// an object whose children are its properties followed by its sub objects
+    pub fn new(
+        object_type: String,
+        properties: Vec<ContentLine>,
+        sub_objects: Vec<ICalObject>,
+    ) -> Self {
+        ICalObject {
+            object_type,
+            properties,
+            sub_objects,
+            child_order: Vec::new(),
+        }
+    }
+
     pub fn from_peekable(
         peekable: &mut Peekable<impl Iterator<Item = Result<ContentLine>>>,
     ) -> Result<Self> {
         let mut properties = Vec::new();
         let mut sub_objects = Vec::new();
+        let mut child_order = Vec::new();
         let line = peekable.next().ok_or(eyre!("no line found"))??;
         if !line.name.eq_ignore_ascii_case("BEGIN") {
             return Err(eyre!("expected BEGIN"));
         }
         let object_type = line.value;
-        while let Some(line) = match peekable.peek() {
-            Some(Ok(line)) => Some(line),
-            Some(Err(_)) => {
-                // read then return the error
-                let next = peekable.next().unwrap();
-                next?;
-                unreachable!()
-            }
-            None => None,
-        } {
+        loop {
+            let line = match peekable.peek() {
+                Some(Ok(line)) => line,
+                Some(Err(_)) => {
+                    // read then return the error
+                    let next = peekable.next().unwrap();
+                    next?;
+                    unreachable!()
+                }
+                None => {
+                    return Err(eyre!(
+                        "unexpected end of input, expected END:{}",
+                        object_type
+                    ))
+                }
+            };
             if line.name.eq_ignore_ascii_case("END") {
                 // get the next line
                 let line = peekable.next().unwrap()?;
                 // check that the object type matches
-                if line.value != object_type {
+                if !line.value.eq_ignore_ascii_case(&object_type) {
                     return Err(eyre!("expected END:{}", object_type));
                 }
                 break;
             }
             // check if it's a begin property
             if line.name.eq_ignore_ascii_case("BEGIN") {
+                child_order.push(Child::SubObject(sub_objects.len()));
                 sub_objects.push(ICalObject::from_peekable(peekable)?);
             } else {
                 // get line
                 let line = peekable.next().unwrap()?;
+                child_order.push(Child::Property(properties.len()));
                 properties.push(line);
             }
         }
@@ -58,12 +171,219 @@ impl ICalObject {
             object_type,
             properties,
             sub_objects,
+            child_order,
         })
     }
 
+    /// parses exactly one object, anything after its END is an error
     pub fn from_iterator(iterator: &mut impl Iterator<Item = Result<ContentLine>>) -> Result<Self> {
         let mut peekable = iterator.peekable();
-        Self::from_peekable(&mut peekable)
+        let object = Self::from_peekable(&mut peekable)?;
+        match peekable.next() {
+            None => Ok(object),
+            Some(Ok(line)) => Err(eyre!(
+                "unexpected content after END:{}: {}",
+                object.object_type,
+                line
+            )),
+            Some(Err(e)) => Err(e.wrap_err(format!(
+                "unexpected content after END:{}",
+                object.object_type
+            ))),
+        }
+    }
+
+    /// component types are case-insensitive, see RFC 5545 section 2.1
+    pub fn is_type(&self, object_type: &str) -> bool {
+        self.object_type.eq_ignore_ascii_case(object_type)
+    }
+
+    /// first property with the given name
+    pub fn get_property(&self, name: &str) -> Option<&ContentLine> {
+        self.properties.iter().find(|line| line.name_eq(name))
+    }
+
+    /// all properties with the given name
+    pub fn get_properties<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a ContentLine> {
+        self.properties
+            .iter()
+            .filter(move |line| line.name_eq(name))
+    }
+
+    /// the ATTENDEE with the given calendar address
+    pub fn get_attendee(&self, address: &str) -> Option<&ContentLine> {
+        self.get_properties("ATTENDEE")
+            .find(|line| same_address(&line.value, address))
+    }
+
+    /// all direct sub objects of the given type
+    pub fn get_sub_objects<'a>(
+        &'a self,
+        object_type: &'a str,
+    ) -> impl Iterator<Item = &'a ICalObject> {
+        self.sub_objects
+            .iter()
+            .filter(move |object| object.is_type(object_type))
+    }
+
+    /// [ICalObject::child_order] with invalid entries removed and
+    /// missing children appended, lists every child exactly once
+    pub fn effective_child_order(&self) -> Vec<Child> {
+        let mut seen_properties = vec![false; self.properties.len()];
+        let mut seen_sub_objects = vec![false; self.sub_objects.len()];
+        let mut order = Vec::with_capacity(self.properties.len() + self.sub_objects.len());
+        for child in &self.child_order {
+            let seen = match *child {
+                Child::Property(i) => seen_properties.get_mut(i),
+                Child::SubObject(i) => seen_sub_objects.get_mut(i),
+            };
+            if let Some(seen @ false) = seen {
+                *seen = true;
+                order.push(*child);
+            }
+        }
+        for (i, seen) in seen_properties.into_iter().enumerate() {
+            if !seen {
+                order.push(Child::Property(i));
+            }
+        }
+        for (i, seen) in seen_sub_objects.into_iter().enumerate() {
+            if !seen {
+                order.push(Child::SubObject(i));
+            }
+        }
+        order
+    }
+
+    /// properties and sub objects in their (effective) original order
+    pub fn children(&self) -> Children<'_> {
+        if self.is_ordered() {
+            let (properties, sub_objects) =
+                self.child_order
+                    .iter()
+                    .fold((0, 0), |(p, s), child| match child {
+                        Child::Property(_) => (p + 1, s),
+                        Child::SubObject(_) => (p, s + 1),
+                    });
+            Children {
+                object: self,
+                order: Cow::Borrowed(&self.child_order),
+                position: 0,
+                properties,
+                sub_objects,
+            }
+        } else {
+            Children {
+                object: self,
+                order: Cow::Owned(self.effective_child_order()),
+                position: 0,
+                properties: self.properties.len(),
+                sub_objects: self.sub_objects.len(),
+            }
+        }
+    }
+
+    // whether the order lists the first properties and sub objects in turn,
+    // as the parser and the methods below keep it, the rest follows in order
+    fn is_ordered(&self) -> bool {
+        let (mut properties, mut sub_objects) = (0, 0);
+        for child in &self.child_order {
+            let (next, len) = match child {
+                Child::Property(i) => (i, &mut properties),
+                Child::SubObject(i) => (i, &mut sub_objects),
+            };
+            if *next != *len {
+                return false;
+            }
+            *len += 1;
+        }
+        properties <= self.properties.len() && sub_objects <= self.sub_objects.len()
+    }
+
+    /// appends a property after all existing children
+    pub fn push_property(&mut self, line: ContentLine) {
+        self.child_order = self.effective_child_order();
+        self.child_order
+            .push(Child::Property(self.properties.len()));
+        self.properties.push(line);
+    }
+
+    /// appends a sub object after all existing children
+    pub fn push_sub_object(&mut self, object: ICalObject) {
+        self.child_order = self.effective_child_order();
+        self.child_order
+            .push(Child::SubObject(self.sub_objects.len()));
+        self.sub_objects.push(object);
+    }
+
+    /// replaces the first property with the same name and removes the others,
+    /// or appends it if there is none
+    pub fn set_property(&mut self, line: ContentLine) {
+        let mut indices = self
+            .properties
+            .iter()
+            .enumerate()
+            .filter(|(_, existing)| existing.name_eq(&line.name))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>()
+            .into_iter();
+        match indices.next() {
+            Some(first) => {
+                self.properties[first] = line;
+                for i in indices.rev() {
+                    self.remove_property(i);
+                }
+            }
+            None => self.push_property(line),
+        }
+    }
+
+    /// removes a property and keeps the order of the remaining children
+    pub fn remove_property(&mut self, index: usize) -> ContentLine {
+        let line = self.properties.remove(index);
+        self.child_order = self
+            .child_order
+            .iter()
+            .filter_map(|child| match *child {
+                Child::Property(i) if i == index => None,
+                Child::Property(i) if i > index => Some(Child::Property(i - 1)),
+                child => Some(child),
+            })
+            .collect();
+        line
+    }
+
+    /// removes a sub object and keeps the order of the remaining children
+    pub fn remove_sub_object(&mut self, index: usize) -> ICalObject {
+        let object = self.sub_objects.remove(index);
+        self.child_order = self
+            .child_order
+            .iter()
+            .filter_map(|child| match *child {
+                Child::SubObject(i) if i == index => None,
+                Child::SubObject(i) if i > index => Some(Child::SubObject(i - 1)),
+                child => Some(child),
+            })
+            .collect();
+        object
+    }
+
+    /// uppercases the object type and all property and param names, recursively
+    pub fn normalize_case(&mut self) {
+        self.object_type.make_ascii_uppercase();
+        for line in &mut self.properties {
+            line.normalize_case();
+        }
+        for object in &mut self.sub_objects {
+            object.normalize_case();
+        }
+    }
+
+    /// serializes like [Display], but with all names uppercased
+    pub fn to_canonical_case_string(&self) -> String {
+        let mut object = self.clone();
+        object.normalize_case();
+        object.to_string()
     }
 }
 
@@ -78,29 +398,188 @@ impl FromStr for ICalObject {
 
 impl ICalObject {
     pub fn from_bufread(read: &mut impl BufRead) -> Result<Self> {
-        let mut unfolded =
-            Unfold::new(read).flat_map(|line| line.map(|line| line.parse::<ContentLine>()));
-        ICalObject::from_iterator(&mut unfolded)
+        ICalObject::from_bufread_with_options(read, &ParseOptions::default())
+    }
+
+    pub fn from_bufread_with_options(
+        read: &mut impl BufRead,
+        options: &ParseOptions,
+    ) -> Result<Self> {
+        // unfold errors are content line errors too, they must not be skipped
+        let mut unfolded = Unfold::new(read)
+            .require_trailing_crlf(options.require_trailing_crlf)
+            .map(|line| line.and_then(|line| line.parse::<ContentLine>()));
+        let mut object = ICalObject::from_iterator(&mut unfolded)?;
+        if options.normalize_case {
+            object.normalize_case();
+        }
+        Ok(object)
     }
 }
 
-impl Display for ICalObject {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}\r\n", &fold(&format!("BEGIN:{}", self.object_type)))?;
-        for line in &self.properties {
-            write!(f, "{}\r\n", fold(&line.to_string()))?;
+impl ICalObject {
+    /// like [Display], but folds with the given options
+    pub fn display_with<'a>(&'a self, fold_options: &'a FoldOptions) -> DisplayWith<'a> {
+        DisplayWith {
+            object: self,
+            fold_options,
         }
-        for object in &self.sub_objects {
-            write!(f, "{}", object)?;
+    }
+}
+
+/// see [ICalObject::display_with]
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayWith<'a> {
+    object: &'a ICalObject,
+    fold_options: &'a FoldOptions,
+}
+
+impl Display for DisplayWith<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fold = |line: &str| fold_with_options(line, self.fold_options);
+        write!(
+            f,
+            "{}\r\n",
+            &fold(&format!("BEGIN:{}", self.object.object_type))
+        )?;
+        for child in self.object.children() {
+            match child {
+                ChildRef::Property(line) => write!(f, "{}\r\n", fold(&line.to_string()))?,
+                ChildRef::SubObject(object) => {
+                    write!(f, "{}", object.display_with(self.fold_options))?
+                }
+            }
         }
-        write!(f, "{}\r\n", &fold(&format!("END:{}", self.object_type)))
+        write!(
+            f,
+            "{}\r\n",
+            &fold(&format!("END:{}", self.object.object_type))
+        )
+    }
+}
+
+impl Display for ICalObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display_with(&FoldOptions::default()))
     }
 }
 
 // tests
 #[cfg(test)]
 mod tests {
-    use super::ICalObject;
+    use super::{Child, ICalObject, ParseOptions};
+
+    #[test]
+    fn names_are_case_insensitive() {
+        let input = "begin:vcalendar\r\nBegin:VEvent\r\nDtStart;Value=DATE:20220101\r\nsummary:Mixed Case\r\nEND:vevent\r\nend:VCALENDAR\r\n";
+        let ical: ICalObject = input.parse().unwrap();
+        assert!(ical.is_type("VCALENDAR"));
+        let event = ical.get_sub_objects("VEVENT").next().unwrap();
+        let dtstart = event.get_property("DTSTART").unwrap();
+        assert_eq!(dtstart.param("VALUE").unwrap().values(), ["DATE"]);
+        // the parser preserves the original case by default
+        assert_eq!(dtstart.name, "DtStart");
+
+        let normalized = ICalObject::from_bufread_with_options(
+            &mut input.as_bytes(),
+            &ParseOptions {
+                normalize_case: true,
+                ..ParseOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(normalized.sub_objects[0].properties[0].name, "DTSTART");
+        assert_eq!(
+            normalized.to_string(),
+            "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART;VALUE=DATE:20220101\r\nSUMMARY:Mixed Case\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n"
+        );
+        assert_eq!(ical.to_canonical_case_string(), normalized.to_string());
+    }
+
+    #[test]
+    fn preserves_the_order_of_children() {
+        let input = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:1@example.com\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT15M\r
+END:VALARM\r
+SUMMARY:after the alarm\r
+END:VEVENT\r
+VERSION:2.0\r
+END:VCALENDAR\r
+";
+        let mut ical: ICalObject = input.parse().unwrap();
+        assert_eq!(ical.to_string(), input);
+        let event = &ical.sub_objects[0];
+        assert_eq!(
+            event.effective_child_order(),
+            [Child::Property(0), Child::SubObject(0), Child::Property(1)]
+        );
+
+        // without an explicit order, properties come first
+        let mut unordered = ical.clone();
+        unordered.sub_objects[0].child_order.clear();
+        assert_ne!(unordered, ical);
+        assert!(unordered
+            .to_string()
+            .contains("SUMMARY:after the alarm\r\nBEGIN:VALARM"));
+
+        let event = &mut ical.sub_objects[0];
+        let uid = event.remove_property(0);
+        event.push_property(uid);
+        assert!(ical
+            .to_string()
+            .contains("END:VALARM\r\nSUMMARY:after the alarm\r\nUID:1@example.com\r\n"));
+
+        // a set property takes the place of the first one with its name
+        let event = &mut ical.sub_objects[0];
+        event.push_property("SUMMARY:duplicate".parse().unwrap());
+        event.set_property("SUMMARY:replaced".parse().unwrap());
+        assert!(ical
+            .to_string()
+            .contains("END:VALARM\r\nSUMMARY:replaced\r\nUID:1@example.com\r\nEND:VEVENT"));
+
+        // children added to the fields directly follow the ordered ones
+        ical.sub_objects[0]
+            .properties
+            .push("COMMENT:added".parse().unwrap());
+        assert!(ical
+            .to_string()
+            .contains("UID:1@example.com\r\nCOMMENT:added\r\nEND:VEVENT\r\n"));
+    }
+
+    #[test]
+    fn requires_end() {
+        let ical: ICalObject = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nEND:VEVENT\r\nEND:VCALENDAR"
+            .parse()
+            .unwrap();
+        assert_eq!(ical.sub_objects.len(), 1);
+        let options = ParseOptions {
+            require_trailing_crlf: true,
+            ..ParseOptions::default()
+        };
+        assert!(ICalObject::from_bufread_with_options(
+            &mut "BEGIN:VCALENDAR\r\nEND:VCALENDAR".as_bytes(),
+            &options
+        )
+        .is_err());
+        // unterminated components
+        assert!("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nEND:VEVENT\r\n"
+            .parse::<ICalObject>()
+            .is_err());
+        assert!("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nEND:VCALENDAR\r\n"
+            .parse::<ICalObject>()
+            .is_err());
+        // trailing garbage after the root END
+        assert!("BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\nX-GARBAGE:1\r\n"
+            .parse::<ICalObject>()
+            .is_err());
+        assert!("BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\ngarbage\r\n"
+            .parse::<ICalObject>()
+            .is_err());
+    }
 
     #[test]
     fn it_works_on_all_private_test_icals() {
@@ -125,4 +604,54 @@ mod tests {
             }
         }
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_shape() {
+        let ical: ICalObject = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+END:VEVENT\r
+DTSTART;TZID=Europe/Berlin:20220101T090000\r
+END:VCALENDAR\r
+"
+        .parse()
+        .unwrap();
+        let json = serde_json::to_value(&ical).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "object_type": "VCALENDAR",
+                "properties": [{
+                    "name": "DTSTART",
+                    "params": [{"name": "TZID", "values": ["Europe/Berlin"]}],
+                    "value": "20220101T090000"
+                }],
+                "sub_objects": [{
+                    "object_type": "VEVENT",
+                    "properties": [],
+                    "sub_objects": [],
+                    "child_order": []
+                }],
+                "child_order": [{"sub_object": 0}, {"property": 0}]
+            })
+        );
+        let back: ICalObject = serde_json::from_value(json).unwrap();
+        assert_eq!(back, ical);
+        assert_eq!(
+            serde_json::to_value(Child::SubObject(1)).unwrap(),
+            serde_json::json!({"sub_object": 1})
+        );
+        // the order may be left out
+        let unordered: ICalObject = serde_json::from_value(serde_json::json!({
+            "object_type": "VEVENT",
+            "properties": [],
+            "sub_objects": []
+        }))
+        .unwrap();
+        assert_eq!(unordered.children().count(), 0);
+
+        // formats that are not self-describing need every field
+        let bytes = bincode::serialize(&ical).unwrap();
+        assert_eq!(bincode::deserialize::<ICalObject>(&bytes).unwrap(), ical);
+    }
 }
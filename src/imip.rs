@@ -0,0 +1,446 @@
+// iMIP, see RFC 6047
+//
+// to_mime wraps an iTIP message into a multipart/alternative body with a
+// text/plain part for mail clients without calendar support, extract finds the
+// calendars in all parts of a raw RFC 5322 message, MIME is only parsed as far
+// as needed for this, encoded words in headers are not decoded
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use eyre::{eyre, Result, WrapErr};
+
+use crate::{
+    ical_object::ICalObject,
+    vcard_legacy::{decode_charset, decode_quoted_printable},
+};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// multiparts and attached messages nested deeper than this are not searched
+const MAX_DEPTH: usize = 16;
+
+/// a calendar found in an email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarPart {
+    /// the uppercased method param of the Content-Type
+    pub method: Option<String>,
+    pub calendar: ICalObject,
+}
+
+/// the MIME headers and body of an email that carries an iTIP message,
+/// the caller adds From, To, Subject and the other RFC 5322 headers
+pub fn to_mime(message: &ICalObject, text: &str) -> Result<String> {
+    let method = message
+        .get_property("METHOD")
+        .ok_or_else(|| eyre!("an iMIP message needs a METHOD"))?
+        .value
+        .trim()
+        .to_ascii_uppercase();
+    let mut calendar_type = format!("text/calendar; charset=UTF-8; method={}", method);
+    if let Some(component) = message.sub_objects.iter().find(|o| !o.is_type("VTIMEZONE")) {
+        calendar_type.push_str("; component=");
+        calendar_type.push_str(&component.object_type.to_ascii_uppercase());
+    }
+    // folded lines are short enough for 7bit, unless they are not ASCII
+    let calendar = message.to_string();
+    let (calendar_encoding, calendar) = match calendar.is_ascii() {
+        true => ("7bit", calendar),
+        false => ("base64", encode_base64(calendar.as_bytes())),
+    };
+    let text = encode_quoted_printable(text);
+    let boundary = boundary(&[&text, &calendar]);
+    let mut out = format!(
+        "MIME-Version: 1.0\r\nContent-Type: multipart/alternative; boundary=\"{}\"\r\n\r\n",
+        boundary
+    );
+    for (content_type, encoding, body) in [
+        ("text/plain; charset=UTF-8", "quoted-printable", text),
+        (calendar_type.as_str(), calendar_encoding, calendar),
+    ] {
+        out.push_str(&format!(
+            "--{}\r\nContent-Type: {}\r\nContent-Transfer-Encoding: {}\r\n\r\n{}",
+            boundary, content_type, encoding, body
+        ));
+        // the line break before a boundary belongs to the boundary
+        out.push_str("\r\n");
+    }
+    out.push_str(&format!("--{}--\r\n", boundary));
+    Ok(out)
+}
+
+// a boundary that does not occur in the parts
+fn boundary(parts: &[&str]) -> String {
+    let mut hasher = DefaultHasher::new();
+    parts.hash(&mut hasher);
+    let mut seed = hasher.finish();
+    loop {
+        let boundary = format!("=_ical_{:016x}", seed);
+        if !parts.iter().any(|part| part.contains(&boundary)) {
+            return boundary;
+        }
+        seed = seed.wrapping_add(1);
+    }
+}
+
+// quoted-printable with soft line breaks after at most 76 characters,
+// see RFC 2045 section 6.7, line breaks become CRLF
+fn encode_quoted_printable(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push_str("\r\n");
+        }
+        let line = line.strip_suffix('\r').unwrap_or(line).as_bytes();
+        let mut length = 0;
+        for (j, &byte) in line.iter().enumerate() {
+            let is_last = j + 1 == line.len();
+            let encoded = match byte {
+                b' ' | b'\t' if is_last => format!("={:02X}", byte),
+                b'=' => format!("={:02X}", byte),
+                b' ' | b'\t' | 33..=126 => (byte as char).to_string(),
+                _ => format!("={:02X}", byte),
+            };
+            if length + encoded.len() > 75 {
+                out.push_str("=\r\n");
+                length = 0;
+            }
+            length += encoded.len();
+            out.push_str(&encoded);
+        }
+    }
+    out
+}
+
+// base64 with lines of 76 characters
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 4 / 3 + bytes.len() / 38 + 4);
+    for (i, chunk) in bytes.chunks(3).enumerate() {
+        if i > 0 && i % 19 == 0 {
+            out.push_str("\r\n");
+        }
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (j, &byte)| {
+            bits | (byte as u32) << (16 - 8 * j)
+        });
+        for j in 0..4 {
+            match j <= chunk.len() {
+                true => out.push(BASE64[(bits >> (18 - 6 * j)) as usize & 63] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+fn decode_base64(text: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for &c in text {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            c => return Err(eyre!("invalid base64 character {:?}", c as char)),
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    Ok(out)
+}
+
+/// extracts and parses the text/calendar and application/ics parts of a raw
+/// RFC 5322 message, including those of attached messages, a part that can
+/// not be decoded or parsed is an error that does not affect the others
+pub fn extract(raw: &[u8]) -> Vec<Result<CalendarPart>> {
+    let mut parts = Vec::new();
+    collect(raw, 0, &mut parts);
+    parts
+}
+
+fn collect(raw: &[u8], depth: usize, parts: &mut Vec<Result<CalendarPart>>) {
+    let (headers, body) = split_entity(raw);
+    let (media_type, params) = header(&headers, "Content-Type")
+        .map(content_type)
+        .unwrap_or_else(|| ("text/plain".to_string(), Vec::new()));
+    let is_nested = media_type.starts_with("multipart/") || media_type == "message/rfc822";
+    if is_nested && depth >= MAX_DEPTH {
+        parts.push(Err(eyre!(
+            "{} nested deeper than {} levels",
+            media_type,
+            MAX_DEPTH
+        )));
+        return;
+    }
+    if media_type.starts_with("multipart/") {
+        match param(&params, "boundary") {
+            Some(boundary) => {
+                for part in split_multipart(body, boundary) {
+                    collect(part, depth + 1, parts);
+                }
+            }
+            None => parts.push(Err(eyre!("{} without a boundary", media_type))),
+        }
+        return;
+    }
+    // only the parts that may contain a calendar are decoded
+    if !matches!(
+        media_type.as_str(),
+        "message/rfc822" | "text/calendar" | "application/ics"
+    ) {
+        return;
+    }
+    let body = match header(&headers, "Content-Transfer-Encoding")
+        .map(|encoding| encoding.trim().to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("base64") => match decode_base64(body) {
+            Ok(body) => body,
+            Err(error) => {
+                parts.push(Err(error.wrap_err(format!("invalid {} part", media_type))));
+                return;
+            }
+        },
+        Some("quoted-printable") => {
+            let text = String::from_utf8_lossy(body);
+            decode_quoted_printable(&text.replace("=\r\n", "").replace("=\n", ""))
+        }
+        _ => body.to_vec(),
+    };
+    if media_type == "message/rfc822" {
+        collect(&body, depth + 1, parts);
+    } else {
+        parts.push(calendar_part(&body, &media_type, &params));
+    }
+}
+
+fn calendar_part(
+    body: &[u8],
+    media_type: &str,
+    params: &[(String, String)],
+) -> Result<CalendarPart> {
+    let text = decode_charset(body, param(params, "charset").unwrap_or("UTF-8"))?;
+    // mail transport may change CRLF to LF
+    let text = text.trim_end().replace("\r\n", "\n").replace('\n', "\r\n") + "\r\n";
+    let calendar = text
+        .parse()
+        .wrap_err_with(|| format!("invalid {} part", media_type))?;
+    Ok(CalendarPart {
+        method: param(params, "method").map(str::to_ascii_uppercase),
+        calendar,
+    })
+}
+
+// the unfolded headers and the body of a message or body part
+fn split_entity(raw: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut offset = 0;
+    for line in raw.split_inclusive(|&byte| byte == b'\n') {
+        offset += line.len();
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            return (headers, &raw[offset..]);
+        }
+        match (line.starts_with([' ', '\t']), headers.last_mut()) {
+            (true, Some((_, value))) => value.push_str(line),
+            _ => {
+                let (name, value) = line.split_once(':').unwrap_or((line, ""));
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+    }
+    (headers, &[])
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+// the lowercased media type and the params of a Content-Type,
+// param names are lowercased and quotes removed
+fn content_type(value: &str) -> (String, Vec<(String, String)>) {
+    let mut fields = Vec::new();
+    let (mut field, mut in_quotes) = (String::new(), false);
+    for c in value.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    let media_type = fields[0].trim().to_ascii_lowercase();
+    let params = fields[1..]
+        .iter()
+        .filter_map(|field| field.split_once('='))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    (media_type, params)
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(param, _)| param == name)
+        .map(|(_, value)| value.as_str())
+}
+
+// the body parts between the delimiter lines, see RFC 2046 section 5.1.1
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let close_delimiter = format!("{}--", delimiter);
+    let mut parts = Vec::new();
+    let (mut start, mut offset) = (None, 0);
+    for line in body.split_inclusive(|&byte| byte == b'\n') {
+        let content = String::from_utf8_lossy(line);
+        let content = content.trim_end();
+        let is_close = content == close_delimiter;
+        if content == delimiter || is_close {
+            if let Some(start) = start {
+                // the line break before a delimiter belongs to it
+                let part = &body[start..offset];
+                let part = part.strip_suffix(b"\n").unwrap_or(part);
+                parts.push(part.strip_suffix(b"\r").unwrap_or(part));
+            }
+            if is_close {
+                return parts;
+            }
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    // without a close delimiter the last part runs to the end
+    parts.extend(start.map(|start| &body[start..]));
+    parts
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_and_extracts_messages() {
+        let message: ICalObject = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//elikoga-ical-rs//EN\r
+METHOD:REQUEST\r
+BEGIN:VEVENT\r
+UID:planning@example.com\r
+DTSTAMP:20240110T120000Z\r
+DTSTART:20240115T090000Z\r
+SUMMARY:Café planning\r
+ORGANIZER:mailto:olga@example.com\r
+ATTENDEE:mailto:ana@example.com\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        .parse()
+        .unwrap();
+        let mime = to_mime(&message, "Olga invites you to Café planning.\n").unwrap();
+        assert!(mime.contains(
+            "Content-Type: text/calendar; charset=UTF-8; method=REQUEST; component=VEVENT\r\n\
+             Content-Transfer-Encoding: base64\r\n"
+        ));
+        assert!(mime.contains("Olga invites you to Caf=C3=A9 planning.\r\n"));
+        let email = format!("From: olga@example.com\r\nSubject: Planning\r\n{}", mime);
+        assert_eq!(
+            extract(email.as_bytes())
+                .into_iter()
+                .collect::<Result<Vec<_>>>()
+                .unwrap(),
+            [CalendarPart {
+                method: Some("REQUEST".to_string()),
+                calendar: message,
+            }]
+        );
+        assert_eq!(
+            encode_quoted_printable(&format!("{} \n", "x".repeat(80))),
+            format!("{}=\r\n{}=20\r\n", "x".repeat(75), "x".repeat(5))
+        );
+    }
+
+    #[test]
+    fn extracts_calendars_from_fixtures() {
+        let extract = |raw: &[u8]| {
+            extract(raw)
+                .into_iter()
+                .collect::<Result<Vec<_>>>()
+                .unwrap()
+        };
+        let parts = extract(include_bytes!("../test-fixtures/imip/request.eml"));
+        let summaries: Vec<_> = parts
+            .iter()
+            .map(|part| {
+                let event = part.calendar.get_sub_objects("VEVENT").next().unwrap();
+                (
+                    part.method.as_deref(),
+                    event.get_property("SUMMARY").unwrap().value.as_str(),
+                )
+            })
+            .collect();
+        // the invitation and the same invitation attached as a file
+        assert_eq!(
+            summaries,
+            [
+                (Some("REQUEST"), "Quarterly review"),
+                (None, "Quarterly review")
+            ]
+        );
+
+        let parts = extract(include_bytes!("../test-fixtures/imip/reply.eml"));
+        let event = parts[0].calendar.get_sub_objects("VEVENT").next().unwrap();
+        assert_eq!(parts[0].method.as_deref(), Some("REPLY"));
+        assert_eq!(
+            event.get_property("COMMENT").unwrap().value,
+            "Désolé, I am travelling that week and can not make it to the review"
+        );
+
+        let parts = extract(include_bytes!("../test-fixtures/imip/forwarded.eml"));
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].method.as_deref(), Some("CANCEL"));
+    }
+
+    #[test]
+    fn keeps_the_valid_parts() {
+        let calendar = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nEND:VCALENDAR\r\n";
+        let email = format!(
+            "Content-Type: multipart/mixed; boundary=b\r\n\r\n\
+             --b\r\nContent-Type: application/pdf\r\nContent-Transfer-Encoding: base64\r\n\r\n\
+             not base64!\r\n\
+             --b\r\nContent-Type: text/calendar; charset=x-unknown\r\n\r\n{0}\r\n\
+             --b\r\nContent-Type: text/calendar; method=PUBLISH\r\n\r\n{0}\r\n\
+             --b--\r\n",
+            calendar
+        );
+        let parts = extract(email.as_bytes());
+        assert_eq!(parts.len(), 2);
+        assert!(parts[0].is_err());
+        assert_eq!(
+            parts[1].as_ref().unwrap().method.as_deref(),
+            Some("PUBLISH")
+        );
+
+        let mut nested = format!("Content-Type: text/calendar\r\n\r\n{}", calendar);
+        for _ in 0..100 {
+            nested = format!("Content-Type: message/rfc822\r\n\r\n{}", nested);
+        }
+        let parts = extract(nested.as_bytes());
+        assert_eq!(parts.len(), 1);
+        assert!(parts[0].is_err());
+    }
+}
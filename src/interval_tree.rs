@@ -0,0 +1,140 @@
+// a static interval tree: the intervals sorted by start form an implicit
+// balanced binary tree, the middle of a range is the root of its subtree,
+// and each node knows the latest end in its subtree to skip whole subtrees
+
+use crate::datetime::DateTime;
+
+/// intervals from start to end, end excluded, with a value each
+#[derive(Debug, Clone)]
+pub struct IntervalTree<T> {
+    intervals: Vec<(DateTime, DateTime, T)>,
+    // the latest end in the subtree of each node
+    max_end: Vec<DateTime>,
+}
+
+impl<T> IntervalTree<T> {
+    pub fn new(mut intervals: Vec<(DateTime, DateTime, T)>) -> Self {
+        intervals.sort_by_key(|(start, end, _)| (*start, *end));
+        let mut max_end: Vec<DateTime> = intervals.iter().map(|(_, end, _)| *end).collect();
+        fill_max_end(&mut max_end, 0, intervals.len());
+        IntervalTree { intervals, max_end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// all intervals, sorted by start
+    pub fn iter(&self) -> impl Iterator<Item = &(DateTime, DateTime, T)> {
+        self.intervals.iter()
+    }
+
+    /// the intervals that overlap the one from start to end, sorted by start,
+    /// an interval without length overlaps if it is at start or within
+    pub fn query(&self, start: DateTime, end: DateTime) -> Vec<&(DateTime, DateTime, T)> {
+        let mut found = Vec::new();
+        self.search(0, self.intervals.len(), start, end, &mut found);
+        found
+    }
+
+    fn search<'a>(
+        &'a self,
+        low: usize,
+        high: usize,
+        start: DateTime,
+        end: DateTime,
+        found: &mut Vec<&'a (DateTime, DateTime, T)>,
+    ) {
+        if low >= high {
+            return;
+        }
+        let middle = low + (high - low) / 2;
+        // nothing below ends late enough
+        if self.max_end[middle] < start {
+            return;
+        }
+        self.search(low, middle, start, end, found);
+        let interval = &self.intervals[middle];
+        // the rest starts too late
+        if interval.0 >= end {
+            return;
+        }
+        if interval.1 > start || (interval.0 == interval.1 && interval.0 >= start) {
+            found.push(interval);
+        }
+        self.search(middle + 1, high, start, end, found);
+    }
+}
+
+// sets each node to the latest end of its subtree, returns that of the range
+fn fill_max_end(max_end: &mut [DateTime], low: usize, high: usize) -> Option<DateTime> {
+    if low >= high {
+        return None;
+    }
+    let middle = low + (high - low) / 2;
+    let left = fill_max_end(max_end, low, middle);
+    let right = fill_max_end(max_end, middle + 1, high);
+    let latest = [left, right]
+        .into_iter()
+        .flatten()
+        .fold(max_end[middle], DateTime::max);
+    max_end[middle] = latest;
+    Some(latest)
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_overlapping_intervals() {
+        let hour = |hour: i64| DateTime::new(2024, 1, 1, 0, 0, 0).add_seconds(hour * 3600);
+        let tree = IntervalTree::new(vec![
+            (hour(5), hour(6), 'c'),
+            (hour(0), hour(10), 'a'),
+            (hour(2), hour(3), 'b'),
+            (hour(7), hour(7), 'd'),
+            (hour(11), hour(12), 'e'),
+        ]);
+        let query = |start, end| {
+            tree.query(hour(start), hour(end))
+                .into_iter()
+                .map(|(_, _, value)| *value)
+                .collect::<String>()
+        };
+        assert_eq!(query(3, 5), "a");
+        assert_eq!(query(5, 8), "acd");
+        assert_eq!(query(7, 8), "ad");
+        assert_eq!(query(10, 11), "");
+        assert_eq!(query(0, 24), "abcde");
+
+        // the same as comparing every interval
+        let intervals: Vec<_> = (0..200)
+            .map(|i: i64| (hour(i * 7 % 100), hour(i * 7 % 100 + i % 13), i))
+            .collect();
+        let tree = IntervalTree::new(intervals.clone());
+        for start in 0..110 {
+            let mut expected: Vec<i64> = intervals
+                .iter()
+                .filter(|(from, to, _)| {
+                    *from < hour(start + 3)
+                        && (*to > hour(start) || (from == to && *from >= hour(start)))
+                })
+                .map(|(_, _, i)| *i)
+                .collect();
+            let mut found: Vec<i64> = tree
+                .query(hour(start), hour(start + 3))
+                .into_iter()
+                .map(|(_, _, i)| *i)
+                .collect();
+            expected.sort_unstable();
+            found.sort_unstable();
+            assert_eq!(found, expected);
+        }
+    }
+}
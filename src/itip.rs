@@ -0,0 +1,1229 @@
+// iTIP, see RFC 5546
+//
+// messages are built from the VCALENDAR of one event, its master VEVENT and
+// overridden instances, the VEVENTs of a message only keep the properties
+// that the restriction tables of section 3.2 allow for its METHOD,
+// DTSTAMP is the time the message is built and SEQUENCE follows section 2.1.4,
+// process applies the answers of the other side to the stored calendar
+
+use std::fmt::Display;
+
+use eyre::{eyre, Result};
+
+use crate::{
+    content_line::{ContentLine, Param},
+    datetime::DateTime,
+    ical_object::{ChildRef, ICalObject},
+    value::{escape_text, same_address},
+};
+
+/// the iTIP methods for VEVENTs, except PUBLISH
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    Request,
+    Reply,
+    Add,
+    Cancel,
+    Refresh,
+    Counter,
+    DeclineCounter,
+}
+
+impl Method {
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Request => "REQUEST",
+            Method::Reply => "REPLY",
+            Method::Add => "ADD",
+            Method::Cancel => "CANCEL",
+            Method::Refresh => "REFRESH",
+            Method::Counter => "COUNTER",
+            Method::DeclineCounter => "DECLINECOUNTER",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Method> {
+        [
+            Method::Request,
+            Method::Reply,
+            Method::Add,
+            Method::Cancel,
+            Method::Refresh,
+            Method::Counter,
+            Method::DeclineCounter,
+        ]
+        .into_iter()
+        .find(|method| method.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+// how often a property may appear in a VEVENT of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Count {
+    One,
+    OneOrMore,
+    Optional,
+    Any,
+}
+
+// the VEVENT properties of RFC 5545, those not listed in the table of a method
+// must not appear in its messages, other properties are always allowed
+const PROPERTIES: [&str; 31] = [
+    "ATTACH",
+    "ATTENDEE",
+    "CATEGORIES",
+    "CLASS",
+    "COMMENT",
+    "CONTACT",
+    "CREATED",
+    "DESCRIPTION",
+    "DTEND",
+    "DTSTAMP",
+    "DTSTART",
+    "DURATION",
+    "EXDATE",
+    "EXRULE",
+    "GEO",
+    "LAST-MODIFIED",
+    "LOCATION",
+    "ORGANIZER",
+    "PRIORITY",
+    "RDATE",
+    "RECURRENCE-ID",
+    "RELATED-TO",
+    "REQUEST-STATUS",
+    "RESOURCES",
+    "RRULE",
+    "SEQUENCE",
+    "STATUS",
+    "SUMMARY",
+    "TRANSP",
+    "UID",
+    "URL",
+];
+
+// properties whose change reschedules an event and bumps its SEQUENCE
+const SIGNIFICANT: [&str; 7] = [
+    "DTSTART", "DTEND", "DURATION", "DUE", "RRULE", "RDATE", "EXDATE",
+];
+
+// the tables of RFC 5546 section 3.2 for VEVENT, and whether VALARMs are allowed
+fn restrictions(method: Method) -> (&'static [(&'static str, Count)], bool) {
+    use Count::*;
+    match method {
+        Method::Request => (
+            &[
+                ("ATTACH", Any),
+                ("ATTENDEE", OneOrMore),
+                ("CATEGORIES", Any),
+                ("CLASS", Optional),
+                ("COMMENT", Any),
+                ("CONTACT", Any),
+                ("CREATED", Optional),
+                ("DESCRIPTION", Optional),
+                ("DTEND", Optional),
+                ("DTSTAMP", One),
+                ("DTSTART", One),
+                ("DURATION", Optional),
+                ("EXDATE", Any),
+                ("GEO", Optional),
+                ("LAST-MODIFIED", Optional),
+                ("LOCATION", Optional),
+                ("ORGANIZER", One),
+                ("PRIORITY", Optional),
+                ("RDATE", Any),
+                ("RECURRENCE-ID", Optional),
+                ("RELATED-TO", Any),
+                ("RESOURCES", Any),
+                ("RRULE", Any),
+                ("SEQUENCE", Optional),
+                ("STATUS", Optional),
+                ("SUMMARY", One),
+                ("TRANSP", Optional),
+                ("UID", One),
+                ("URL", Optional),
+            ],
+            true,
+        ),
+        Method::Reply => (
+            &[
+                ("ATTACH", Any),
+                ("ATTENDEE", One),
+                ("CATEGORIES", Any),
+                ("CLASS", Optional),
+                ("COMMENT", Any),
+                ("CONTACT", Any),
+                ("CREATED", Optional),
+                ("DESCRIPTION", Optional),
+                ("DTEND", Optional),
+                ("DTSTAMP", One),
+                ("DTSTART", Optional),
+                ("DURATION", Optional),
+                ("EXDATE", Any),
+                ("GEO", Optional),
+                ("LAST-MODIFIED", Optional),
+                ("LOCATION", Optional),
+                ("ORGANIZER", One),
+                ("PRIORITY", Optional),
+                ("RDATE", Any),
+                ("RECURRENCE-ID", Optional),
+                ("RELATED-TO", Any),
+                ("REQUEST-STATUS", Any),
+                ("RESOURCES", Any),
+                ("RRULE", Any),
+                ("SEQUENCE", Optional),
+                ("STATUS", Optional),
+                ("SUMMARY", Optional),
+                ("TRANSP", Optional),
+                ("UID", One),
+                ("URL", Optional),
+            ],
+            false,
+        ),
+        Method::Add => (
+            &[
+                ("ATTACH", Any),
+                ("ATTENDEE", Any),
+                ("CATEGORIES", Any),
+                ("CLASS", Optional),
+                ("COMMENT", Any),
+                ("CONTACT", Any),
+                ("CREATED", Optional),
+                ("DESCRIPTION", Optional),
+                ("DTEND", Optional),
+                ("DTSTAMP", One),
+                ("DTSTART", One),
+                ("DURATION", Optional),
+                ("GEO", Optional),
+                ("LAST-MODIFIED", Optional),
+                ("LOCATION", Optional),
+                ("ORGANIZER", One),
+                ("PRIORITY", Optional),
+                ("RDATE", Any),
+                ("RELATED-TO", Any),
+                ("RESOURCES", Any),
+                ("SEQUENCE", One),
+                ("STATUS", Optional),
+                ("SUMMARY", One),
+                ("TRANSP", Optional),
+                ("UID", One),
+                ("URL", Optional),
+            ],
+            true,
+        ),
+        Method::Cancel => (
+            &[
+                ("ATTACH", Any),
+                ("ATTENDEE", Any),
+                ("CATEGORIES", Any),
+                ("CLASS", Optional),
+                ("COMMENT", Any),
+                ("CONTACT", Any),
+                ("CREATED", Optional),
+                ("DESCRIPTION", Optional),
+                ("DTEND", Optional),
+                ("DTSTAMP", One),
+                ("DTSTART", Optional),
+                ("DURATION", Optional),
+                ("EXDATE", Any),
+                ("GEO", Optional),
+                ("LAST-MODIFIED", Optional),
+                ("LOCATION", Optional),
+                ("ORGANIZER", One),
+                ("PRIORITY", Optional),
+                ("RDATE", Any),
+                ("RECURRENCE-ID", Optional),
+                ("RELATED-TO", Any),
+                ("RESOURCES", Any),
+                ("RRULE", Optional),
+                ("SEQUENCE", One),
+                ("STATUS", Optional),
+                ("SUMMARY", Optional),
+                ("TRANSP", Optional),
+                ("UID", One),
+                ("URL", Optional),
+            ],
+            false,
+        ),
+        Method::Refresh => (
+            &[
+                ("ATTENDEE", One),
+                ("COMMENT", Optional),
+                ("DTSTAMP", One),
+                ("ORGANIZER", One),
+                ("RECURRENCE-ID", Optional),
+                ("UID", One),
+            ],
+            false,
+        ),
+        Method::Counter => (
+            &[
+                ("ATTACH", Any),
+                ("ATTENDEE", Any),
+                ("CATEGORIES", Any),
+                ("CLASS", Optional),
+                ("COMMENT", Any),
+                ("CONTACT", Any),
+                ("CREATED", Optional),
+                ("DESCRIPTION", Optional),
+                ("DTEND", Optional),
+                ("DTSTAMP", One),
+                ("DTSTART", One),
+                ("DURATION", Optional),
+                ("EXDATE", Any),
+                ("GEO", Optional),
+                ("LAST-MODIFIED", Optional),
+                ("LOCATION", Optional),
+                ("ORGANIZER", One),
+                ("PRIORITY", Optional),
+                ("RDATE", Any),
+                ("RECURRENCE-ID", Optional),
+                ("RELATED-TO", Any),
+                ("REQUEST-STATUS", Any),
+                ("RESOURCES", Any),
+                ("RRULE", Any),
+                ("SEQUENCE", Optional),
+                ("STATUS", Optional),
+                ("SUMMARY", One),
+                ("TRANSP", Optional),
+                ("UID", One),
+                ("URL", Optional),
+            ],
+            true,
+        ),
+        Method::DeclineCounter => (
+            &[
+                ("ATTENDEE", Any),
+                ("COMMENT", Optional),
+                ("DTSTAMP", One),
+                ("ORGANIZER", One),
+                ("RECURRENCE-ID", Optional),
+                ("REQUEST-STATUS", Any),
+                ("SEQUENCE", Optional),
+                ("UID", One),
+            ],
+            false,
+        ),
+    }
+}
+
+fn allowed_count(method: Method, name: &str) -> Option<Count> {
+    let (table, _) = restrictions(method);
+    let is_listed = PROPERTIES
+        .iter()
+        .any(|known| known.eq_ignore_ascii_case(name));
+    if !is_listed {
+        return Some(Count::Any);
+    }
+    table
+        .iter()
+        .find(|(listed, _)| listed.eq_ignore_ascii_case(name))
+        .map(|&(_, count)| count)
+}
+
+/// checks a message against the restriction tables of RFC 5546 section 3.2
+pub fn validate(message: &ICalObject) -> Result<Method> {
+    check(message).map_err(|status| eyre!("{}", status.data.unwrap_or(status.description)))
+}
+
+// validate with a REQUEST-STATUS for each kind of error
+fn check(message: &ICalObject) -> std::result::Result<Method, RequestStatus> {
+    let error = |code: &str, data: String| Err(RequestStatus::new(code, Some(data)));
+    if !message.is_type("VCALENDAR") {
+        return error("3.13", "an iTIP message must be a VCALENDAR".to_string());
+    }
+    let Some(method) = message.get_property("METHOD") else {
+        return error("3.11", "missing METHOD".to_string());
+    };
+    let Some(method) = Method::from_name(&method.value) else {
+        return error("3.14", format!("unsupported METHOD {}", method.value));
+    };
+    let (_, allows_alarms) = restrictions(method);
+    let mut uid = None;
+    for component in &message.sub_objects {
+        if component.is_type("VTIMEZONE") {
+            continue;
+        }
+        if !component.is_type("VEVENT") {
+            return error(
+                "3.13",
+                format!(
+                    "{} with a {} is not supported",
+                    method.name(),
+                    component.object_type
+                ),
+            );
+        }
+        for name in PROPERTIES {
+            let found = component.get_properties(name).count();
+            let (is_valid, expected) = match allowed_count(method, name) {
+                None => (found == 0, "no"),
+                Some(Count::One) => (found == 1, "exactly one"),
+                Some(Count::OneOrMore) => (found >= 1, "at least one"),
+                Some(Count::Optional) => (found <= 1, "at most one"),
+                Some(Count::Any) => (true, "any"),
+            };
+            if !is_valid {
+                return error(
+                    if found == 0 { "3.11" } else { "3.13" },
+                    format!(
+                        "{} allows {} {} in a VEVENT, found {}",
+                        method.name(),
+                        expected,
+                        name,
+                        found
+                    ),
+                );
+            }
+        }
+        if component.get_property("DTEND").is_some() && component.get_property("DURATION").is_some()
+        {
+            return error(
+                "3.13",
+                "DTEND and DURATION are mutually exclusive".to_string(),
+            );
+        }
+        if !allows_alarms && component.get_sub_objects("VALARM").next().is_some() {
+            return error("3.13", format!("{} does not allow VALARMs", method.name()));
+        }
+        let component_uid = component.get_property("UID").map(|line| line.value.trim());
+        if uid.is_some_and(|uid| Some(uid) != component_uid) {
+            return error(
+                "3.1",
+                "all VEVENTs of a message must have the same UID".to_string(),
+            );
+        }
+        uid = component_uid;
+    }
+    if uid.is_none() {
+        return error("3.11", format!("{} without a VEVENT", method.name()));
+    }
+    Ok(method)
+}
+
+/// the SEQUENCE of an event, 0 if it has none
+pub fn sequence(event: &ICalObject) -> u32 {
+    event
+        .get_property("SEQUENCE")
+        .and_then(|line| line.value.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn recurrence_id(event: &ICalObject) -> Option<&str> {
+    event
+        .get_property("RECURRENCE-ID")
+        .map(|line| line.value.trim())
+}
+
+/// whether the change from previous to event reschedules it,
+/// see RFC 5546 section 2.1.4
+pub fn is_significant_change(previous: &ICalObject, event: &ICalObject) -> bool {
+    SIGNIFICANT.iter().any(|name| {
+        let mut before: Vec<String> = previous
+            .get_properties(name)
+            .map(|l| l.to_string())
+            .collect();
+        let mut after: Vec<String> = event.get_properties(name).map(|l| l.to_string()).collect();
+        before.sort();
+        after.sort();
+        before != after
+    })
+}
+
+fn events(calendar: &ICalObject) -> Result<Vec<&ICalObject>> {
+    let events: Vec<&ICalObject> = calendar.get_sub_objects("VEVENT").collect();
+    let uid = events
+        .first()
+        .and_then(|event| event.get_property("UID"))
+        .ok_or_else(|| eyre!("the calendar has no VEVENT with a UID"))?;
+    let same_uid = events.iter().all(|event| {
+        event
+            .get_property("UID")
+            .is_some_and(|line| line.value == uid.value)
+    });
+    if !same_uid {
+        return Err(eyre!("the calendar must contain a single event"));
+    }
+    Ok(events)
+}
+
+fn master(calendar: &ICalObject) -> Result<&ICalObject> {
+    events(calendar)?
+        .into_iter()
+        .find(|event| recurrence_id(event).is_none())
+        .ok_or_else(|| eyre!("the calendar has no master VEVENT"))
+}
+
+// the override of an instance, or a new one
+fn instance(calendar: &ICalObject, recurrence: &str) -> Result<ICalObject> {
+    let events = events(calendar)?;
+    match events
+        .iter()
+        .find(|event| recurrence_id(event) == Some(recurrence.trim()))
+    {
+        Some(event) => Ok((*event).clone()),
+        None => new_override(master(calendar)?, recurrence),
+    }
+}
+
+// a copy of the master for one instance, without its recurrence,
+// the RECURRENCE-ID and DTSTART are of the same type as the master's DTSTART
+fn new_override(master: &ICalObject, recurrence: &str) -> Result<ICalObject> {
+    let mut event = master.clone();
+    let start = master
+        .get_property("DTSTART")
+        .ok_or_else(|| eyre!("the master VEVENT has no DTSTART"))?;
+    let instance_start = DateTime::parse(recurrence.trim())?;
+    // DTEND keeps its distance to DTSTART
+    if let Some(end) = master.get_property("DTEND") {
+        let length = DateTime::parse(end.value.trim())?.timestamp()
+            - DateTime::parse(start.value.trim())?.timestamp();
+        let instance_end = instance_start.add_seconds(length);
+        let value = match (end.value.contains('T'), end.value.trim().ends_with('Z')) {
+            (false, _) => instance_end.to_date_string(),
+            (true, false) => instance_end.to_string(),
+            (true, true) => format!("{}Z", instance_end),
+        };
+        event.set_property(ContentLine::new(
+            end.name.clone(),
+            end.params.clone(),
+            value,
+        ));
+    }
+    for i in (0..event.properties.len()).rev() {
+        let name = event.properties[i].name.to_ascii_uppercase();
+        if ["EXDATE", "EXRULE", "RDATE", "RRULE"].contains(&name.as_str()) {
+            event.remove_property(i);
+        }
+    }
+    let line = |name: &str| {
+        ContentLine::new(
+            name.to_string(),
+            start.params.clone(),
+            recurrence.trim().to_string(),
+        )
+    };
+    event.set_property(line("DTSTART"));
+    event.set_property(line("RECURRENCE-ID"));
+    Ok(event)
+}
+
+// a copy of event without what method does not allow
+fn restrict(method: Method, event: &ICalObject) -> ICalObject {
+    let (_, allows_alarms) = restrictions(method);
+    let mut restricted = ICalObject {
+        object_type: event.object_type.clone(),
+        ..Default::default()
+    };
+    for child in event.children() {
+        match child {
+            ChildRef::Property(line) if allowed_count(method, &line.name).is_some() => {
+                restricted.push_property(line.clone())
+            }
+            ChildRef::SubObject(object) if allows_alarms || !object.is_type("VALARM") => {
+                restricted.push_sub_object(object.clone())
+            }
+            _ => {}
+        }
+    }
+    restricted
+}
+
+fn stamp(event: &mut ICalObject, sequence: u32, now: DateTime) {
+    if sequence > 0 || event.get_property("SEQUENCE").is_some() {
+        event.set_property(ContentLine::plain("SEQUENCE", sequence.to_string()));
+    }
+    event.set_property(ContentLine::plain("DTSTAMP", format!("{}Z", now)));
+}
+
+// a VEVENT that only identifies the event and one of its attendees
+fn reference(event: &ICalObject, attendee: ContentLine) -> ICalObject {
+    let mut reference = ICalObject {
+        object_type: "VEVENT".to_string(),
+        ..Default::default()
+    };
+    for name in ["ORGANIZER", "UID", "RECURRENCE-ID", "SEQUENCE"] {
+        if let Some(line) = event.get_property(name) {
+            reference.push_property(line.clone());
+        }
+    }
+    reference.push_property(attendee);
+    reference
+}
+
+fn attendee_of(calendar: &ICalObject, event: &ICalObject, address: &str) -> Result<ContentLine> {
+    event
+        .get_attendee(address)
+        .or_else(|| {
+            master(calendar)
+                .ok()
+                .and_then(|master| master.get_attendee(address))
+        })
+        .cloned()
+        .ok_or_else(|| eyre!("{} is not an attendee", address))
+}
+
+// wraps the events into a VCALENDAR with METHOD and the VTIMEZONEs they use
+fn message(method: Method, calendar: &ICalObject, events: Vec<ICalObject>) -> Result<ICalObject> {
+    let mut message = ICalObject {
+        object_type: "VCALENDAR".to_string(),
+        ..Default::default()
+    };
+    let product = calendar
+        .get_property("PRODID")
+        .cloned()
+        .unwrap_or_else(|| ContentLine::plain("PRODID", "-//elikoga-ical-rs//EN".to_string()));
+    message.push_property(product);
+    message.push_property(ContentLine::plain("VERSION", "2.0".to_string()));
+    message.push_property(ContentLine::plain("METHOD", method.name().to_string()));
+    let time_zones: Vec<&str> = events
+        .iter()
+        .flat_map(|event| &event.properties)
+        .filter_map(|line| line.param("TZID"))
+        .flat_map(|param| param.values())
+        .map(String::as_str)
+        .collect();
+    for time_zone in calendar.get_sub_objects("VTIMEZONE") {
+        let is_used = time_zone
+            .get_property("TZID")
+            .is_some_and(|line| time_zones.contains(&line.value.as_str()));
+        if is_used {
+            message.push_sub_object(time_zone.clone());
+        }
+    }
+    for event in events {
+        message.push_sub_object(event);
+    }
+    validate(&message)?;
+    Ok(message)
+}
+
+/// METHOD:REQUEST with all VEVENTs of the organizer's calendar
+///
+/// previous is the calendar last sent to the attendees, an instance whose
+/// DTSTART, DTEND, DURATION or recurrence changed gets the next SEQUENCE
+pub fn request(
+    calendar: &ICalObject,
+    previous: Option<&ICalObject>,
+    now: DateTime,
+) -> Result<ICalObject> {
+    let mut components = Vec::new();
+    for event in events(calendar)? {
+        let mut sequence = sequence(event);
+        let before = previous.and_then(|previous| {
+            previous
+                .get_sub_objects("VEVENT")
+                .find(|before| recurrence_id(before) == recurrence_id(event))
+        });
+        if let Some(before) = before {
+            let bump = is_significant_change(before, event) as u32;
+            sequence = sequence.max(self::sequence(before) + bump);
+        }
+        let mut event = restrict(Method::Request, event);
+        stamp(&mut event, sequence, now);
+        components.push(event);
+    }
+    message(Method::Request, calendar, components)
+}
+
+/// METHOD:REPLY of an attendee with a new PARTSTAT, for the whole event or an instance
+pub fn reply(
+    calendar: &ICalObject,
+    attendee: &str,
+    participation_status: &str,
+    recurrence: Option<&str>,
+    now: DateTime,
+) -> Result<ICalObject> {
+    let event = match recurrence {
+        Some(recurrence) => instance(calendar, recurrence)?,
+        None => master(calendar)?.clone(),
+    };
+    let mut line = attendee_of(calendar, &event, attendee)?;
+    line.params
+        .retain(|param| !param.name_eq("PARTSTAT") && !param.name_eq("RSVP"));
+    line.params.push(Param::new(
+        "PARTSTAT".to_string(),
+        vec![participation_status.to_string()],
+    ));
+    let mut reply = restrict(Method::Reply, &reference(&event, line));
+    stamp(&mut reply, sequence(&event), now);
+    message(Method::Reply, calendar, vec![reply])
+}
+
+/// METHOD:ADD with new instances of a recurring event, which bumps its SEQUENCE,
+/// the instances get the UID, ORGANIZER, ATTENDEEs and SUMMARY of the master
+pub fn add(calendar: &ICalObject, instances: &[ICalObject], now: DateTime) -> Result<ICalObject> {
+    let master = master(calendar)?;
+    let next = events(calendar)?
+        .into_iter()
+        .map(sequence)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut components = Vec::new();
+    for instance in instances {
+        let mut event = instance.clone();
+        for name in ["UID", "ORGANIZER", "SUMMARY"] {
+            if let Some(line) = master.get_property(name) {
+                event.set_property(line.clone());
+            }
+        }
+        if event.get_property("ATTENDEE").is_none() {
+            for line in master.get_properties("ATTENDEE") {
+                event.push_property(line.clone());
+            }
+        }
+        let mut event = restrict(Method::Add, &event);
+        stamp(&mut event, next, now);
+        components.push(event);
+    }
+    message(Method::Add, calendar, components)
+}
+
+/// METHOD:CANCEL, which bumps the SEQUENCE
+///
+/// without attendees the event or instance is cancelled for everyone,
+/// otherwise only the given attendees are removed from it
+pub fn cancel(
+    calendar: &ICalObject,
+    attendees: &[&str],
+    recurrence: Option<&str>,
+    now: DateTime,
+) -> Result<ICalObject> {
+    let next = events(calendar)?
+        .into_iter()
+        .map(sequence)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let event = match recurrence {
+        Some(recurrence) => instance(calendar, recurrence)?,
+        None => master(calendar)?.clone(),
+    };
+    let mut event = restrict(Method::Cancel, &event);
+    if attendees.is_empty() {
+        event.set_property(ContentLine::plain("STATUS", "CANCELLED".to_string()));
+    } else {
+        for attendee in attendees {
+            attendee_of(calendar, &event, attendee)?;
+        }
+        let removed: Vec<usize> = event
+            .properties
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| {
+                line.name_eq("ATTENDEE")
+                    && !attendees
+                        .iter()
+                        .any(|attendee| same_address(&line.value, attendee))
+            })
+            .map(|(i, _)| i)
+            .collect();
+        for i in removed.into_iter().rev() {
+            event.remove_property(i);
+        }
+    }
+    stamp(&mut event, next, now);
+    message(Method::Cancel, calendar, vec![event])
+}
+
+/// METHOD:REFRESH, an attendee asks for the latest version of the event or an instance
+pub fn refresh(
+    calendar: &ICalObject,
+    attendee: &str,
+    recurrence: Option<&str>,
+    now: DateTime,
+) -> Result<ICalObject> {
+    let event = match recurrence {
+        Some(recurrence) => instance(calendar, recurrence)?,
+        None => master(calendar)?.clone(),
+    };
+    let line = attendee_of(calendar, &event, attendee)
+        .unwrap_or_else(|_| ContentLine::plain("ATTENDEE", attendee.to_string()));
+    let mut refresh = restrict(Method::Refresh, &reference(&event, line));
+    stamp(&mut refresh, 0, now);
+    message(Method::Refresh, calendar, vec![refresh])
+}
+
+/// METHOD:COUNTER with the attendee's copy of the calendar that holds the proposed
+/// changes, the SEQUENCE stays the one of the organizer
+pub fn counter(calendar: &ICalObject, attendee: &str, now: DateTime) -> Result<ICalObject> {
+    let mut components = Vec::new();
+    for event in events(calendar)? {
+        attendee_of(calendar, event, attendee)?;
+        let mut event = restrict(Method::Counter, event);
+        let sequence = sequence(&event);
+        stamp(&mut event, sequence, now);
+        components.push(event);
+    }
+    message(Method::Counter, calendar, components)
+}
+
+/// METHOD:DECLINECOUNTER, the organizer rejects the COUNTER of an attendee
+pub fn decline_counter(
+    calendar: &ICalObject,
+    attendee: &str,
+    recurrence: Option<&str>,
+    now: DateTime,
+) -> Result<ICalObject> {
+    let event = match recurrence {
+        Some(recurrence) => instance(calendar, recurrence)?,
+        None => master(calendar)?.clone(),
+    };
+    let line = attendee_of(calendar, &event, attendee)?;
+    let mut decline = restrict(Method::DeclineCounter, &reference(&event, line));
+    stamp(&mut decline, sequence(&event), now);
+    message(Method::DeclineCounter, calendar, vec![decline])
+}
+
+/// a REQUEST-STATUS, see RFC 5546 section 3.6
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestStatus {
+    pub code: String,
+    pub description: String,
+    pub data: Option<String>,
+}
+
+impl RequestStatus {
+    /// a status with the description that RFC 5546 gives its code
+    pub fn new(code: &str, data: Option<String>) -> Self {
+        let description = match code {
+            "2.0" => "Success",
+            "3.1" => "Invalid property value",
+            "3.7" => "Invalid calendar user",
+            "3.8" => "No authority",
+            "3.11" => "Required component or property missing",
+            "3.13" => "Unsupported component or property found",
+            "3.14" => "Unsupported capability",
+            _ => "Unknown status",
+        };
+        RequestStatus {
+            code: code.to_string(),
+            description: description.to_string(),
+            data,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.code.starts_with("2.")
+    }
+
+    pub fn to_line(&self) -> ContentLine {
+        ContentLine::plain("REQUEST-STATUS", self.to_string())
+    }
+}
+
+impl Display for RequestStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{};{}", self.code, escape_text(&self.description))?;
+        if let Some(data) = &self.data {
+            write!(f, ";{}", escape_text(data))?;
+        }
+        Ok(())
+    }
+}
+
+// properties of a COUNTER that describe the proposal, not the event
+const NOT_PROPOSED: [&str; 8] = [
+    "ATTENDEE",
+    "COMMENT",
+    "DTSTAMP",
+    "ORGANIZER",
+    "RECURRENCE-ID",
+    "REQUEST-STATUS",
+    "SEQUENCE",
+    "UID",
+];
+
+/// applies an incoming REPLY, CANCEL or COUNTER to the stored calendar of the event
+///
+/// REPLY updates the PARTSTAT of the attendee, CANCEL marks the event or an
+/// instance as cancelled and COUNTER accepts the proposed changes, after which
+/// the organizer sends a new [request], see [decline_counter] to reject it,
+/// returns one status per VEVENT of the message, those that fail are not applied
+pub fn process(stored: &mut ICalObject, message: &ICalObject) -> Result<Vec<RequestStatus>> {
+    master(stored)?;
+    let method = match check(message) {
+        Ok(method) => method,
+        Err(status) => return Ok(vec![status]),
+    };
+    if !matches!(method, Method::Reply | Method::Cancel | Method::Counter) {
+        let data = format!("{} is not processed", method.name());
+        return Ok(vec![RequestStatus::new("3.14", Some(data))]);
+    }
+    Ok(message
+        .get_sub_objects("VEVENT")
+        .map(|component| match apply(stored, method, component) {
+            Ok(()) => RequestStatus::new("2.0", None),
+            Err(status) => status,
+        })
+        .collect())
+}
+
+fn apply(
+    stored: &mut ICalObject,
+    method: Method,
+    component: &ICalObject,
+) -> std::result::Result<(), RequestStatus> {
+    let error = |code: &str, data: String| RequestStatus::new(code, Some(data));
+    let master = master(stored).map_err(|e| error("3.1", e.to_string()))?;
+    let value = |event: &ICalObject, name: &str| {
+        event
+            .get_property(name)
+            .map(|line| line.value.trim().to_string())
+            .unwrap_or_default()
+    };
+    if value(master, "UID") != value(component, "UID") {
+        return Err(error(
+            "3.1",
+            format!("unknown UID {}", value(component, "UID")),
+        ));
+    }
+    if !same_address(&value(master, "ORGANIZER"), &value(component, "ORGANIZER")) {
+        return Err(error(
+            "3.8",
+            format!("ORGANIZER {}", value(component, "ORGANIZER")),
+        ));
+    }
+    let recurrence = recurrence_id(component);
+    let index = stored
+        .sub_objects
+        .iter()
+        .position(|event| event.is_type("VEVENT") && recurrence_id(event) == recurrence);
+    let current = index.map_or(sequence(master), |i| sequence(&stored.sub_objects[i]));
+    if sequence(component) < current {
+        return Err(error(
+            "3.1",
+            format!("SEQUENCE {} is older than {}", sequence(component), current),
+        ));
+    }
+    if method == Method::Cancel && recurrence.is_none() {
+        for event in stored
+            .sub_objects
+            .iter_mut()
+            .filter(|e| e.is_type("VEVENT"))
+        {
+            event.set_property(ContentLine::plain("STATUS", "CANCELLED".to_string()));
+            event.set_property(ContentLine::plain(
+                "SEQUENCE",
+                sequence(component).to_string(),
+            ));
+        }
+        return Ok(());
+    }
+    // the ATTENDEE that replies, its presence is checked by check
+    let reply = component
+        .get_property("ATTENDEE")
+        .filter(|_| method == Method::Reply);
+    if let Some(reply) = reply {
+        let event = index.map_or(master, |i| &stored.sub_objects[i]);
+        if event.get_attendee(&reply.value).is_none() {
+            return Err(error("3.7", reply.value.clone()));
+        }
+    }
+    let index = match (index, recurrence) {
+        (Some(index), _) => index,
+        (None, Some(recurrence)) => {
+            let event =
+                new_override(master, recurrence).map_err(|e| error("3.1", e.to_string()))?;
+            stored.push_sub_object(event);
+            stored.sub_objects.len() - 1
+        }
+        (None, None) => stored
+            .sub_objects
+            .iter()
+            .position(|event| event.is_type("VEVENT") && recurrence_id(event).is_none())
+            .unwrap_or_default(),
+    };
+    let event = &mut stored.sub_objects[index];
+    match method {
+        Method::Reply => {
+            let line = reply.and_then(|reply| {
+                event
+                    .properties
+                    .iter_mut()
+                    .find(|line| {
+                        line.name_eq("ATTENDEE") && same_address(&line.value, &reply.value)
+                    })
+                    .map(|line| (reply, line))
+            });
+            if let Some((reply, line)) = line {
+                let status = reply.param("PARTSTAT").cloned().unwrap_or_else(|| {
+                    Param::new("PARTSTAT".to_string(), vec!["NEEDS-ACTION".to_string()])
+                });
+                line.params
+                    .retain(|param| !param.name_eq("PARTSTAT") && !param.name_eq("DELEGATED-TO"));
+                line.params.push(status);
+                line.params.extend(reply.param("DELEGATED-TO").cloned());
+            }
+        }
+        Method::Cancel => {
+            event.set_property(ContentLine::plain("STATUS", "CANCELLED".to_string()));
+            event.set_property(ContentLine::plain(
+                "SEQUENCE",
+                sequence(component).to_string(),
+            ));
+        }
+        _ => {
+            let before = event.clone();
+            let proposed: Vec<&ContentLine> = component
+                .properties
+                .iter()
+                .filter(|line| !NOT_PROPOSED.iter().any(|name| line.name_eq(name)))
+                .collect();
+            let mut replaced: Vec<String> = proposed
+                .iter()
+                .map(|line| line.name.to_ascii_uppercase())
+                .collect();
+            // DTEND and DURATION replace each other
+            if replaced
+                .iter()
+                .any(|name| name == "DTEND" || name == "DURATION")
+            {
+                replaced.extend(["DTEND".to_string(), "DURATION".to_string()]);
+            }
+            for i in (0..event.properties.len()).rev() {
+                if replaced.contains(&event.properties[i].name.to_ascii_uppercase()) {
+                    event.remove_property(i);
+                }
+            }
+            for line in proposed {
+                event.push_property(line.clone());
+            }
+            if is_significant_change(&before, event) {
+                let next = sequence(&before) + 1;
+                event.set_property(ContentLine::plain("SEQUENCE", next.to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//Example//Booking//EN\r
+BEGIN:VTIMEZONE\r
+TZID:Europe/Berlin\r
+BEGIN:STANDARD\r
+DTSTART:19701025T030000\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+UID:planning@example.com\r
+DTSTAMP:20240101T090000Z\r
+DTSTART;TZID=Europe/Berlin:20240115T100000\r
+DTEND;TZID=Europe/Berlin:20240115T110000\r
+RRULE:FREQ=WEEKLY;COUNT=4\r
+SUMMARY:Planning\r
+ORGANIZER:mailto:olga@example.com\r
+ATTENDEE;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:ana@example.com\r
+ATTENDEE;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:ben@example.com\r
+REQUEST-STATUS:2.0;Success\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT15M\r
+DESCRIPTION:Planning\r
+END:VALARM\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn builds_messages() {
+        let calendar: ICalObject = CALENDAR.parse().unwrap();
+        let previous: ICalObject = CALENDAR.replace("T100000", "T090000").parse().unwrap();
+        let now = DateTime::new(2024, 1, 10, 12, 0, 0);
+
+        let request = request(&calendar, Some(&previous), now).unwrap();
+        let event = &request.sub_objects[1];
+        assert_eq!(request.get_property("METHOD").unwrap().value, "REQUEST");
+        assert!(request.sub_objects[0].is_type("VTIMEZONE"));
+        assert_eq!(event.get_property("SEQUENCE").unwrap().value, "1");
+        assert_eq!(
+            event.get_property("DTSTAMP").unwrap().value,
+            "20240110T120000Z"
+        );
+        assert!(event.get_property("REQUEST-STATUS").is_none());
+        assert_eq!(event.sub_objects.len(), 1);
+        // an unchanged event keeps its SEQUENCE
+        let unchanged = super::request(&calendar, Some(&calendar), now).unwrap();
+        assert!(unchanged.sub_objects[1].get_property("SEQUENCE").is_none());
+
+        let reply = reply(&calendar, "MAILTO:ana@example.com", "ACCEPTED", None, now).unwrap();
+        assert_eq!(
+            reply.to_string(),
+            "BEGIN:VCALENDAR\r
+PRODID:-//Example//Booking//EN\r
+VERSION:2.0\r
+METHOD:REPLY\r
+BEGIN:VEVENT\r
+ORGANIZER:mailto:olga@example.com\r
+UID:planning@example.com\r
+ATTENDEE;PARTSTAT=ACCEPTED:mailto:ana@example.com\r
+DTSTAMP:20240110T120000Z\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        );
+
+        let cancel = cancel(&calendar, &[], Some("20240122T100000"), now).unwrap();
+        let event = &cancel.sub_objects[1];
+        assert_eq!(
+            event.get_property("RECURRENCE-ID").unwrap().to_string(),
+            "RECURRENCE-ID;TZID=Europe/Berlin:20240122T100000"
+        );
+        assert_eq!(event.get_property("STATUS").unwrap().value, "CANCELLED");
+        assert!(event.get_property("RRULE").is_none());
+        assert_eq!(event.get_property("SEQUENCE").unwrap().value, "1");
+        assert!(event.sub_objects.is_empty());
+        let uninvite = super::cancel(&calendar, &["mailto:ben@example.com"], None, now).unwrap();
+        let attendees: Vec<_> = uninvite.sub_objects[1]
+            .get_properties("ATTENDEE")
+            .map(|line| line.value.as_str())
+            .collect();
+        assert_eq!(attendees, ["mailto:ben@example.com"]);
+
+        let refresh = refresh(&calendar, "mailto:ben@example.com", None, now).unwrap();
+        let names: Vec<_> = refresh.sub_objects[0]
+            .properties
+            .iter()
+            .map(|line| line.name.as_str())
+            .collect();
+        assert_eq!(names, ["ORGANIZER", "UID", "ATTENDEE", "DTSTAMP"]);
+
+        let mut instance: ICalObject = "BEGIN:VEVENT\r
+DTSTART;TZID=Europe/Berlin:20240220T100000\r
+DURATION:PT1H\r
+END:VEVENT\r
+"
+        .parse()
+        .unwrap();
+        instance.push_property(ContentLine::plain(
+            "REQUEST-STATUS",
+            "2.0;Success".to_string(),
+        ));
+        let add = add(&calendar, &[instance], now).unwrap();
+        let event = &add.sub_objects[1];
+        assert_eq!(event.get_property("SEQUENCE").unwrap().value, "1");
+        assert_eq!(event.get_property("SUMMARY").unwrap().value, "Planning");
+        assert_eq!(event.get_properties("ATTENDEE").count(), 2);
+
+        assert!(counter(&calendar, "mailto:ana@example.com", now).is_ok());
+        assert!(counter(&calendar, "mailto:eve@example.com", now).is_err());
+        assert!(decline_counter(&calendar, "mailto:ana@example.com", None, now).is_ok());
+    }
+
+    #[test]
+    fn enforces_the_restriction_tables() {
+        let without_summary = CALENDAR.replace("SUMMARY:Planning\r\n", "");
+        let calendar: ICalObject = without_summary.parse().unwrap();
+        let now = DateTime::new(2024, 1, 10, 12, 0, 0);
+        let error = request(&calendar, None, now).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "REQUEST allows exactly one SUMMARY in a VEVENT, found 0"
+        );
+
+        let mut message =
+            reply(&calendar, "mailto:ana@example.com", "DECLINED", None, now).unwrap();
+        assert_eq!(validate(&message).unwrap(), Method::Reply);
+        message.sub_objects[0].push_property(ContentLine::plain(
+            "ATTENDEE",
+            "mailto:ben@example.com".to_string(),
+        ));
+        assert!(validate(&message).is_err());
+    }
+
+    #[test]
+    fn processes_replies_cancellations_and_counters() {
+        let mut stored: ICalObject = CALENDAR.parse().unwrap();
+        let now = DateTime::new(2024, 1, 10, 12, 0, 0);
+        let ok = vec![RequestStatus::new("2.0", None)];
+
+        let message = reply(&stored, "mailto:ana@example.com", "ACCEPTED", None, now).unwrap();
+        assert_eq!(process(&mut stored, &message).unwrap(), ok);
+        assert_eq!(
+            stored.sub_objects[1]
+                .get_attendee("mailto:ana@example.com")
+                .unwrap()
+                .to_string(),
+            "ATTENDEE;RSVP=TRUE;PARTSTAT=ACCEPTED:mailto:ana@example.com"
+        );
+
+        // a reply for one instance creates an override
+        let message = reply(
+            &stored,
+            "mailto:ben@example.com",
+            "DECLINED",
+            Some("20240122T100000"),
+            now,
+        )
+        .unwrap();
+        assert_eq!(process(&mut stored, &message).unwrap(), ok);
+        let instance = &stored.sub_objects[2];
+        assert_eq!(
+            instance.get_property("DTEND").unwrap().value,
+            "20240122T110000"
+        );
+        assert!(instance.get_property("RRULE").is_none());
+        assert!(instance
+            .get_attendee("mailto:ben@example.com")
+            .unwrap()
+            .to_string()
+            .contains("PARTSTAT=DECLINED"));
+
+        let mut stranger = reply(&stored, "mailto:ana@example.com", "ACCEPTED", None, now).unwrap();
+        stranger.sub_objects[0].properties[2].value = "mailto:eve@example.com".to_string();
+        let mut forged = reply(&stored, "mailto:ana@example.com", "ACCEPTED", None, now).unwrap();
+        forged.sub_objects[0].properties[0].value = "mailto:mallory@example.com".to_string();
+        let codes = |message: &ICalObject, stored: &mut ICalObject| -> Vec<String> {
+            process(stored, message)
+                .unwrap()
+                .into_iter()
+                .map(|status| status.code)
+                .collect()
+        };
+        assert_eq!(codes(&stranger, &mut stored), ["3.7"]);
+        assert_eq!(codes(&forged, &mut stored), ["3.8"]);
+
+        // accepting a counter proposal reschedules the event
+        let proposal: ICalObject = CALENDAR
+            .replace("T100000", "T140000")
+            .replace("T110000", "T150000")
+            .parse()
+            .unwrap();
+        let message = counter(&proposal, "mailto:ana@example.com", now).unwrap();
+        assert_eq!(process(&mut stored, &message).unwrap(), ok);
+        let master = &stored.sub_objects[1];
+        assert_eq!(
+            master.get_property("DTSTART").unwrap().value,
+            "20240115T140000"
+        );
+        assert_eq!(master.get_property("SEQUENCE").unwrap().value, "1");
+        // which makes the first reply outdated
+        let outdated = reply(&proposal, "mailto:ana@example.com", "ACCEPTED", None, now).unwrap();
+        assert_eq!(codes(&outdated, &mut stored), ["3.1"]);
+
+        let message = cancel(&stored, &[], None, now).unwrap();
+        let mut attendee_copy: ICalObject = CALENDAR.parse().unwrap();
+        assert_eq!(process(&mut attendee_copy, &message).unwrap(), ok);
+        assert_eq!(
+            attendee_copy.sub_objects[1]
+                .get_property("STATUS")
+                .unwrap()
+                .value,
+            "CANCELLED"
+        );
+        assert_eq!(
+            RequestStatus::new("3.8", Some("ORGANIZER".to_string())).to_string(),
+            "3.8;No authority;ORGANIZER"
+        );
+    }
+}
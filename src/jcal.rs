@@ -0,0 +1,320 @@
+// jCal, the JSON format for iCalendar, see RFC 7265
+//
+// an ICalObject becomes `["vcalendar", [properties], [components]]`,
+// every property becomes `[name, {params}, type, value, ...]`
+
+use eyre::{eyre, Result};
+use serde_json::{Map, Value};
+
+use crate::{
+    content_line::{ContentLine, Param},
+    ical_object::ICalObject,
+    value::{
+        escape_text, from_extended, is_integer_recur_part, is_multi_valued, is_structured,
+        split_unescaped, to_extended, unescape_text, ValueType,
+    },
+};
+
+/// converts an object and its sub objects to jCal
+pub fn to_jcal(object: &ICalObject) -> Value {
+    Value::Array(vec![
+        Value::String(object.object_type.to_ascii_lowercase()),
+        Value::Array(object.properties.iter().map(property_to_jcal).collect()),
+        Value::Array(object.sub_objects.iter().map(to_jcal).collect()),
+    ])
+}
+
+/// converts a property to a jCal property array
+pub fn property_to_jcal(line: &ContentLine) -> Value {
+    let value_type = ValueType::of(line);
+    let mut params = Map::new();
+    for param in &line.params {
+        // the VALUE param is the type of the jCal property
+        if param.name_eq("VALUE") {
+            continue;
+        }
+        let value = match param.values() {
+            [value] => Value::String(value.clone()),
+            values => Value::Array(values.iter().cloned().map(Value::String).collect()),
+        };
+        params.insert(param.name().to_ascii_lowercase(), value);
+    }
+    let mut property = vec![
+        Value::String(line.name.to_ascii_lowercase()),
+        Value::Object(params),
+        Value::String(value_type.name().to_ascii_lowercase()),
+    ];
+    if is_structured(&line.name) {
+        // structured values are a single array, see RFC 7265 section 3.4.1.2
+        let parts = split_unescaped(&line.value, ';');
+        property.push(Value::Array(
+            parts
+                .into_iter()
+                .map(|part| value_to_jcal(value_type, part))
+                .collect(),
+        ));
+    } else if is_multi_valued(&line.name) {
+        property.extend(
+            split_unescaped(&line.value, ',')
+                .into_iter()
+                .map(|part| value_to_jcal(value_type, part)),
+        );
+    } else {
+        property.push(value_to_jcal(value_type, &line.value));
+    }
+    Value::Array(property)
+}
+
+fn value_to_jcal(value_type: ValueType, value: &str) -> Value {
+    match value_type {
+        ValueType::Text => Value::String(unescape_text(value)),
+        ValueType::Integer => value
+            .parse::<i64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| Value::String(value.to_string())),
+        ValueType::Float => value
+            .parse::<f64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| Value::String(value.to_string())),
+        ValueType::Boolean => Value::Bool(value.eq_ignore_ascii_case("TRUE")),
+        ValueType::Recur => recur_to_jcal(value),
+        ValueType::Date
+        | ValueType::DateTime
+        | ValueType::Time
+        | ValueType::UtcOffset
+        | ValueType::Period => Value::String(to_extended(value_type, value)),
+        _ => Value::String(value.to_string()),
+    }
+}
+
+// a recurrence rule is an object with a member per rule part,
+// see RFC 7265 section 3.6.10
+fn recur_to_jcal(value: &str) -> Value {
+    let mut recur = Map::new();
+    for part in value.split(';').filter(|part| !part.is_empty()) {
+        let (name, value) = part.split_once('=').unwrap_or((part, ""));
+        let values: Vec<Value> = value
+            .split(',')
+            .map(|value| {
+                if name.eq_ignore_ascii_case("UNTIL") {
+                    let value_type = if value.contains('T') {
+                        ValueType::DateTime
+                    } else {
+                        ValueType::Date
+                    };
+                    Value::String(to_extended(value_type, value))
+                } else if is_integer_recur_part(name) {
+                    value
+                        .parse::<i64>()
+                        .map(Value::from)
+                        .unwrap_or_else(|_| Value::String(value.to_string()))
+                } else {
+                    Value::String(value.to_string())
+                }
+            })
+            .collect();
+        let value = match <[Value; 1]>::try_from(values) {
+            Ok([value]) => value,
+            Err(values) => Value::Array(values),
+        };
+        recur.insert(name.to_ascii_lowercase(), value);
+    }
+    Value::Object(recur)
+}
+
+/// converts a jCal component array to an object
+pub fn from_jcal(value: &Value) -> Result<ICalObject> {
+    let component = value
+        .as_array()
+        .filter(|component| component.len() == 3)
+        .ok_or(eyre!("a jCal component must be an array of 3 elements"))?;
+    let object_type = component[0]
+        .as_str()
+        .ok_or(eyre!("the component name must be a string"))?
+        .to_ascii_uppercase();
+    let properties = component[1]
+        .as_array()
+        .ok_or(eyre!("the properties of {} must be an array", object_type))?
+        .iter()
+        .map(property_from_jcal)
+        .collect::<Result<Vec<_>>>()?;
+    let sub_objects = component[2]
+        .as_array()
+        .ok_or(eyre!("the components of {} must be an array", object_type))?
+        .iter()
+        .map(from_jcal)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(ICalObject {
+        object_type,
+        properties,
+        sub_objects,
+        ..ICalObject::default()
+    })
+}
+
+/// converts a jCal property array to a content line
+pub fn property_from_jcal(value: &Value) -> Result<ContentLine> {
+    let property = value
+        .as_array()
+        .filter(|property| property.len() >= 4)
+        .ok_or(eyre!(
+            "a jCal property must be an array of at least 4 elements"
+        ))?;
+    let name = property[0]
+        .as_str()
+        .ok_or(eyre!("the property name must be a string"))?
+        .to_ascii_uppercase();
+    let mut params = Vec::new();
+    for (param_name, value) in property[1]
+        .as_object()
+        .ok_or(eyre!("the params of {} must be an object", name))?
+    {
+        let values = match value {
+            Value::String(value) => vec![value.clone()],
+            Value::Array(values) => values
+                .iter()
+                .map(|value| {
+                    value
+                        .as_str()
+                        .map(str::to_string)
+                        .ok_or(eyre!("param values must be strings"))
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(eyre!("param {} must be a string or an array", param_name)),
+        };
+        params.push(Param::new(param_name.to_ascii_uppercase(), values));
+    }
+    let type_name = property[2]
+        .as_str()
+        .ok_or(eyre!("the type of {} must be a string", name))?;
+    let value_type = ValueType::from_name(type_name);
+    // a type other than the default has to be kept in a VALUE param
+    if value_type != ValueType::default_for(&name) && !type_name.eq_ignore_ascii_case("unknown") {
+        params.push(Param::new(
+            "VALUE".to_string(),
+            vec![type_name.to_ascii_uppercase()],
+        ));
+    }
+    let values = property[3..]
+        .iter()
+        .map(|value| match value {
+            Value::Array(parts) if is_structured(&name) => Ok(parts
+                .iter()
+                .map(|part| value_from_jcal(value_type, part))
+                .collect::<Result<Vec<_>>>()?
+                .join(";")),
+            value => value_from_jcal(value_type, value),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(ContentLine::new(name, params, values.join(",")))
+}
+
+fn value_from_jcal(value_type: ValueType, value: &Value) -> Result<String> {
+    Ok(match value {
+        Value::String(value) => match value_type {
+            ValueType::Text => escape_text(value),
+            value_type => from_extended(value_type, value),
+        },
+        Value::Number(number) => number.to_string(),
+        Value::Bool(true) => "TRUE".to_string(),
+        Value::Bool(false) => "FALSE".to_string(),
+        Value::Object(recur) => recur_from_jcal(recur)?,
+        Value::Null | Value::Array(_) => return Err(eyre!("unexpected value {}", value)),
+    })
+}
+
+fn recur_from_jcal(recur: &Map<String, Value>) -> Result<String> {
+    let mut parts = Vec::new();
+    for (name, value) in recur {
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        let values = values
+            .into_iter()
+            .map(|value| match value {
+                Value::String(value) if name.eq_ignore_ascii_case("until") => {
+                    Ok(from_extended(ValueType::DateTime, value))
+                }
+                Value::String(value) => Ok(value.clone()),
+                Value::Number(number) => Ok(number.to_string()),
+                value => Err(eyre!("unexpected recur value {}", value)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        parts.push(format!(
+            "{}={}",
+            name.to_ascii_uppercase(),
+            values.join(",")
+        ));
+    }
+    // FREQ first, for compatibility with RFC 2445 parsers
+    parts.sort_by_key(|part| !part.starts_with("FREQ="));
+    Ok(parts.join(";"))
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{from_jcal, to_jcal};
+    use crate::ICalObject;
+
+    #[test]
+    fn converts_both_ways() {
+        // the example from RFC 7265 appendix B.1, plus some more value types
+        let ical: ICalObject = "BEGIN:VCALENDAR\r
+CALSCALE:GREGORIAN\r
+PRODID:-//Example Inc.//Example Calendar//EN\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+DTSTAMP:20080205T191224Z\r
+DTSTART;VALUE=DATE:20081006\r
+SUMMARY:Planning meeting\\, with a comma\r
+UID:4088E990AD89CB3DBB484909\r
+CATEGORIES:WORK,MEETING\r
+GEO:37.386013;-122.082932\r
+RRULE:FREQ=WEEKLY;UNTIL=20081231T000000Z;BYDAY=MO,WE;INTERVAL=2\r
+ATTENDEE;DELEGATED-FROM=\"mailto:a@example.com\",\"mailto:b@example.com\":mailto:c@example.com\r
+X-FOO;VALUE=INTEGER:42\r
+X-BAR:baz\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        .parse()
+        .unwrap();
+        let jcal = json!(["vcalendar",
+          [
+            ["calscale", {}, "text", "GREGORIAN"],
+            ["prodid", {}, "text", "-//Example Inc.//Example Calendar//EN"],
+            ["version", {}, "text", "2.0"]
+          ],
+          [
+            ["vevent",
+              [
+                ["dtstamp", {}, "date-time", "2008-02-05T19:12:24Z"],
+                ["dtstart", {}, "date", "2008-10-06"],
+                ["summary", {}, "text", "Planning meeting, with a comma"],
+                ["uid", {}, "text", "4088E990AD89CB3DBB484909"],
+                ["categories", {}, "text", "WORK", "MEETING"],
+                ["geo", {}, "float", [37.386013, -122.082932]],
+                ["rrule", {}, "recur", {
+                    "freq": "WEEKLY",
+                    "until": "2008-12-31T00:00:00Z",
+                    "byday": ["MO", "WE"],
+                    "interval": 2
+                }],
+                ["attendee", {"delegated-from": ["mailto:a@example.com", "mailto:b@example.com"]},
+                    "cal-address", "mailto:c@example.com"],
+                ["x-foo", {}, "integer", 42],
+                ["x-bar", {}, "unknown", "baz"]
+              ],
+              []
+            ]
+          ]
+        ]);
+        assert_eq!(to_jcal(&ical), jcal);
+        assert_eq!(from_jcal(&jcal).unwrap(), ical);
+        assert!(from_jcal(&json!(["vcalendar", []])).is_err());
+    }
+}
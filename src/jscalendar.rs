@@ -0,0 +1,1364 @@
+// JSCalendar, see RFC 8984, mapped from and to iCalendar following
+// draft-ietf-calext-jscalendar-icalendar
+//
+// VEVENTs become Events and VTODOs Tasks, components with a RECURRENCE-ID
+// become recurrenceOverrides of the component with the same UID,
+// whatever has no JSCalendar equivalent is reported in Conversion::unmapped
+
+use eyre::{eyre, Result};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    content_line::{ContentLine, Param},
+    datetime::{format_duration, DateTime},
+    ical_object::ICalObject,
+    recurrence::{parse_by_day, WEEKDAYS},
+    value::{escape_text, from_extended, split_unescaped, to_extended, unescape_text, ValueType},
+};
+
+/// the result of a conversion and a description of everything
+/// that could not be mapped and was dropped
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion<T> {
+    pub value: T,
+    pub unmapped: Vec<String>,
+}
+
+// members of an object that a recurrence override can not patch
+const NOT_PATCHABLE: [&str; 7] = [
+    "@type",
+    "uid",
+    "recurrenceRules",
+    "excludedRecurrenceRules",
+    "recurrenceOverrides",
+    "recurrenceId",
+    "recurrenceIdTimeZone",
+];
+
+// members that component_from_jscalendar knows about
+const KNOWN_MEMBERS: [&str; 31] = [
+    "@type",
+    "uid",
+    "updated",
+    "created",
+    "sequence",
+    "title",
+    "description",
+    "locations",
+    "links",
+    "start",
+    "timeZone",
+    "showWithoutTime",
+    "duration",
+    "due",
+    "recurrenceId",
+    "recurrenceRules",
+    "excludedRecurrenceRules",
+    "recurrenceOverrides",
+    "status",
+    "progress",
+    "freeBusyStatus",
+    "privacy",
+    "priority",
+    "percentComplete",
+    "keywords",
+    "color",
+    "participants",
+    "replyTo",
+    "alerts",
+    "excluded",
+    "recurrenceIdTimeZone",
+];
+
+// iCalendar rule parts and their JSCalendar names, see RFC 8984 section 4.3.3
+const RULE_PARTS: [(&str, &str); 16] = [
+    ("FREQ", "frequency"),
+    ("INTERVAL", "interval"),
+    ("COUNT", "count"),
+    ("UNTIL", "until"),
+    ("WKST", "firstDayOfWeek"),
+    ("RSCALE", "rscale"),
+    ("SKIP", "skip"),
+    ("BYDAY", "byDay"),
+    ("BYMONTH", "byMonth"),
+    ("BYMONTHDAY", "byMonthDay"),
+    ("BYYEARDAY", "byYearDay"),
+    ("BYWEEKNO", "byWeekNo"),
+    ("BYHOUR", "byHour"),
+    ("BYMINUTE", "byMinute"),
+    ("BYSECOND", "bySecond"),
+    ("BYSETPOS", "bySetPosition"),
+];
+
+/// converts the VEVENTs and VTODOs of a VCALENDAR, or a single VEVENT or
+/// VTODO, to JSCalendar Events and Tasks
+pub fn to_jscalendar(object: &ICalObject) -> Result<Conversion<Vec<Value>>> {
+    let mut unmapped = Vec::new();
+    let components: Vec<&ICalObject> = if object.is_type("VCALENDAR") {
+        for line in &object.properties {
+            if !["CALSCALE", "PRODID", "VERSION"]
+                .iter()
+                .any(|name| line.name_eq(name))
+            {
+                unmapped.push(format!("VCALENDAR property {}", line.name));
+            }
+        }
+        let mut components = Vec::new();
+        for sub_object in &object.sub_objects {
+            if is_convertible(sub_object) {
+                components.push(sub_object);
+            } else if !sub_object.is_type("VTIMEZONE") {
+                // time zones are referenced by their TZID, which should be an IANA name
+                unmapped.push(format!("component {}", sub_object.object_type));
+            }
+        }
+        components
+    } else if is_convertible(object) {
+        vec![object]
+    } else {
+        return Err(eyre!(
+            "cannot convert a {} to JSCalendar",
+            object.object_type
+        ));
+    };
+
+    let (masters, overrides): (Vec<&ICalObject>, Vec<&ICalObject>) = components
+        .into_iter()
+        .partition(|component| component.get_property("RECURRENCE-ID").is_none());
+    let mut overrides: Vec<Option<&ICalObject>> = overrides.into_iter().map(Some).collect();
+    let mut values = Vec::new();
+    for master in masters {
+        let mut object = component_to_jscalendar(master, &mut unmapped)?;
+        let uid = master.get_property("UID").map(|line| &line.value);
+        for slot in overrides.iter_mut() {
+            let Some(component) = slot.filter(|component| {
+                component.is_type(&master.object_type)
+                    && component.get_property("UID").map(|line| &line.value) == uid
+            }) else {
+                continue;
+            };
+            *slot = None;
+            let instance = component_to_jscalendar(component, &mut unmapped)?;
+            let recurrence_id = instance
+                .get("recurrenceId")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            let patch = patch_between(&object, &instance);
+            object
+                .entry("recurrenceOverrides")
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .unwrap()
+                .insert(recurrence_id, Value::Object(patch));
+        }
+        values.push(Value::Object(object));
+    }
+    // instances without their master stand alone, with their recurrenceId
+    for component in overrides.into_iter().flatten() {
+        values.push(Value::Object(component_to_jscalendar(
+            component,
+            &mut unmapped,
+        )?));
+    }
+    Ok(Conversion {
+        value: values,
+        unmapped,
+    })
+}
+
+fn is_convertible(object: &ICalObject) -> bool {
+    object.is_type("VEVENT") || object.is_type("VTODO")
+}
+
+// the members of an instance that differ from its master,
+// members the instance lacks are removed with null
+fn patch_between(master: &Map<String, Value>, instance: &Map<String, Value>) -> Map<String, Value> {
+    let mut patch = Map::new();
+    for (key, value) in instance {
+        if !NOT_PATCHABLE.contains(&key.as_str()) && master.get(key) != Some(value) {
+            patch.insert(pointer_segment(key), value.clone());
+        }
+    }
+    for key in master.keys() {
+        if !NOT_PATCHABLE.contains(&key.as_str()) && !instance.contains_key(key) {
+            patch.insert(pointer_segment(key), Value::Null);
+        }
+    }
+    patch
+}
+
+// a member name escaped for a JSON pointer, see RFC 6901 section 3
+fn pointer_segment(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+// a DATE or DATE-TIME property as a LocalDateTime, its time zone and whether it is a DATE
+fn local_date_time(line: &ContentLine) -> (String, Option<String>, bool) {
+    let is_date = ValueType::of(line) == ValueType::Date || line.value.len() == 8;
+    if is_date {
+        return (
+            format!("{}T00:00:00", to_extended(ValueType::Date, &line.value)),
+            None,
+            true,
+        );
+    }
+    let time_zone = match line.param("TZID").and_then(|param| param.values().first()) {
+        Some(tzid) => Some(tzid.clone()),
+        None if line.value.ends_with('Z') => Some("Etc/UTC".to_string()),
+        None => None,
+    };
+    let local = to_extended(ValueType::DateTime, line.value.trim_end_matches('Z'));
+    (local, time_zone, false)
+}
+
+// the inverse of local_date_time
+fn date_time_line(name: &str, local: &str, time_zone: Option<&str>, is_date: bool) -> ContentLine {
+    let value = from_extended(ValueType::DateTime, local);
+    if is_date {
+        let date = value.split('T').next().unwrap_or_default().to_string();
+        let params = vec![Param::new("VALUE".to_string(), vec!["DATE".to_string()])];
+        return ContentLine::new(name.to_string(), params, date);
+    }
+    match time_zone {
+        Some("Etc/UTC") => ContentLine::plain(name, value + "Z"),
+        Some(tzid) => ContentLine::new(
+            name.to_string(),
+            vec![Param::new("TZID".to_string(), vec![tzid.to_string()])],
+            value,
+        ),
+        None => ContentLine::plain(name, value),
+    }
+}
+
+fn first_param<'a>(line: &'a ContentLine, name: &str) -> Option<&'a str> {
+    line.param(name)
+        .and_then(|param| param.values().first())
+        .map(String::as_str)
+}
+
+// an entry of an id map like locations or alerts, created if missing
+fn map_entry<'a>(
+    object: &'a mut Map<String, Value>,
+    member: &str,
+    id: &str,
+    object_type: &str,
+) -> &'a mut Map<String, Value> {
+    object
+        .entry(member)
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .unwrap()
+        .entry(id)
+        .or_insert_with(|| json!({ "@type": object_type }))
+        .as_object_mut()
+        .unwrap()
+}
+
+fn component_to_jscalendar(
+    component: &ICalObject,
+    unmapped: &mut Vec<String>,
+) -> Result<Map<String, Value>> {
+    let is_task = component.is_type("VTODO");
+    let mut object = Map::new();
+    object.insert(
+        "@type".to_string(),
+        json!(if is_task { "Task" } else { "Event" }),
+    );
+    let start = component.get_property("DTSTART");
+    if let Some(start) = start {
+        let (local, time_zone, is_date) = local_date_time(start);
+        object.insert("start".to_string(), json!(local));
+        if let Some(time_zone) = time_zone {
+            object.insert("timeZone".to_string(), json!(time_zone));
+        }
+        if is_date {
+            object.insert("showWithoutTime".to_string(), json!(true));
+        }
+    }
+
+    for line in &component.properties {
+        let text = || unescape_text(&line.value);
+        let handled_params: &[&str] = match line.name.to_ascii_uppercase().as_str() {
+            "UID" => {
+                object.insert("uid".to_string(), json!(line.value));
+                &[]
+            }
+            "DTSTAMP" => {
+                object.insert(
+                    "updated".to_string(),
+                    json!(to_extended(ValueType::DateTime, &line.value)),
+                );
+                &[]
+            }
+            "CREATED" => {
+                object.insert(
+                    "created".to_string(),
+                    json!(to_extended(ValueType::DateTime, &line.value)),
+                );
+                &[]
+            }
+            "SEQUENCE" | "PRIORITY" | "PERCENT-COMPLETE" => {
+                let member = match line.name.to_ascii_uppercase().as_str() {
+                    "SEQUENCE" => "sequence",
+                    "PRIORITY" => "priority",
+                    _ => "percentComplete",
+                };
+                let Ok(number) = line.value.trim().parse::<i64>() else {
+                    unmapped.push(format!("{}:{}", line.name, line.value));
+                    continue;
+                };
+                object.insert(member.to_string(), json!(number));
+                &[]
+            }
+            "SUMMARY" => {
+                object.insert("title".to_string(), json!(text()));
+                &[]
+            }
+            "DESCRIPTION" => {
+                object.insert("description".to_string(), json!(text()));
+                &[]
+            }
+            "LOCATION" => {
+                map_entry(&mut object, "locations", "1", "Location")
+                    .insert("name".to_string(), json!(text()));
+                &[]
+            }
+            "GEO" => {
+                let parts = split_unescaped(&line.value, ';');
+                let coordinates = format!("geo:{}", parts.join(","));
+                map_entry(&mut object, "locations", "1", "Location")
+                    .insert("coordinates".to_string(), json!(coordinates));
+                &[]
+            }
+            "URL" => {
+                map_entry(&mut object, "links", "1", "Link")
+                    .insert("href".to_string(), json!(line.value));
+                &[]
+            }
+            "DTSTART" => &["TZID", "VALUE"],
+            "DTEND" if !is_task => {
+                let end = local_date_time(line);
+                let start = start.map(local_date_time);
+                let duration = start
+                    .as_ref()
+                    .and_then(|(local, _, _)| {
+                        DateTime::parse(&from_extended(ValueType::DateTime, local)).ok()
+                    })
+                    .zip(DateTime::parse(&from_extended(ValueType::DateTime, &end.0)).ok())
+                    .map(|(start, end)| end.timestamp() - start.timestamp());
+                let Some(duration) = duration else {
+                    unmapped.push(format!("{} without a valid DTSTART", line.name));
+                    continue;
+                };
+                if start.as_ref().map(|start| &start.1) != Some(&end.1) {
+                    unmapped.push(format!(
+                        "{} in a different time zone than DTSTART",
+                        line.name
+                    ));
+                }
+                object.insert("duration".to_string(), json!(format_duration(duration)));
+                &["TZID", "VALUE"]
+            }
+            "DURATION" => {
+                object.insert("duration".to_string(), json!(line.value));
+                &[]
+            }
+            "DUE" if is_task => {
+                let (local, time_zone, is_date) = local_date_time(line);
+                // showWithoutTime applies to both start and due
+                match start.map(|start| local_date_time(start).2) {
+                    None if is_date => {
+                        object.insert("showWithoutTime".to_string(), json!(true));
+                    }
+                    Some(start_is_date) if start_is_date != is_date => unmapped.push(format!(
+                        "{} with a different value type than DTSTART",
+                        line.name
+                    )),
+                    _ => (),
+                }
+                object.insert("due".to_string(), json!(local));
+                if let Some(time_zone) = time_zone {
+                    object.entry("timeZone").or_insert(json!(time_zone));
+                }
+                &["TZID", "VALUE"]
+            }
+            "RECURRENCE-ID" => {
+                let (local, time_zone, _) = local_date_time(line);
+                object.insert("recurrenceId".to_string(), json!(local));
+                // null for a floating time
+                if time_zone != start.and_then(|start| local_date_time(start).1) {
+                    object.insert("recurrenceIdTimeZone".to_string(), json!(time_zone));
+                }
+                &["TZID", "VALUE"]
+            }
+            "RRULE" | "EXRULE" => {
+                let member = if line.name_eq("RRULE") {
+                    "recurrenceRules"
+                } else {
+                    "excludedRecurrenceRules"
+                };
+                let rule = rule_to_jscalendar(&line.value, unmapped);
+                object
+                    .entry(member)
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                    .as_array_mut()
+                    .unwrap()
+                    .push(rule);
+                &[]
+            }
+            "RDATE" | "EXDATE" => {
+                if ValueType::of(line) == ValueType::Period {
+                    unmapped.push(format!("{} with PERIOD values", line.name));
+                    continue;
+                }
+                let patch = if line.name_eq("EXDATE") {
+                    json!({ "excluded": true })
+                } else {
+                    json!({})
+                };
+                let overrides = object
+                    .entry("recurrenceOverrides")
+                    .or_insert_with(|| Value::Object(Map::new()))
+                    .as_object_mut()
+                    .unwrap();
+                for value in split_unescaped(&line.value, ',') {
+                    let single =
+                        ContentLine::new(line.name.clone(), line.params.clone(), value.to_string());
+                    overrides.insert(local_date_time(&single).0, patch.clone());
+                }
+                &["TZID", "VALUE"]
+            }
+            "STATUS" => {
+                let member = if is_task { "progress" } else { "status" };
+                object.insert(member.to_string(), json!(line.value.to_ascii_lowercase()));
+                &[]
+            }
+            "TRANSP" if !is_task => {
+                let status = if line.value.eq_ignore_ascii_case("TRANSPARENT") {
+                    "free"
+                } else {
+                    "busy"
+                };
+                object.insert("freeBusyStatus".to_string(), json!(status));
+                &[]
+            }
+            "CLASS" => {
+                let privacy = match line.value.to_ascii_uppercase().as_str() {
+                    "PUBLIC" => "public",
+                    "PRIVATE" => "private",
+                    "CONFIDENTIAL" => "secret",
+                    _ => {
+                        unmapped.push(format!("CLASS:{}", line.value));
+                        continue;
+                    }
+                };
+                object.insert("privacy".to_string(), json!(privacy));
+                &[]
+            }
+            "CATEGORIES" => {
+                let keywords = object
+                    .entry("keywords")
+                    .or_insert_with(|| Value::Object(Map::new()))
+                    .as_object_mut()
+                    .unwrap();
+                for category in split_unescaped(&line.value, ',') {
+                    keywords.insert(unescape_text(category), json!(true));
+                }
+                &[]
+            }
+            "COLOR" => {
+                object.insert("color".to_string(), json!(line.value));
+                &[]
+            }
+            "ORGANIZER" | "ATTENDEE" => {
+                participant_to_jscalendar(&mut object, line);
+                &["CN", "CUTYPE", "PARTSTAT", "ROLE", "RSVP"]
+            }
+            _ => {
+                unmapped.push(format!("{} property {}", component.object_type, line.name));
+                continue;
+            }
+        };
+        for param in &line.params {
+            if !handled_params.iter().any(|name| param.name_eq(name)) {
+                unmapped.push(format!("{} param {}", line.name, param.name()));
+            }
+        }
+    }
+
+    let title = object
+        .get("title")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    for sub_object in &component.sub_objects {
+        if sub_object.is_type("VALARM") {
+            let alert = alert_to_jscalendar(sub_object, title.clone(), unmapped)?;
+            let alerts = object
+                .entry("alerts")
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .unwrap();
+            let id = (alerts.len() + 1).to_string();
+            alerts.insert(id, alert);
+        } else {
+            unmapped.push(format!("component {}", sub_object.object_type));
+        }
+    }
+    Ok(object)
+}
+
+// participants are keyed by their position, an ORGANIZER that is also an
+// ATTENDEE is a single participant with both roles
+fn participant_to_jscalendar(object: &mut Map<String, Value>, line: &ContentLine) {
+    let is_organizer = line.name_eq("ORGANIZER");
+    if is_organizer {
+        object.insert("replyTo".to_string(), json!({ "imip": line.value }));
+    }
+    let participants = object
+        .entry("participants")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .unwrap();
+    let same_address = |participant: &Value| {
+        participant["sendTo"]["imip"]
+            .as_str()
+            .is_some_and(|address| address.eq_ignore_ascii_case(&line.value))
+    };
+    let id = match participants
+        .iter()
+        .find(|(_, participant)| same_address(participant))
+    {
+        Some((id, _)) => id.clone(),
+        None => (participants.len() + 1).to_string(),
+    };
+    let participant = participants
+        .entry(id)
+        .or_insert_with(|| json!({ "@type": "Participant" }))
+        .as_object_mut()
+        .unwrap();
+    if let Some(name) = first_param(line, "CN") {
+        participant.insert("name".to_string(), json!(name));
+    }
+    if line.value.to_ascii_lowercase().starts_with("mailto:") {
+        participant.insert("email".to_string(), json!(line.value[7..]));
+    }
+    participant.insert("sendTo".to_string(), json!({ "imip": line.value }));
+    let roles = participant
+        .entry("roles")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .unwrap();
+    if is_organizer {
+        roles.insert("owner".to_string(), json!(true));
+        return;
+    }
+    let role_names: &[&str] = match first_param(line, "ROLE")
+        .map(str::to_ascii_uppercase)
+        .as_deref()
+    {
+        Some("CHAIR") => &["attendee", "chair"],
+        Some("OPT-PARTICIPANT") => &["attendee", "optional"],
+        Some("NON-PARTICIPANT") => &["informational"],
+        _ => &["attendee"],
+    };
+    for role in role_names {
+        roles.insert(role.to_string(), json!(true));
+    }
+    if let Some(kind) = first_param(line, "CUTYPE") {
+        participant.insert("kind".to_string(), json!(kind.to_ascii_lowercase()));
+    }
+    if let Some(status) = first_param(line, "PARTSTAT") {
+        participant.insert(
+            "participationStatus".to_string(),
+            json!(status.to_ascii_lowercase()),
+        );
+    }
+    if first_param(line, "RSVP").is_some_and(|rsvp| rsvp.eq_ignore_ascii_case("TRUE")) {
+        participant.insert("expectReply".to_string(), json!(true));
+    }
+}
+
+fn alert_to_jscalendar(
+    alarm: &ICalObject,
+    title: Option<String>,
+    unmapped: &mut Vec<String>,
+) -> Result<Value> {
+    let mut alert = Map::new();
+    alert.insert("@type".to_string(), json!("Alert"));
+    for line in &alarm.properties {
+        match line.name.to_ascii_uppercase().as_str() {
+            "TRIGGER" => {
+                let trigger = if ValueType::of(line) == ValueType::DateTime {
+                    json!({
+                        "@type": "AbsoluteTrigger",
+                        "when": to_extended(ValueType::DateTime, &line.value),
+                    })
+                } else {
+                    let mut trigger = json!({ "@type": "OffsetTrigger", "offset": line.value });
+                    if first_param(line, "RELATED")
+                        .is_some_and(|related| related.eq_ignore_ascii_case("END"))
+                    {
+                        trigger["relativeTo"] = json!("end");
+                    }
+                    trigger
+                };
+                alert.insert("trigger".to_string(), trigger);
+            }
+            "ACTION" => {
+                let action = line.value.to_ascii_lowercase();
+                if action != "display" && action != "email" {
+                    unmapped.push(format!("VALARM ACTION:{}", line.value));
+                }
+                alert.insert("action".to_string(), json!(action));
+            }
+            "ACKNOWLEDGED" => {
+                alert.insert(
+                    "acknowledged".to_string(),
+                    json!(to_extended(ValueType::DateTime, &line.value)),
+                );
+            }
+            // DESCRIPTION and SUMMARY are recreated from the title
+            "DESCRIPTION" | "SUMMARY" if Some(unescape_text(&line.value)) == title => (),
+            _ => unmapped.push(format!("VALARM property {}", line.name)),
+        }
+    }
+    if !alert.contains_key("trigger") {
+        return Err(eyre!("VALARM without TRIGGER"));
+    }
+    Ok(Value::Object(alert))
+}
+
+fn rule_to_jscalendar(value: &str, unmapped: &mut Vec<String>) -> Value {
+    let mut rule = Map::new();
+    rule.insert("@type".to_string(), json!("RecurrenceRule"));
+    for part in value.split(';').filter(|part| !part.is_empty()) {
+        let (name, values) = part.split_once('=').unwrap_or((part, ""));
+        let name = name.to_ascii_uppercase();
+        let Some((_, member)) = RULE_PARTS.iter().find(|(part_name, _)| *part_name == name) else {
+            unmapped.push(format!("RRULE part {}", name));
+            continue;
+        };
+        let numbers = || -> Value {
+            values
+                .split(',')
+                .map(|value| {
+                    value
+                        .parse::<i64>()
+                        .map(Value::from)
+                        .unwrap_or_else(|_| json!(value))
+                })
+                .collect()
+        };
+        let value = match name.as_str() {
+            "FREQ" | "WKST" | "RSCALE" | "SKIP" => json!(values.to_ascii_lowercase()),
+            "INTERVAL" | "COUNT" => values
+                .parse::<i64>()
+                .map(Value::from)
+                .unwrap_or_else(|_| json!(values)),
+            "UNTIL" => {
+                if values.ends_with('Z') {
+                    // converting to the time zone of the start needs time zone data
+                    unmapped.push("the UTC time zone of RRULE UNTIL".to_string());
+                }
+                let until = ContentLine::new(String::new(), Vec::new(), values.to_string());
+                json!(local_date_time(&until).0)
+            }
+            "BYDAY" => values
+                .split(',')
+                .filter_map(|day| {
+                    let Some((nth, weekday)) = parse_by_day(day) else {
+                        unmapped.push(format!("RRULE BYDAY {}", day));
+                        return None;
+                    };
+                    let weekday = WEEKDAYS[weekday as usize].to_ascii_lowercase();
+                    let mut n_day = json!({ "@type": "NDay", "day": weekday });
+                    if nth != 0 {
+                        n_day["nthOfPeriod"] = json!(nth);
+                    }
+                    Some(n_day)
+                })
+                .collect(),
+            "BYMONTH" => values.split(',').map(|month| json!(month)).collect(),
+            _ => numbers(),
+        };
+        rule.insert(member.to_string(), value);
+    }
+    Value::Object(rule)
+}
+
+fn rule_from_jscalendar(rule: &Value, unmapped: &mut Vec<String>) -> Result<String> {
+    let rule = rule
+        .as_object()
+        .ok_or(eyre!("a recurrence rule must be an object"))?;
+    let mut parts = Vec::new();
+    for (member, value) in rule {
+        if member == "@type" {
+            continue;
+        }
+        let Some((name, _)) = RULE_PARTS
+            .iter()
+            .find(|(_, part_member)| part_member == member)
+        else {
+            unmapped.push(format!("recurrence rule member {}", member));
+            continue;
+        };
+        let scalar = |value: &Value| match value {
+            Value::String(value) => value.to_ascii_uppercase(),
+            value => value.to_string(),
+        };
+        let value = match (*name, value) {
+            ("UNTIL", Value::String(until)) => from_extended(ValueType::DateTime, until),
+            ("BYDAY", Value::Array(days)) => days
+                .iter()
+                .map(|day| {
+                    let nth = day["nthOfPeriod"]
+                        .as_i64()
+                        .map(|nth| nth.to_string())
+                        .unwrap_or_default();
+                    format!("{}{}", nth, scalar(&day["day"]))
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            (_, Value::Array(values)) => values.iter().map(scalar).collect::<Vec<_>>().join(","),
+            (_, value) => scalar(value),
+        };
+        parts.push(format!("{}={}", name, value));
+    }
+    // FREQ first, for compatibility with RFC 2445 parsers
+    parts.sort_by_key(|part| !part.starts_with("FREQ="));
+    Ok(parts.join(";"))
+}
+
+/// converts JSCalendar Events and Tasks to a VCALENDAR,
+/// recurrenceOverrides become RDATEs, EXDATEs and components with a RECURRENCE-ID
+pub fn from_jscalendar(values: &[Value]) -> Result<Conversion<ICalObject>> {
+    let mut unmapped = Vec::new();
+    let mut calendar = ICalObject {
+        object_type: "VCALENDAR".to_string(),
+        ..ICalObject::default()
+    };
+    calendar.push_property(ContentLine::plain("VERSION", "2.0".to_string()));
+    calendar.push_property(ContentLine::plain(
+        "PRODID",
+        format!("-//{}//EN", env!("CARGO_PKG_NAME")),
+    ));
+    for value in values {
+        let object = value
+            .as_object()
+            .ok_or(eyre!("a JSCalendar object must be an object"))?;
+        calendar.push_sub_object(component_from_jscalendar(object, &mut unmapped)?);
+        let overrides = object
+            .get("recurrenceOverrides")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten();
+        for (recurrence_id, patch) in overrides {
+            let patch = patch
+                .as_object()
+                .ok_or(eyre!("a recurrence override must be an object"))?;
+            if is_date_patch(patch) {
+                continue;
+            }
+            let mut instance = object.clone();
+            for member in NOT_PATCHABLE.iter().skip(2) {
+                instance.remove(*member);
+            }
+            // the instance starts at its recurrence id unless the patch moves it
+            if let Some(member) = ["start", "due"]
+                .into_iter()
+                .find(|member| object.contains_key(*member))
+            {
+                instance.insert(member.to_string(), json!(recurrence_id));
+            }
+            if let Some(time_zone) = object.get("timeZone") {
+                instance.insert("recurrenceIdTimeZone".to_string(), time_zone.clone());
+            }
+            apply_patch(&mut instance, patch)?;
+            instance.insert("recurrenceId".to_string(), json!(recurrence_id));
+            calendar.push_sub_object(component_from_jscalendar(&instance, &mut unmapped)?);
+        }
+    }
+    Ok(Conversion {
+        value: calendar,
+        unmapped,
+    })
+}
+
+// overrides that only add an occurrence or exclude one, whatever else
+// they patch, become RDATEs and EXDATEs
+fn is_date_patch(patch: &Map<String, Value>) -> bool {
+    patch.is_empty() || patch.get("excluded") == Some(&json!(true))
+}
+
+// applies a PatchObject, see RFC 8984 section 1.4.9
+fn apply_patch(object: &mut Map<String, Value>, patch: &Map<String, Value>) -> Result<()> {
+    for (pointer, value) in patch {
+        // ~1 before ~0, so that ~01 becomes ~1 and not /
+        let mut path: Vec<String> = pointer
+            .trim_start_matches('/')
+            .split('/')
+            .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+            .collect();
+        let last = path.pop().unwrap_or_default();
+        let mut target = &mut *object;
+        for segment in path {
+            target = target
+                .entry(segment)
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .ok_or(eyre!(
+                    "patch {} goes through a value that is not an object",
+                    pointer
+                ))?;
+        }
+        if value.is_null() {
+            target.remove(&last);
+        } else {
+            target.insert(last, value.clone());
+        }
+    }
+    Ok(())
+}
+
+fn component_from_jscalendar(
+    object: &Map<String, Value>,
+    unmapped: &mut Vec<String>,
+) -> Result<ICalObject> {
+    let object_type = match object.get("@type").and_then(Value::as_str) {
+        Some("Event") => "VEVENT",
+        Some("Task") => "VTODO",
+        object_type => return Err(eyre!("cannot convert a JSCalendar {:?}", object_type)),
+    };
+    let mut component = ICalObject {
+        object_type: object_type.to_string(),
+        ..ICalObject::default()
+    };
+    let text = |member: &str| object.get(member).and_then(Value::as_str);
+    let number = |member: &str| object.get(member).and_then(Value::as_i64);
+    let entries = |member: &str| {
+        object
+            .get(member)
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+    };
+    let time_zone = text("timeZone");
+    let is_date = object.get("showWithoutTime") == Some(&json!(true));
+
+    let uid = text("uid").ok_or(eyre!("a JSCalendar object must have a uid"))?;
+    component.push_property(ContentLine::plain("UID", uid.to_string()));
+    if let Some(updated) = text("updated") {
+        component.push_property(ContentLine::plain(
+            "DTSTAMP",
+            from_extended(ValueType::DateTime, updated),
+        ));
+    }
+    if let Some(created) = text("created") {
+        component.push_property(ContentLine::plain(
+            "CREATED",
+            from_extended(ValueType::DateTime, created),
+        ));
+    }
+    if let Some(sequence) = number("sequence") {
+        component.push_property(ContentLine::plain("SEQUENCE", sequence.to_string()));
+    }
+    if let Some(title) = text("title") {
+        component.push_property(ContentLine::plain("SUMMARY", escape_text(title)));
+    }
+    if let Some(description) = text("description") {
+        component.push_property(ContentLine::plain("DESCRIPTION", escape_text(description)));
+    }
+    for (i, (id, location)) in entries("locations").enumerate() {
+        if i > 0 {
+            unmapped.push(format!("location {}", id));
+            continue;
+        }
+        if let Some(name) = location["name"].as_str() {
+            component.push_property(ContentLine::plain("LOCATION", escape_text(name)));
+        }
+        if let Some(coordinates) = location["coordinates"].as_str() {
+            let coordinates = coordinates.trim_start_matches("geo:");
+            component.push_property(ContentLine::plain("GEO", coordinates.replacen(',', ";", 1)));
+        }
+    }
+    for (i, (id, link)) in entries("links").enumerate() {
+        match link["href"].as_str() {
+            Some(href) if i == 0 => {
+                component.push_property(ContentLine::plain("URL", href.to_string()))
+            }
+            _ => unmapped.push(format!("link {}", id)),
+        }
+    }
+    if let Some(start) = text("start") {
+        component.push_property(date_time_line("DTSTART", start, time_zone, is_date));
+    }
+    if let Some(duration) = text("duration") {
+        component.push_property(ContentLine::plain("DURATION", duration.to_string()));
+    }
+    if let Some(due) = text("due") {
+        component.push_property(date_time_line("DUE", due, time_zone, is_date));
+    }
+    if let Some(recurrence_id) = text("recurrenceId") {
+        component.push_property(date_time_line(
+            "RECURRENCE-ID",
+            recurrence_id,
+            match object.get("recurrenceIdTimeZone") {
+                Some(time_zone) => time_zone.as_str(),
+                None => time_zone,
+            },
+            is_date,
+        ));
+    }
+    for (member, name) in [
+        ("recurrenceRules", "RRULE"),
+        ("excludedRecurrenceRules", "EXRULE"),
+    ] {
+        for rule in object
+            .get(member)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            component.push_property(ContentLine::plain(
+                name,
+                rule_from_jscalendar(rule, unmapped)?,
+            ));
+        }
+    }
+    let (mut rdates, mut exdates) = (Vec::new(), Vec::new());
+    for (recurrence_id, patch) in entries("recurrenceOverrides") {
+        match patch.as_object() {
+            Some(patch) if patch.is_empty() => rdates.push(recurrence_id),
+            Some(patch) if is_date_patch(patch) => {
+                for member in patch.keys().filter(|member| *member != "excluded") {
+                    unmapped.push(format!("member {} of an excluded occurrence", member));
+                }
+                exdates.push(recurrence_id)
+            }
+            _ => (),
+        }
+    }
+    for (name, dates) in [("RDATE", rdates), ("EXDATE", exdates)] {
+        let lines: Vec<ContentLine> = dates
+            .into_iter()
+            .map(|date| date_time_line(name, date, time_zone, is_date))
+            .collect();
+        if let Some(first) = lines.first() {
+            let values: Vec<&str> = lines.iter().map(|line| line.value.as_str()).collect();
+            component.push_property(ContentLine::new(
+                name.to_string(),
+                first.params.clone(),
+                values.join(","),
+            ));
+        }
+    }
+    if let Some(status) = text("status").or(text("progress")) {
+        component.push_property(ContentLine::plain("STATUS", status.to_ascii_uppercase()));
+    }
+    if let Some(status) = text("freeBusyStatus") {
+        let transp = if status == "free" {
+            "TRANSPARENT"
+        } else {
+            "OPAQUE"
+        };
+        component.push_property(ContentLine::plain("TRANSP", transp.to_string()));
+    }
+    if let Some(privacy) = text("privacy") {
+        let class = match privacy {
+            "secret" => "CONFIDENTIAL".to_string(),
+            privacy => privacy.to_ascii_uppercase(),
+        };
+        component.push_property(ContentLine::plain("CLASS", class));
+    }
+    if let Some(priority) = number("priority") {
+        component.push_property(ContentLine::plain("PRIORITY", priority.to_string()));
+    }
+    if let Some(percent) = number("percentComplete") {
+        component.push_property(ContentLine::plain("PERCENT-COMPLETE", percent.to_string()));
+    }
+    let keywords: Vec<String> = entries("keywords")
+        .filter(|(_, value)| **value == json!(true))
+        .map(|(keyword, _)| escape_text(keyword))
+        .collect();
+    if !keywords.is_empty() {
+        component.push_property(ContentLine::plain("CATEGORIES", keywords.join(",")));
+    }
+    if let Some(color) = text("color") {
+        component.push_property(ContentLine::plain("COLOR", color.to_string()));
+    }
+    participants_from_jscalendar(&mut component, object, unmapped);
+    for (_, alert) in entries("alerts") {
+        component.push_sub_object(alert_from_jscalendar(alert, text("title"), unmapped)?);
+    }
+    for member in object.keys() {
+        if !KNOWN_MEMBERS.contains(&member.as_str()) {
+            unmapped.push(format!("member {}", member));
+        }
+    }
+    Ok(component)
+}
+
+fn participants_from_jscalendar(
+    component: &mut ICalObject,
+    object: &Map<String, Value>,
+    unmapped: &mut Vec<String>,
+) {
+    let mut organizer = None;
+    let mut attendees = Vec::new();
+    for (id, participant) in object
+        .get("participants")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+    {
+        let address = participant["sendTo"]["imip"]
+            .as_str()
+            .map(str::to_string)
+            .or_else(|| {
+                participant["email"]
+                    .as_str()
+                    .map(|email| format!("mailto:{}", email))
+            });
+        let Some(address) = address else {
+            unmapped.push(format!("participant {} without an address", id));
+            continue;
+        };
+        let has_role = |role: &str| participant["roles"][role] == json!(true);
+        let mut params = Vec::new();
+        if let Some(name) = participant["name"].as_str() {
+            params.push(Param::new("CN".to_string(), vec![name.to_string()]));
+        }
+        if has_role("owner") {
+            organizer = Some(ContentLine::new(
+                "ORGANIZER".to_string(),
+                params.clone(),
+                address.clone(),
+            ));
+        }
+        let is_attendee = ["attendee", "chair", "optional", "informational"]
+            .iter()
+            .any(|role| has_role(role));
+        if !is_attendee && has_role("owner") {
+            continue;
+        }
+        if let Some(kind) = participant["kind"].as_str() {
+            params.push(Param::new(
+                "CUTYPE".to_string(),
+                vec![kind.to_ascii_uppercase()],
+            ));
+        }
+        let role = if has_role("chair") {
+            Some("CHAIR")
+        } else if has_role("optional") {
+            Some("OPT-PARTICIPANT")
+        } else if has_role("informational") && !has_role("attendee") {
+            Some("NON-PARTICIPANT")
+        } else {
+            None
+        };
+        if let Some(role) = role {
+            params.push(Param::new("ROLE".to_string(), vec![role.to_string()]));
+        }
+        if let Some(status) = participant["participationStatus"].as_str() {
+            params.push(Param::new(
+                "PARTSTAT".to_string(),
+                vec![status.to_ascii_uppercase()],
+            ));
+        }
+        if participant["expectReply"] == json!(true) {
+            params.push(Param::new("RSVP".to_string(), vec!["TRUE".to_string()]));
+        }
+        attendees.push(ContentLine::new("ATTENDEE".to_string(), params, address));
+    }
+    let reply_to = object
+        .get("replyTo")
+        .and_then(|reply_to| reply_to["imip"].as_str())
+        .map(|address| ContentLine::plain("ORGANIZER", address.to_string()));
+    if let Some(organizer) = organizer.or(reply_to) {
+        component.push_property(organizer);
+    }
+    for attendee in attendees {
+        component.push_property(attendee);
+    }
+}
+
+fn alert_from_jscalendar(
+    alert: &Value,
+    title: Option<&str>,
+    unmapped: &mut Vec<String>,
+) -> Result<ICalObject> {
+    let mut alarm = ICalObject {
+        object_type: "VALARM".to_string(),
+        ..ICalObject::default()
+    };
+    let is_email = alert["action"] == json!("email");
+    let action = if is_email { "EMAIL" } else { "DISPLAY" };
+    alarm.push_property(ContentLine::plain("ACTION", action.to_string()));
+    let trigger = &alert["trigger"];
+    let trigger = match trigger["@type"].as_str() {
+        Some("AbsoluteTrigger") => ContentLine::new(
+            "TRIGGER".to_string(),
+            vec![Param::new(
+                "VALUE".to_string(),
+                vec!["DATE-TIME".to_string()],
+            )],
+            from_extended(
+                ValueType::DateTime,
+                trigger["when"].as_str().unwrap_or_default(),
+            ),
+        ),
+        _ => {
+            let offset = trigger["offset"]
+                .as_str()
+                .ok_or(eyre!("an alert needs an offset or absolute trigger"))?;
+            let params = if trigger["relativeTo"] == json!("end") {
+                vec![Param::new("RELATED".to_string(), vec!["END".to_string()])]
+            } else {
+                Vec::new()
+            };
+            ContentLine::new("TRIGGER".to_string(), params, offset.to_string())
+        }
+    };
+    alarm.push_property(trigger);
+    // DISPLAY and EMAIL alarms require a DESCRIPTION, EMAIL alarms a SUMMARY too
+    let description = escape_text(title.unwrap_or("Reminder"));
+    alarm.push_property(ContentLine::plain("DESCRIPTION", description.clone()));
+    if is_email {
+        alarm.push_property(ContentLine::plain("SUMMARY", description));
+    }
+    if let Some(acknowledged) = alert["acknowledged"].as_str() {
+        alarm.push_property(ContentLine::plain(
+            "ACKNOWLEDGED",
+            from_extended(ValueType::DateTime, acknowledged),
+        ));
+    }
+    for member in alert.as_object().into_iter().flat_map(Map::keys) {
+        if !["@type", "action", "trigger", "acknowledged"].contains(&member.as_str()) {
+            unmapped.push(format!("alert member {}", member));
+        }
+    }
+    Ok(alarm)
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{apply_patch, from_jscalendar, patch_between, to_jscalendar};
+    use crate::ICalObject;
+
+    #[test]
+    fn converts_both_ways() {
+        let ical: ICalObject = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//elikoga-ical-rs//EN\r
+BEGIN:VEVENT\r
+UID:weekly@example.com\r
+DTSTAMP:20220101T090000Z\r
+SUMMARY:Team meeting\r
+LOCATION:Room 1\\, second floor\r
+DTSTART;TZID=Europe/Berlin:20220103T100000\r
+DURATION:PT1H\r
+RRULE:FREQ=WEEKLY;COUNT=10;BYDAY=MO,-1FR\r
+EXDATE;TZID=Europe/Berlin:20220110T100000\r
+STATUS:CONFIRMED\r
+ORGANIZER;CN=Alice:mailto:alice@example.com\r
+ATTENDEE;CN=Alice;ROLE=CHAIR;PARTSTAT=ACCEPTED:mailto:alice@example.com\r
+ATTENDEE;CN=Bob;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:bob@example.com\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT15M\r
+DESCRIPTION:Team meeting\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:weekly@example.com\r
+DTSTAMP:20220101T090000Z\r
+SUMMARY:Team meeting (moved)\r
+LOCATION:Room 1\\, second floor\r
+DTSTART;TZID=Europe/Berlin:20220117T140000\r
+DURATION:PT1H\r
+RECURRENCE-ID;TZID=Europe/Berlin:20220117T100000\r
+STATUS:CONFIRMED\r
+ORGANIZER;CN=Alice:mailto:alice@example.com\r
+ATTENDEE;CN=Alice;ROLE=CHAIR;PARTSTAT=ACCEPTED:mailto:alice@example.com\r
+ATTENDEE;CN=Bob;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:bob@example.com\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT15M\r
+DESCRIPTION:Team meeting (moved)\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VTODO\r
+UID:task@example.com\r
+SUMMARY:Write the minutes\r
+DUE;VALUE=DATE:20220104\r
+STATUS:IN-PROCESS\r
+PERCENT-COMPLETE:50\r
+END:VTODO\r
+END:VCALENDAR\r
+"
+        .parse()
+        .unwrap();
+        let converted = to_jscalendar(&ical).unwrap();
+        assert_eq!(converted.unmapped, Vec::<String>::new());
+        assert_eq!(
+            converted.value,
+            [
+                json!({
+                    "@type": "Event",
+                    "uid": "weekly@example.com",
+                    "updated": "2022-01-01T09:00:00Z",
+                    "title": "Team meeting",
+                    "locations": {"1": {"@type": "Location", "name": "Room 1, second floor"}},
+                    "start": "2022-01-03T10:00:00",
+                    "timeZone": "Europe/Berlin",
+                    "duration": "PT1H",
+                    "recurrenceRules": [{
+                        "@type": "RecurrenceRule",
+                        "frequency": "weekly",
+                        "count": 10,
+                        "byDay": [
+                            {"@type": "NDay", "day": "mo"},
+                            {"@type": "NDay", "day": "fr", "nthOfPeriod": -1}
+                        ]
+                    }],
+                    "recurrenceOverrides": {
+                        "2022-01-10T10:00:00": {"excluded": true},
+                        "2022-01-17T10:00:00": {
+                            "title": "Team meeting (moved)",
+                            "start": "2022-01-17T14:00:00"
+                        }
+                    },
+                    "status": "confirmed",
+                    "replyTo": {"imip": "mailto:alice@example.com"},
+                    "participants": {
+                        "1": {
+                            "@type": "Participant",
+                            "name": "Alice",
+                            "email": "alice@example.com",
+                            "sendTo": {"imip": "mailto:alice@example.com"},
+                            "roles": {"owner": true, "attendee": true, "chair": true},
+                            "participationStatus": "accepted"
+                        },
+                        "2": {
+                            "@type": "Participant",
+                            "name": "Bob",
+                            "email": "bob@example.com",
+                            "sendTo": {"imip": "mailto:bob@example.com"},
+                            "roles": {"attendee": true},
+                            "participationStatus": "needs-action",
+                            "expectReply": true
+                        }
+                    },
+                    "alerts": {"1": {
+                        "@type": "Alert",
+                        "action": "display",
+                        "trigger": {"@type": "OffsetTrigger", "offset": "-PT15M"}
+                    }}
+                }),
+                json!({
+                    "@type": "Task",
+                    "uid": "task@example.com",
+                    "title": "Write the minutes",
+                    "showWithoutTime": true,
+                    "due": "2022-01-04T00:00:00",
+                    "progress": "in-process",
+                    "percentComplete": 50
+                })
+            ]
+        );
+        let back = from_jscalendar(&converted.value).unwrap();
+        assert_eq!(back.unmapped, Vec::<String>::new());
+        assert_eq!(back.value, ical);
+    }
+
+    #[test]
+    fn reports_what_can_not_be_mapped() {
+        let ical: ICalObject = "BEGIN:VEVENT\r
+UID:1@example.com\r
+SUMMARY;LANGUAGE=de:Besprechung\r
+SEQUENCE:one\r
+DTSTART:20220103T100000Z\r
+DTEND:20220104T113000Z\r
+RRULE:FREQ=WEEKLY;BYDAY=aéM,MO\r
+X-CUSTOM:value\r
+BEGIN:VALARM\r
+ACTION:AUDIO\r
+TRIGGER;RELATED=END:PT0S\r
+END:VALARM\r
+END:VEVENT\r
+"
+        .parse()
+        .unwrap();
+        let converted = to_jscalendar(&ical).unwrap();
+        assert_eq!(
+            converted.unmapped,
+            [
+                "SUMMARY param LANGUAGE",
+                "SEQUENCE:one",
+                "RRULE BYDAY aéM",
+                "VEVENT property X-CUSTOM",
+                "VALARM ACTION:AUDIO"
+            ]
+        );
+        let event = &converted.value[0];
+        assert_eq!(event["timeZone"], "Etc/UTC");
+        assert_eq!(event["duration"], "P1DT1H30M");
+        assert_eq!(
+            event["recurrenceRules"][0]["byDay"],
+            json!([{"@type": "NDay", "day": "mo"}])
+        );
+        assert_eq!(event["alerts"]["1"]["trigger"]["relativeTo"], "end");
+        assert!(to_jscalendar(&"BEGIN:VJOURNAL\r\nEND:VJOURNAL\r\n".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn escapes_patch_pointers() {
+        let master = json!({"keywords": {"a/b": true}, "x~y": 1});
+        let instance = json!({"keywords": {"a/b": true}, "x~y": 2, "c/d": 3});
+        let patch = patch_between(master.as_object().unwrap(), instance.as_object().unwrap());
+        assert_eq!(Value::Object(patch.clone()), json!({"x~0y": 2, "c~1d": 3}));
+        let mut patched = master.as_object().unwrap().clone();
+        apply_patch(&mut patched, &patch).unwrap();
+        assert_eq!(Value::Object(patched.clone()), instance);
+        let removal = json!({"keywords/a~1b": null, "keywords/~01": true});
+        apply_patch(&mut patched, removal.as_object().unwrap()).unwrap();
+        assert_eq!(patched["keywords"], json!({"~1": true}));
+    }
+
+    #[test]
+    fn applies_overrides() {
+        let event = json!({
+            "@type": "Event",
+            "uid": "daily@example.com",
+            "title": "Standup",
+            "start": "2024-01-01T10:00:00",
+            "timeZone": "Etc/UTC",
+            "recurrenceRules": [{"@type": "RecurrenceRule", "frequency": "daily"}],
+            "recurrenceOverrides": {
+                "2024-01-02T10:00:00": {"title": "renamed"},
+                "2024-01-03T10:00:00": {"excluded": true, "title": "gone"}
+            }
+        });
+        let converted = from_jscalendar(&[event]).unwrap();
+        assert_eq!(
+            converted.unmapped,
+            ["member title of an excluded occurrence"]
+        );
+        let calendar = converted.value.to_string();
+        assert!(calendar.contains("EXDATE:20240103T100000Z\r\n"));
+        assert!(calendar.contains(
+            "UID:daily@example.com\r\nSUMMARY:renamed\r\nDTSTART:20240102T100000Z\r\n\
+             RECURRENCE-ID:20240102T100000Z\r\n"
+        ));
+        assert!(!calendar.contains("SUMMARY:gone"));
+
+        // DTEND needs a DTSTART to become a duration
+        let ical = "BEGIN:VEVENT\r\nUID:1\r\nDTEND:20240101T100000Z\r\nEND:VEVENT\r\n";
+        let converted = to_jscalendar(&ical.parse().unwrap()).unwrap();
+        assert_eq!(converted.unmapped, ["DTEND without a valid DTSTART"]);
+    }
+}
@@ -1,11 +1,44 @@
 //! [ICalObject] implements FromStr and Display, see its docs and its source
 
+pub mod availability;
+#[cfg(feature = "caldav")]
+pub mod caldav_data;
+#[cfg(feature = "caldav")]
+pub mod caldav_filter;
+#[cfg(feature = "caldav")]
+pub mod caldav_server;
+pub mod canonical;
+pub mod conflicts;
 pub mod content_line;
+pub mod cst;
+pub mod datetime;
 pub mod fold;
+pub mod freebusy;
 pub mod ical_object;
+pub mod imip;
+pub mod interval_tree;
+pub mod itip;
+#[cfg(feature = "jcal")]
+pub mod jcal;
+#[cfg(feature = "jscalendar")]
+pub mod jscalendar;
+pub mod recurrence;
+pub mod time_index;
+pub mod timezone;
 pub mod unfold;
+pub mod value;
+pub mod vcalendar;
+pub mod vcard;
+pub mod vcard_legacy;
+pub mod writer;
+#[cfg(feature = "xcal")]
+pub mod xcal;
+#[cfg(any(feature = "xcal", feature = "caldav"))]
+mod xml;
 
 pub use content_line::{ContentLine, Param};
-pub use fold::fold;
-pub use ical_object::ICalObject;
+pub use fold::{fold, FoldOptions};
+pub use ical_object::{Child, ChildRef, Children, ICalObject, ParseOptions};
 pub use unfold::Unfold;
+pub use vcard::VCard;
+pub use writer::ICalWriter;
@@ -0,0 +1,1051 @@
+// recurrence rules and the instances of recurring components,
+// see https://icalendar.org/iCalendar-RFC-5545/3-3-10-recurrence-rule.html
+// and https://icalendar.org/iCalendar-RFC-5545/3-8-5-3-recurrence-rule.html
+//
+// occurrences are computed in the local time of DTSTART, a period of the
+// frequency at a time: its days are limited or expanded by the BYxxx parts,
+// combined with the times of the period, then BYSETPOS picks from the result
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::str::FromStr;
+
+use eyre::{eyre, Result};
+
+use crate::datetime::{days_in_month, is_leap_year, parse_duration, DateTime};
+use crate::timezone::{TimeZone, TimeZones};
+use crate::{ContentLine, ICalObject};
+
+pub(crate) const WEEKDAYS: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+// occurrences after this year are not computed, DATE-TIME values have four
+// digit years
+pub(crate) const LAST_YEAR: i64 = 9999;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Frequency {
+    Secondly,
+    Minutely,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    pub fn name(self) -> &'static str {
+        match self {
+            Frequency::Secondly => "SECONDLY",
+            Frequency::Minutely => "MINUTELY",
+            Frequency::Hourly => "HOURLY",
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        }
+    }
+
+    // the length of a period in seconds, for the frequencies below DAILY
+    fn seconds(self) -> Option<i64> {
+        match self {
+            Frequency::Secondly => Some(1),
+            Frequency::Minutely => Some(60),
+            Frequency::Hourly => Some(3600),
+            _ => None,
+        }
+    }
+}
+
+/// a parsed RECUR value, weekdays are numbered like [DateTime::weekday]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    /// UNTIL without its trailing `Z`, see `until_is_utc`
+    pub until: Option<DateTime>,
+    pub until_is_utc: bool,
+    pub by_second: Vec<u32>,
+    pub by_minute: Vec<u32>,
+    pub by_hour: Vec<u32>,
+    /// the ordinal, 0 for every such weekday of the period, and the weekday
+    pub by_day: Vec<(i32, u32)>,
+    pub by_month_day: Vec<i32>,
+    pub by_year_day: Vec<i32>,
+    pub by_week_no: Vec<i32>,
+    pub by_month: Vec<u32>,
+    pub by_set_pos: Vec<i32>,
+    pub week_start: u32,
+}
+
+impl FromStr for RecurrenceRule {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self> {
+        fn list<T: FromStr>(name: &str, value: &str, valid: impl Fn(&T) -> bool) -> Result<Vec<T>> {
+            value
+                .split(',')
+                .map(|item| {
+                    item.parse()
+                        .ok()
+                        .filter(&valid)
+                        .ok_or_else(|| eyre!("invalid {} in RRULE: {}", name, item))
+                })
+                .collect()
+        }
+        fn weekday(value: &str) -> Result<u32> {
+            WEEKDAYS
+                .iter()
+                .position(|day| day.eq_ignore_ascii_case(value))
+                .map(|day| day as u32)
+                .ok_or_else(|| eyre!("invalid weekday in RRULE: {}", value))
+        }
+
+        let mut frequency = None;
+        let mut rule = RecurrenceRule {
+            frequency: Frequency::Yearly,
+            interval: 1,
+            count: None,
+            until: None,
+            until_is_utc: false,
+            by_second: Vec::new(),
+            by_minute: Vec::new(),
+            by_hour: Vec::new(),
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_year_day: Vec::new(),
+            by_week_no: Vec::new(),
+            by_month: Vec::new(),
+            by_set_pos: Vec::new(),
+            week_start: 0,
+        };
+        let ordinal = |max: i32| move |n: &i32| *n != 0 && n.abs() <= max;
+        for part in value.trim().split(';') {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| eyre!("invalid part in RRULE: {}", part))?;
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "SECONDLY" => Frequency::Secondly,
+                        "MINUTELY" => Frequency::Minutely,
+                        "HOURLY" => Frequency::Hourly,
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(eyre!("invalid FREQ in RRULE: {}", value)),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| *interval > 0)
+                        .ok_or_else(|| eyre!("invalid INTERVAL in RRULE: {}", value))?
+                }
+                "COUNT" => {
+                    rule.count = Some(
+                        value
+                            .parse()
+                            .map_err(|_| eyre!("invalid COUNT in RRULE: {}", value))?,
+                    )
+                }
+                "UNTIL" => {
+                    rule.until = Some(DateTime::parse(value)?);
+                    rule.until_is_utc = value.ends_with('Z');
+                }
+                "BYSECOND" => rule.by_second = list(name, value, |n| *n <= 60)?,
+                "BYMINUTE" => rule.by_minute = list(name, value, |n| *n <= 59)?,
+                "BYHOUR" => rule.by_hour = list(name, value, |n| *n <= 23)?,
+                "BYDAY" => {
+                    rule.by_day = value
+                        .split(',')
+                        .map(|item| {
+                            parse_by_day(item)
+                                .filter(|(number, _)| number.abs() <= 53)
+                                .ok_or_else(|| eyre!("invalid BYDAY in RRULE: {}", item))
+                        })
+                        .collect::<Result<_>>()?
+                }
+                "BYMONTHDAY" => rule.by_month_day = list(name, value, ordinal(31))?,
+                "BYYEARDAY" => rule.by_year_day = list(name, value, ordinal(366))?,
+                "BYWEEKNO" => rule.by_week_no = list(name, value, ordinal(53))?,
+                "BYMONTH" => rule.by_month = list(name, value, |n| (1..=12).contains(n))?,
+                "BYSETPOS" => rule.by_set_pos = list(name, value, ordinal(366))?,
+                "WKST" => rule.week_start = weekday(value)?,
+                // unknown parts are x-names or from later extensions
+                _ => {}
+            }
+        }
+        rule.frequency = frequency.ok_or_else(|| eyre!("RRULE without FREQ: {}", value))?;
+        if rule.count.is_some() && rule.until.is_some() {
+            return Err(eyre!("RRULE with both COUNT and UNTIL: {}", value));
+        }
+        Ok(rule)
+    }
+}
+
+/// a BYDAY item like `MO` or `-1FR` as its ordinal, 0 if it has none,
+/// and its weekday, numbered like [DateTime::weekday]
+pub fn parse_by_day(item: &str) -> Option<(i32, u32)> {
+    // the weekday is the last two bytes, which must not split a character
+    let split = item.len().checked_sub(2)?;
+    let (number, day) = (item.get(..split)?, item.get(split..)?);
+    let day = WEEKDAYS
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(day))?;
+    let number = match number {
+        "" => 0,
+        number => number.parse().ok().filter(|number| *number != 0)?,
+    };
+    Some((number, day as u32))
+}
+
+impl RecurrenceRule {
+    /// the occurrences of the rule for a DTSTART in order, computed lazily,
+    /// DTSTART itself is only included if it matches the rule
+    pub fn occurrences(&self, start: DateTime) -> Occurrences {
+        let mut rule = self.clone();
+        // the parts that default to DTSTART, see the note on BYxxx in RFC 5545
+        let no_days = rule.by_week_no.is_empty()
+            && rule.by_year_day.is_empty()
+            && rule.by_month_day.is_empty()
+            && rule.by_day.is_empty();
+        match rule.frequency {
+            Frequency::Yearly if no_days => {
+                if rule.by_month.is_empty() {
+                    rule.by_month = vec![start.month];
+                }
+                rule.by_month_day = vec![start.day as i32];
+            }
+            Frequency::Monthly if no_days => rule.by_month_day = vec![start.day as i32],
+            Frequency::Weekly if rule.by_day.is_empty() => rule.by_day = vec![(0, start.weekday())],
+            _ => {}
+        }
+        if rule.frequency > Frequency::Hourly && rule.by_hour.is_empty() {
+            rule.by_hour = vec![start.hour];
+        }
+        if rule.frequency > Frequency::Minutely && rule.by_minute.is_empty() {
+            rule.by_minute = vec![start.minute];
+        }
+        if rule.frequency > Frequency::Secondly && rule.by_second.is_empty() {
+            rule.by_second = vec![start.second];
+        }
+        Occurrences {
+            rule,
+            start,
+            since: start,
+            period: 0,
+            pending: VecDeque::new(),
+            emitted: 0,
+            done: false,
+        }
+    }
+
+    // the occurrences of a period in order, None after LAST_YEAR
+    fn period(&self, start: DateTime, index: i64) -> Option<Vec<DateTime>> {
+        let step = index * self.interval as i64;
+        let date = |year, month, day| DateTime::new(year, month, day, 0, 0, 0);
+        let add_days = |date: DateTime, days: i64| date.add_seconds(days * 86400);
+        let start_date = date(start.year, start.month, start.day);
+        let mut time = None;
+        let days: Vec<DateTime> = match self.frequency {
+            Frequency::Yearly => {
+                let year = start.year + step;
+                if year > LAST_YEAR {
+                    return None;
+                }
+                // only the days of BYMONTH can match
+                if self.by_week_no.is_empty() && !self.by_month.is_empty() {
+                    let months = sorted(&self.by_month);
+                    return Some(
+                        self.candidates(
+                            months
+                                .into_iter()
+                                .flat_map(|month| {
+                                    (1..=days_in_month(year, month))
+                                        .map(move |day| date(year, month, day))
+                                })
+                                .collect(),
+                            year,
+                            None,
+                        ),
+                    );
+                }
+                // with BYWEEKNO the weeks of the year may begin in December
+                let (first, last) = if self.by_week_no.is_empty() {
+                    (date(year, 1, 1), date(year + 1, 1, 1))
+                } else {
+                    (
+                        first_week(year, self.week_start),
+                        first_week(year + 1, self.week_start),
+                    )
+                };
+                let count = (last.timestamp() - first.timestamp()) / 86400;
+                (0..count).map(|day| add_days(first, day)).collect()
+            }
+            Frequency::Monthly => {
+                let months = start.month as i64 - 1 + step;
+                let (year, month) = (start.year + months.div_euclid(12), months.rem_euclid(12));
+                if year > LAST_YEAR {
+                    return None;
+                }
+                let month = month as u32 + 1;
+                (1..=days_in_month(year, month))
+                    .map(|day| date(year, month, day))
+                    .collect()
+            }
+            Frequency::Weekly => {
+                let offset = (start.weekday() + 7 - self.week_start) % 7;
+                let first = add_days(start_date, step * 7 - offset as i64);
+                (0..7).map(|day| add_days(first, day)).collect()
+            }
+            Frequency::Daily => vec![add_days(start_date, step)],
+            frequency => {
+                let seconds = frequency.seconds().unwrap_or(1);
+                let truncated = start.timestamp() - start.timestamp().rem_euclid(seconds);
+                let at = DateTime::from_timestamp(truncated + step * seconds);
+                time = Some(at);
+                vec![date(at.year, at.month, at.day)]
+            }
+        };
+        if days.first().is_some_and(|day| day.year > LAST_YEAR) {
+            return None;
+        }
+        let year = match self.frequency {
+            Frequency::Yearly => start.year + step,
+            _ => days[0].year,
+        };
+        Some(self.candidates(days, year, time))
+    }
+
+    // the occurrences on the days of a period in a year, at the time of
+    // the period for frequencies below DAILY
+    fn candidates(&self, days: Vec<DateTime>, year: i64, time: Option<DateTime>) -> Vec<DateTime> {
+        // the times of a day, the units of the frequency itself are limits
+        let limit = |value: u32, by: &Vec<u32>| {
+            if by.is_empty() || by.contains(&value) {
+                vec![value]
+            } else {
+                Vec::new()
+            }
+        };
+        let (hours, minutes, seconds) = match time {
+            Some(at) => (
+                limit(at.hour, &self.by_hour),
+                if self.frequency <= Frequency::Minutely {
+                    limit(at.minute, &self.by_minute)
+                } else {
+                    sorted(&self.by_minute)
+                },
+                if self.frequency == Frequency::Secondly {
+                    limit(at.second, &self.by_second)
+                } else {
+                    sorted(&self.by_second)
+                },
+            ),
+            None => (
+                sorted(&self.by_hour),
+                sorted(&self.by_minute),
+                sorted(&self.by_second),
+            ),
+        };
+
+        let mut candidates = Vec::new();
+        for day in days.into_iter().filter(|day| self.matches_day(*day, year)) {
+            for hour in &hours {
+                for minute in &minutes {
+                    for second in &seconds {
+                        candidates.push(DateTime::new(
+                            day.year, day.month, day.day, *hour, *minute, *second,
+                        ));
+                    }
+                }
+            }
+        }
+        if self.by_set_pos.is_empty() {
+            return candidates;
+        }
+        let len = candidates.len() as i32;
+        let picked: BTreeSet<DateTime> = self
+            .by_set_pos
+            .iter()
+            .filter_map(|pos| {
+                let index = if *pos > 0 { pos - 1 } else { len + pos };
+                (0..len)
+                    .contains(&index)
+                    .then(|| candidates[index as usize])
+            })
+            .collect();
+        picked.into_iter().collect()
+    }
+
+    // whether the BYxxx parts of days allow a day of a period in a year
+    fn matches_day(&self, day: DateTime, year: i64) -> bool {
+        let either = |n: i32, first: u32, last: u32| {
+            n == first as i32 || (n < 0 && n == first as i32 - last as i32 - 1)
+        };
+        let month_days = days_in_month(day.year, day.month);
+        let year_days = if is_leap_year(day.year) { 366 } else { 365 };
+        if !self.by_month.is_empty() && !self.by_month.contains(&day.month) {
+            return false;
+        }
+        if !self.by_week_no.is_empty() && self.frequency == Frequency::Yearly {
+            let first = first_week(year, self.week_start).timestamp();
+            let weeks = (first_week(year + 1, self.week_start).timestamp() - first) / 86400 / 7;
+            let week = (day.timestamp() - first).div_euclid(86400 * 7) + 1;
+            if !self
+                .by_week_no
+                .iter()
+                .any(|n| either(*n, week as u32, weeks as u32))
+            {
+                return false;
+            }
+        }
+        if !self.by_year_day.is_empty()
+            && !self
+                .by_year_day
+                .iter()
+                .any(|n| either(*n, day.day_of_year(), year_days))
+        {
+            return false;
+        }
+        if !self.by_month_day.is_empty()
+            && !self
+                .by_month_day
+                .iter()
+                .any(|n| either(*n, day.day, month_days))
+        {
+            return false;
+        }
+        if !self.by_day.is_empty() {
+            // ordinals count within the month or the year, other frequencies ignore them
+            let (nth, count) = match self.frequency {
+                Frequency::Monthly => ((day.day - 1) / 7 + 1, (month_days - day.day) / 7 + 1),
+                Frequency::Yearly if !self.by_month.is_empty() => {
+                    ((day.day - 1) / 7 + 1, (month_days - day.day) / 7 + 1)
+                }
+                Frequency::Yearly if self.by_week_no.is_empty() => (
+                    (day.day_of_year() - 1) / 7 + 1,
+                    (year_days - day.day_of_year()) / 7 + 1,
+                ),
+                _ => (0, 0),
+            };
+            let matches = self.by_day.iter().any(|(n, weekday)| {
+                *weekday == day.weekday()
+                    && (*n == 0 || count == 0 || *n == nth as i32 || *n == -(count as i32))
+            });
+            if !matches {
+                return false;
+            }
+        }
+        true
+    }
+
+    // for frequencies below DAILY, the first period after index that is not
+    // on a day the rule excludes, so that such days are skipped at once
+    fn skip_excluded_days(&self, start: DateTime, index: i64) -> i64 {
+        let Some(seconds) = self.frequency.seconds() else {
+            return index;
+        };
+        let step = seconds * self.interval as i64;
+        let base = start.timestamp() - start.timestamp().rem_euclid(seconds);
+        let at = DateTime::from_timestamp(base + index * step);
+        let day = DateTime::new(at.year, at.month, at.day, 0, 0, 0);
+        if self.matches_day(day, day.year) {
+            return index;
+        }
+        let next_day = day.timestamp() + 86400;
+        (next_day - base + step - 1).div_euclid(step).max(index + 1)
+    }
+}
+
+// the first day of week 1 of a year, the first week with at least 4 days in the year
+fn first_week(year: i64, week_start: u32) -> DateTime {
+    let first = DateTime::new(year, 1, 1, 0, 0, 0);
+    let offset = (first.weekday() + 7 - week_start) % 7;
+    if offset <= 3 {
+        first.add_seconds(-(offset as i64) * 86400)
+    } else {
+        first.add_seconds((7 - offset as i64) * 86400)
+    }
+}
+
+fn sorted(values: &[u32]) -> Vec<u32> {
+    let mut values = values.to_vec();
+    values.sort_unstable();
+    values.dedup();
+    values
+}
+
+/// see [RecurrenceRule::occurrences]
+#[derive(Debug, Clone)]
+pub struct Occurrences {
+    rule: RecurrenceRule,
+    start: DateTime,
+    since: DateTime,
+    period: i64,
+    pending: VecDeque<DateTime>,
+    emitted: u32,
+    done: bool,
+}
+
+impl Occurrences {
+    /// stops after the last occurrence before or at `end`, which also ends
+    /// the search for rules that match nothing
+    pub fn until(mut self, end: DateTime) -> Occurrences {
+        if self.rule.until.is_none_or(|until| end < until) {
+            self.rule.until = Some(end);
+        }
+        self
+    }
+
+    /// skips the occurrences before `since`, the periods that end before it
+    /// are not computed unless the rule has a COUNT, which counts them too
+    pub fn since(mut self, since: DateTime) -> Occurrences {
+        self.since = self.since.max(since);
+        // BYSETPOS picks within a period, only COUNT depends on the ones before
+        if self.rule.count.is_some() || self.period_start(0) > since {
+            return self;
+        }
+        // the last period that begins before since, by doubling and bisecting
+        let (mut low, mut high) = (0, 1);
+        while self.period_start(high) <= since {
+            low = high;
+            high *= 2;
+            if self.period_start(low).year > LAST_YEAR {
+                self.done = true;
+                return self;
+            }
+        }
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            if self.period_start(middle) <= since {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        // the period before may run into that one, like the weeks of a year
+        self.period = self.period.max(low - 1);
+        self
+    }
+
+    // the earliest time a period can have an occurrence at
+    fn period_start(&self, index: i64) -> DateTime {
+        let step = index * self.rule.interval as i64;
+        let start = self.start;
+        match self.rule.frequency {
+            Frequency::Yearly => first_week(start.year + step, self.rule.week_start)
+                .min(DateTime::new(start.year + step, 1, 1, 0, 0, 0)),
+            Frequency::Monthly => {
+                let months = start.month as i64 - 1 + step;
+                DateTime::new(
+                    start.year + months.div_euclid(12),
+                    months.rem_euclid(12) as u32 + 1,
+                    1,
+                    0,
+                    0,
+                    0,
+                )
+            }
+            Frequency::Weekly => DateTime::new(start.year, start.month, start.day, 0, 0, 0)
+                .add_seconds((step * 7 - 7) * 86400),
+            Frequency::Daily => {
+                DateTime::new(start.year, start.month, start.day, 0, 0, 0).add_seconds(step * 86400)
+            }
+            frequency => {
+                let seconds = frequency.seconds().unwrap_or(1);
+                let truncated = start.timestamp() - start.timestamp().rem_euclid(seconds);
+                DateTime::from_timestamp(truncated + step * seconds)
+            }
+        }
+    }
+}
+
+impl Iterator for Occurrences {
+    type Item = DateTime;
+
+    fn next(&mut self) -> Option<DateTime> {
+        loop {
+            if let Some(next) = self.pending.pop_front() {
+                if self.rule.until.is_some_and(|until| next > until)
+                    || self.rule.count.is_some_and(|count| self.emitted >= count)
+                {
+                    self.done = true;
+                    self.pending.clear();
+                    return None;
+                }
+                self.emitted += 1;
+                if next < self.since {
+                    continue;
+                }
+                return Some(next);
+            }
+            if self.done {
+                return None;
+            }
+            self.period = self.rule.skip_excluded_days(self.start, self.period);
+            // a period that begins after UNTIL ends the rule, even if nothing matched before
+            if let Some(until) = self.rule.until {
+                if self.period_start(self.period) > until {
+                    self.done = true;
+                    return None;
+                }
+            }
+            let Some(candidates) = self.rule.period(self.start, self.period) else {
+                self.done = true;
+                return None;
+            };
+            self.period += 1;
+            self.pending.extend(
+                candidates
+                    .into_iter()
+                    .filter(|candidate| *candidate >= self.start),
+            );
+        }
+    }
+}
+
+/// an occurrence of a component, in UTC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instance<'a> {
+    /// the master or the override that describes the occurrence
+    pub component: &'a ICalObject,
+    /// the original start of an occurrence of a recurring component
+    pub recurrence_id: Option<DateTime>,
+    pub start: DateTime,
+    pub end: DateTime,
+}
+
+impl Instance<'_> {
+    /// whether the instance overlaps the window from start to end,
+    /// end excluded, an instance without length only has to start in it
+    pub fn overlaps(&self, start: DateTime, end: DateTime) -> bool {
+        self.start < end && (self.end > start || (self.start == self.end && self.start >= start))
+    }
+}
+
+// the start of a component in local time, its zone and its length in seconds
+struct Timing<'a> {
+    start: DateTime,
+    zone: Option<&'a TimeZone>,
+    length: i64,
+}
+
+impl Timing<'_> {
+    fn utc_of(&self, local: DateTime) -> DateTime {
+        self.zone.map_or(local, |zone| zone.to_utc(local))
+    }
+
+    fn local_of(&self, utc: DateTime) -> DateTime {
+        self.zone.map_or(utc, |zone| zone.from_utc(utc))
+    }
+
+    // the UTC span of an occurrence starting at a UTC time, the length
+    // applies to local time so that a day stays a day across DST
+    fn span(&self, start: DateTime) -> (DateTime, DateTime) {
+        let end = self.utc_of(self.local_of(start).add_seconds(self.length));
+        (start, end.max(start))
+    }
+}
+
+// DTSTART, or DUE for a VTODO without one, and DTEND, DUE or DURATION
+fn timing_of<'a>(component: &ICalObject, zones: &'a TimeZones) -> Result<Option<Timing<'a>>> {
+    let Some(start) = component
+        .get_property("DTSTART")
+        .or_else(|| component.get_property("DUE"))
+    else {
+        return Ok(None);
+    };
+    let is_utc = start.value.ends_with('Z');
+    let zone = zones.zone_of(start).filter(|_| !is_utc);
+    let local = DateTime::parse(&start.value)?;
+    let end = component
+        .get_property("DTEND")
+        .or_else(|| component.get_property("DUE"))
+        .filter(|end| !std::ptr::eq(*end, start));
+    let length = match (end, component.get_property("DURATION")) {
+        (Some(end), _) => {
+            let same_zone = end.value.ends_with('Z') == is_utc
+                && end.param("TZID").map(|param| param.values())
+                    == start.param("TZID").map(|param| param.values());
+            if same_zone {
+                DateTime::parse(&end.value)?.timestamp() - local.timestamp()
+            } else {
+                let start = zones.to_utc(start, &start.value)?;
+                zones.to_utc(end, &end.value)?.timestamp() - start.timestamp()
+            }
+        }
+        (None, Some(duration)) => parse_duration(&duration.value)?,
+        (None, None) if !start.value.contains('T') => 86400,
+        (None, None) => 0,
+    };
+    Ok(Some(Timing {
+        start: local,
+        zone,
+        length,
+    }))
+}
+
+/// the instances of the components of a type, like VEVENT, that overlap the
+/// window from start to end in UTC, sorted by start
+///
+/// overrides replace the occurrence of their RECURRENCE-ID, RANGE=THISANDFUTURE
+/// is not applied to later ones, RDATE periods use the length of the master
+/// and floating times are taken as UTC
+pub fn instances<'a>(
+    calendar: &'a ICalObject,
+    object_type: &str,
+    start: DateTime,
+    end: DateTime,
+) -> Result<Vec<Instance<'a>>> {
+    let zones = TimeZones::from_calendar(calendar)?;
+    let components = calendar.sub_objects.iter();
+    let components = components.filter(|component| component.is_type(object_type));
+    expand(components, &zones, start, end)
+}
+
+/// like [instances], for components that are not directly in the calendar
+/// whose VTIMEZONEs are given, like the AVAILABLE of a VAVAILABILITY
+pub fn expand<'a>(
+    components: impl IntoIterator<Item = &'a ICalObject>,
+    zones: &TimeZones,
+    start: DateTime,
+    end: DateTime,
+) -> Result<Vec<Instance<'a>>> {
+    let mut masters = Vec::new();
+    let mut overrides: HashMap<&str, BTreeMap<DateTime, &ICalObject>> = HashMap::new();
+    for component in components {
+        let uid = component
+            .get_property("UID")
+            .map_or("", |line| line.value.as_str());
+        match component.get_property("RECURRENCE-ID") {
+            Some(line) => {
+                let recurrence_id = zones.to_utc(line, &line.value)?;
+                overrides
+                    .entry(uid)
+                    .or_default()
+                    .insert(recurrence_id, component);
+            }
+            None => masters.push((uid, component)),
+        }
+    }
+
+    let mut instances = Vec::new();
+    let mut push = |component, recurrence_id, timing: &Timing, start| {
+        let (start, end) = timing.span(start);
+        instances.push(Instance {
+            component,
+            recurrence_id,
+            start,
+            end,
+        });
+    };
+    for (uid, master) in masters {
+        let Some(timing) = timing_of(master, zones)? else {
+            continue;
+        };
+        let first = timing.utc_of(timing.start);
+        let rules: Vec<&ContentLine> = master.get_properties("RRULE").collect();
+        let dates: Vec<&ContentLine> = master.get_properties("RDATE").collect();
+        if rules.is_empty() && dates.is_empty() {
+            push(master, None, &timing, first);
+            continue;
+        }
+        // local times are at most a day off UTC, and the offset changes by
+        // less than a day during an occurrence
+        let horizon = end.add_seconds(86400);
+        let since = start.add_seconds(-2 * 86400 - timing.length.max(0));
+        let mut starts = BTreeSet::from([first]);
+        for line in rules {
+            let mut rule: RecurrenceRule = line.value.parse()?;
+            if rule.until_is_utc {
+                rule.until = rule.until.map(|until| timing.local_of(until));
+            }
+            let occurrences = rule.occurrences(timing.start).since(since);
+            for occurrence in occurrences.until(horizon) {
+                starts.insert(timing.utc_of(occurrence));
+            }
+        }
+        for line in dates {
+            for value in line.value.split(',') {
+                let value = value.split('/').next().unwrap_or(value);
+                starts.insert(zones.to_utc(line, value)?);
+            }
+        }
+        for line in master.get_properties("EXDATE") {
+            for value in line.value.split(',') {
+                starts.remove(&zones.to_utc(line, value)?);
+            }
+        }
+        let mut overrides = overrides.remove(uid).unwrap_or_default();
+        for occurrence in starts {
+            match overrides.remove(&occurrence) {
+                Some(component) => {
+                    if let Some(timing) = timing_of(component, zones)? {
+                        push(
+                            component,
+                            Some(occurrence),
+                            &timing,
+                            timing.utc_of(timing.start),
+                        );
+                    }
+                }
+                None => push(master, Some(occurrence), &timing, occurrence),
+            }
+        }
+        // overrides of occurrences that are not in the set are kept
+        for (recurrence_id, component) in overrides {
+            if let Some(timing) = timing_of(component, zones)? {
+                push(
+                    component,
+                    Some(recurrence_id),
+                    &timing,
+                    timing.utc_of(timing.start),
+                );
+            }
+        }
+    }
+    // instances the calendar has no master for
+    for (recurrence_id, component) in overrides.into_values().flatten() {
+        if let Some(timing) = timing_of(component, zones)? {
+            push(
+                component,
+                Some(recurrence_id),
+                &timing,
+                timing.utc_of(timing.start),
+            );
+        }
+    }
+    instances.retain(|instance| instance.overlaps(start, end));
+    instances.sort_by_key(|instance| (instance.start, instance.end));
+    Ok(instances)
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_rules() {
+        // examples from RFC 5545 section 3.8.5.3
+        let expand = |rule: &str, start: &str, count: usize| {
+            let rule: RecurrenceRule = rule.parse().unwrap();
+            rule.occurrences(DateTime::parse(start).unwrap())
+                .take(count)
+                .map(|occurrence| occurrence.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            expand("FREQ=MONTHLY;BYDAY=FR;BYMONTHDAY=13", "19970902T090000", 3),
+            ["19980213T090000", "19980313T090000", "19981113T090000"]
+        );
+        assert_eq!(
+            expand("FREQ=YEARLY;BYWEEKNO=20;BYDAY=MO", "19970512T090000", 3),
+            ["19970512T090000", "19980511T090000", "19990517T090000"]
+        );
+        assert_eq!(
+            expand("FREQ=YEARLY;BYDAY=20MO", "19970519T090000", 3),
+            ["19970519T090000", "19980518T090000", "19990517T090000"]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=YEARLY;INTERVAL=4;BYMONTH=11;BYDAY=TU;BYMONTHDAY=2,3,4,5,6,7,8",
+                "19961105T090000",
+                3
+            ),
+            ["19961105T090000", "20001107T090000", "20041102T090000"]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1",
+                "19970930T090000",
+                3
+            ),
+            ["19970930T090000", "19971031T090000", "19971128T090000"]
+        );
+        assert_eq!(
+            expand("FREQ=MONTHLY;BYMONTHDAY=-3", "19970928T090000", 3),
+            ["19970928T090000", "19971029T090000", "19971128T090000"]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=WEEKLY;INTERVAL=2;WKST=SU;BYDAY=TU,TH;COUNT=8",
+                "19970902T090000",
+                9
+            ),
+            [
+                "19970902T090000",
+                "19970904T090000",
+                "19970916T090000",
+                "19970918T090000",
+                "19970930T090000",
+                "19971002T090000",
+                "19971014T090000",
+                "19971016T090000"
+            ]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=HOURLY;INTERVAL=3;UNTIL=19970902T170000Z",
+                "19970902T090000",
+                9
+            ),
+            ["19970902T090000", "19970902T120000", "19970902T150000"]
+        );
+        assert_eq!(
+            expand(
+                "FREQ=MINUTELY;INTERVAL=20;BYHOUR=9,10,11,12,13,14,15,16",
+                "19970902T160000",
+                4
+            ),
+            [
+                "19970902T160000",
+                "19970902T162000",
+                "19970902T164000",
+                "19970903T090000"
+            ]
+        );
+        // a rule that never matches ends at its horizon
+        let never: RecurrenceRule = "FREQ=DAILY;BYMONTH=2;BYMONTHDAY=30".parse().unwrap();
+        let start = DateTime::parse("20240101T090000").unwrap();
+        assert_eq!(
+            never
+                .occurrences(start)
+                .until(start.add_seconds(86400 * 800))
+                .count(),
+            0
+        );
+        // since skips the periods before it, except for counting
+        let since = |rule: &str, since: &str| {
+            let rule: RecurrenceRule = rule.parse().unwrap();
+            rule.occurrences(DateTime::parse("20000101T090000").unwrap())
+                .since(DateTime::parse(since).unwrap())
+                .take(2)
+                .map(|occurrence| occurrence.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            since("FREQ=MINUTELY", "20300101T000000"),
+            ["20300101T000000", "20300101T000100"]
+        );
+        assert_eq!(
+            since("FREQ=DAILY;COUNT=3", "20000102T000000"),
+            ["20000102T090000", "20000103T090000"]
+        );
+        assert!("FREQ=DAILY;COUNT=2;UNTIL=20240101"
+            .parse::<RecurrenceRule>()
+            .is_err());
+        assert!("FREQ=FORTNIGHTLY".parse::<RecurrenceRule>().is_err());
+        assert!("FREQ=WEEKLY;BYDAY=aéM".parse::<RecurrenceRule>().is_err());
+        assert!("FREQ=WEEKLY;BYDAY=0MO".parse::<RecurrenceRule>().is_err());
+    }
+
+    #[test]
+    fn yearly_months_match_the_whole_year() {
+        // YEARLY with BYMONTH only looks at the days of its months, which
+        // must give what the days of the whole year give
+        let start = DateTime::parse("20230915T083000").unwrap();
+        for rule in [
+            "FREQ=YEARLY;BYMONTH=2,11",
+            "FREQ=YEARLY;BYMONTH=11,2;BYDAY=MO",
+            "FREQ=YEARLY;BYMONTH=2,3;BYDAY=-1SU,2TU,FR",
+            "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1,29,1",
+            "FREQ=YEARLY;BYMONTH=2,3,12;BYYEARDAY=60,-1,-300,1",
+            "FREQ=YEARLY;BYMONTH=1,6;BYDAY=MO,FR;BYSETPOS=-1,2",
+            "FREQ=YEARLY;INTERVAL=3;BYMONTH=9;BYDAY=WE;BYHOUR=8,20;BYMINUTE=0,45",
+        ] {
+            let rule = rule
+                .parse::<RecurrenceRule>()
+                .unwrap()
+                .occurrences(start)
+                .rule;
+            for index in 0..8 {
+                let year = start.year + index * rule.interval as i64;
+                let days = (1..=12)
+                    .flat_map(|month| {
+                        (1..=days_in_month(year, month))
+                            .map(move |day| DateTime::new(year, month, day, 0, 0, 0))
+                    })
+                    .collect();
+                assert_eq!(
+                    rule.period(start, index),
+                    Some(rule.candidates(days, year, None)),
+                    "{:?} in {}",
+                    rule,
+                    year
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn expands_components() {
+        let calendar: ICalObject = "BEGIN:VCALENDAR\r
+BEGIN:VTIMEZONE\r
+TZID:Europe/Berlin\r
+BEGIN:DAYLIGHT\r
+DTSTART:19810329T020000\r
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+DTSTART:19961027T030000\r
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+UID:weekly\r
+DTSTART;TZID=Europe/Berlin:20240321T100000\r
+DTEND;TZID=Europe/Berlin:20240321T110000\r
+RRULE:FREQ=WEEKLY;COUNT=4\r
+EXDATE;TZID=Europe/Berlin:20240404T100000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:weekly\r
+RECURRENCE-ID;TZID=Europe/Berlin:20240411T100000\r
+DTSTART;TZID=Europe/Berlin:20240411T140000\r
+DURATION:PT30M\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:holiday\r
+DTSTART;VALUE=DATE:20240329\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        .parse()
+        .unwrap();
+        let window = |start, end| {
+            instances(
+                &calendar,
+                "VEVENT",
+                DateTime::parse(start).unwrap(),
+                DateTime::parse(end).unwrap(),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|instance| format!("{}/{}", instance.start, instance.end))
+            .collect::<Vec<_>>()
+        };
+        // the summer time begins on March 31st
+        assert_eq!(
+            window("20240301T000000", "20240501T000000"),
+            [
+                "20240321T090000/20240321T100000",
+                "20240328T090000/20240328T100000",
+                "20240329T000000/20240330T000000",
+                "20240411T120000/20240411T123000",
+            ]
+        );
+        assert_eq!(
+            window("20240328T093000", "20240329T000000"),
+            ["20240328T090000/20240328T100000"]
+        );
+    }
+}
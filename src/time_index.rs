@@ -0,0 +1,526 @@
+// "what is on between X and Y" for parsed calendars, with the time-range
+// semantics of CalDAV, see https://www.rfc-editor.org/rfc/rfc4791#section-9.9
+//
+// occurrences that start before a horizon are expanded once into interval
+// trees, recurrence sets that go on after it are expanded in chunks of the
+// time after the horizon, each chunk when a query first reaches it
+
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::{Mutex, PoisonError};
+
+use eyre::Result;
+
+use crate::datetime::{parse_duration, DateTime};
+use crate::interval_tree::IntervalTree;
+use crate::recurrence::{expand, Instance, RecurrenceRule};
+use crate::timezone::TimeZones;
+use crate::ICalObject;
+
+const INDEXED: [&str; 3] = ["VEVENT", "VTODO", "VJOURNAL"];
+
+// the length of the chunks after the horizon, about a month view
+const CHUNK: i64 = 31 * 86400;
+
+// more repetitions of an alarm than any client shows, REPEAT is otherwise unbounded
+const MAX_REPEAT: i64 = 1000;
+
+fn earliest() -> DateTime {
+    DateTime::new(1, 1, 1, 0, 0, 0)
+}
+
+fn latest() -> DateTime {
+    DateTime::new(9999, 12, 31, 23, 59, 59)
+}
+
+/// an alarm of an occurrence and when it triggers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alarm<'a> {
+    pub instance: Instance<'a>,
+    pub alarm: &'a ICalObject,
+    pub trigger: DateTime,
+}
+
+/// whether an occurrence of a VEVENT, VTODO or VJOURNAL matches the time
+/// range from start to end like the CALDAV:time-range of a comp-filter
+pub fn matches_time_range(instance: &Instance, start: DateTime, end: DateTime) -> bool {
+    let component = instance.component;
+    if !component.is_type("VTODO") {
+        return instance.overlaps(start, end);
+    }
+    let has = |name: &str| component.get_property(name).is_some();
+    let (from, to) = (instance.start, instance.end);
+    match (has("DTSTART"), has("DUE"), has("DURATION")) {
+        (true, _, true) => start <= to && (end > from || end >= to),
+        (true, true, _) => (start < to || start <= from) && (end > from || end >= to),
+        (true, false, false) => start <= from && end > from,
+        // the start of a VTODO with only a DUE is the DUE
+        (false, true, _) => start < from && end >= from,
+        (false, false, _) => {
+            let utc = |name: &str| {
+                component
+                    .get_property(name)
+                    .and_then(|line| DateTime::parse(&line.value).ok())
+            };
+            match (utc("CREATED"), utc("COMPLETED")) {
+                (Some(created), Some(completed)) => {
+                    (start <= created || start <= completed) && (end >= created || end >= completed)
+                }
+                (None, Some(completed)) => start <= completed && end >= completed,
+                (Some(created), None) => end > created,
+                (None, None) => true,
+            }
+        }
+    }
+}
+
+/// the times the VALARM of an occurrence triggers, including its repetitions
+pub fn alarm_triggers(instance: &Instance, alarm: &ICalObject) -> Result<Vec<DateTime>> {
+    let Some(trigger) = alarm.get_property("TRIGGER") else {
+        return Ok(Vec::new());
+    };
+    let param = |name: &str| {
+        trigger
+            .param(name)
+            .and_then(|param| param.values().first())
+            .map(|value| value.to_ascii_uppercase())
+    };
+    let first = if param("VALUE").as_deref() == Some("DATE-TIME") {
+        DateTime::parse(&trigger.value)?
+    } else {
+        let offset = parse_duration(trigger.value.trim())?;
+        match param("RELATED").as_deref() {
+            Some("END") => instance.end.add_seconds(offset),
+            _ => instance.start.add_seconds(offset),
+        }
+    };
+    let mut triggers = vec![first];
+    if let Some(duration) = alarm.get_property("DURATION") {
+        let interval = parse_duration(&duration.value)?;
+        triggers.extend(
+            (1..=repeat(alarm))
+                .map_while(|n| n.checked_mul(interval))
+                .map(|offset| first.add_seconds(offset)),
+        );
+    }
+    Ok(triggers)
+}
+
+// how often a VALARM repeats, at most MAX_REPEAT times
+fn repeat(alarm: &ICalObject) -> i64 {
+    alarm
+        .get_property("REPEAT")
+        .and_then(|line| line.value.trim().parse::<i64>().ok())
+        .unwrap_or(0)
+        .clamp(0, MAX_REPEAT)
+}
+
+// how far alarms of components can trigger from their occurrences, in seconds
+pub(crate) fn alarm_margin(components: &[&ICalObject]) -> i64 {
+    let mut margin = 0;
+    for alarm in components
+        .iter()
+        .flat_map(|component| component.get_sub_objects("VALARM"))
+    {
+        let seconds = |name: &str| {
+            alarm
+                .get_property(name)
+                .and_then(|line| parse_duration(line.value.trim()).ok())
+                .unwrap_or(0)
+        };
+        let repetitions = repeat(alarm).saturating_mul(seconds("DURATION").abs());
+        margin = margin.max(seconds("TRIGGER").abs().saturating_add(repetitions));
+    }
+    margin
+}
+
+// whether a group of components with the same UID may have occurrences after the horizon
+fn is_open(components: &[&ICalObject], horizon: DateTime) -> bool {
+    // UNTIL and RDATE may be local times a day off UTC
+    let horizon = horizon.add_seconds(-86400);
+    components.iter().any(|component| {
+        let rules = component.get_properties("RRULE").any(|line| {
+            line.value
+                .parse::<RecurrenceRule>()
+                .map_or(true, |rule| rule.until.is_none_or(|until| until >= horizon))
+        });
+        let dates = component.get_properties("RDATE").any(|line| {
+            line.value.split(',').any(|value| {
+                let value = value.split('/').next().unwrap_or(value);
+                DateTime::parse(value).map_or(true, |date| date >= horizon)
+            })
+        });
+        rules || dates
+    })
+}
+
+// a recurrence set that is expanded again after the horizon
+#[derive(Debug, Clone)]
+struct Open<'a> {
+    calendar: usize,
+    components: Vec<&'a ICalObject>,
+    margin: i64,
+}
+
+// by open recurrence set and chunk, the occurrences after the horizon that
+// overlap the chunk
+#[derive(Debug, Default)]
+struct Chunks<'a>(Mutex<HashMap<(usize, i64), Vec<Instance<'a>>>>);
+
+impl Clone for Chunks<'_> {
+    fn clone(&self) -> Self {
+        let chunks = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        Chunks(Mutex::new(chunks.clone()))
+    }
+}
+
+/// an index of the VEVENT, VTODO and VJOURNAL occurrences of calendars
+/// and the triggers of their VALARMs
+#[derive(Debug, Clone)]
+pub struct TimeRangeIndex<'a> {
+    zones: Vec<TimeZones>,
+    horizon: DateTime,
+    occurrences: IntervalTree<Instance<'a>>,
+    alarms: IntervalTree<(Instance<'a>, &'a ICalObject)>,
+    open: Vec<Open<'a>>,
+    chunks: Chunks<'a>,
+    // VTODOs without DTSTART and DUE
+    undated: Vec<&'a ICalObject>,
+}
+
+impl<'a> TimeRangeIndex<'a> {
+    /// indexes the occurrences that start before the horizon, recurrence
+    /// sets without an end are expanded after it as far as queries reach
+    pub fn new(calendars: &'a [ICalObject], horizon: DateTime) -> Result<Self> {
+        let mut zones = Vec::new();
+        let mut occurrences = Vec::new();
+        let mut open = Vec::new();
+        let mut undated = Vec::new();
+        for (index, calendar) in calendars.iter().enumerate() {
+            let calendar_zones = TimeZones::from_calendar(calendar)?;
+            let mut groups: Vec<Vec<&ICalObject>> = Vec::new();
+            let mut by_uid: HashMap<(&str, &str), usize> = HashMap::new();
+            for component in &calendar.sub_objects {
+                let Some(object_type) = INDEXED.iter().find(|name| component.is_type(name)) else {
+                    continue;
+                };
+                if component.is_type("VTODO")
+                    && component.get_property("DTSTART").is_none()
+                    && component.get_property("DUE").is_none()
+                {
+                    undated.push(component);
+                    continue;
+                }
+                let uid = component
+                    .get_property("UID")
+                    .map_or("", |line| line.value.as_str());
+                match by_uid.get(&(*object_type, uid)) {
+                    Some(group) if !uid.is_empty() => groups[*group].push(component),
+                    _ => {
+                        by_uid.insert((object_type, uid), groups.len());
+                        groups.push(vec![component]);
+                    }
+                }
+            }
+            for components in groups {
+                let is_open = is_open(&components, horizon);
+                let until = if is_open { horizon } else { latest() };
+                let instances = expand(
+                    components.iter().copied(),
+                    &calendar_zones,
+                    earliest(),
+                    until,
+                )?;
+                occurrences.extend(instances);
+                if is_open {
+                    open.push(Open {
+                        calendar: index,
+                        margin: alarm_margin(&components) + 86400,
+                        components,
+                    });
+                }
+            }
+            zones.push(calendar_zones);
+        }
+
+        let mut alarms = Vec::new();
+        for instance in &occurrences {
+            for alarm in instance.component.get_sub_objects("VALARM") {
+                for trigger in alarm_triggers(instance, alarm)? {
+                    alarms.push((trigger, trigger, (instance.clone(), alarm)));
+                }
+            }
+        }
+        Ok(TimeRangeIndex {
+            zones,
+            horizon,
+            occurrences: IntervalTree::new(
+                occurrences
+                    .into_iter()
+                    .map(|instance| (instance.start, instance.end, instance))
+                    .collect(),
+            ),
+            alarms: IntervalTree::new(alarms),
+            open,
+            chunks: Chunks::default(),
+            undated,
+        })
+    }
+
+    pub fn horizon(&self) -> DateTime {
+        self.horizon
+    }
+
+    // the occurrences of an open recurrence set that start at or after
+    // the horizon and overlap the window
+    fn after_horizon(
+        &self,
+        index: usize,
+        start: DateTime,
+        end: DateTime,
+    ) -> Result<Vec<Instance<'a>>> {
+        let start = start.max(self.horizon);
+        if end <= start {
+            return Ok(Vec::new());
+        }
+        let chunk_of = |time: DateTime| (time.timestamp() - self.horizon.timestamp()) / CHUNK;
+        let (first, last) = (chunk_of(start), chunk_of(end.add_seconds(-1)));
+        let open = &self.open[index];
+        let mut chunks = self.chunks.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut found = Vec::new();
+        for chunk in first..=last {
+            let chunk_start = self.horizon.add_seconds(chunk * CHUNK);
+            let instances = match chunks.entry((index, chunk)) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let zones = &self.zones[open.calendar];
+                    let chunk_end = chunk_start.add_seconds(CHUNK);
+                    let mut instances = expand(
+                        open.components.iter().copied(),
+                        zones,
+                        chunk_start,
+                        chunk_end,
+                    )?;
+                    instances.retain(|instance| instance.start >= self.horizon);
+                    entry.insert(instances)
+                }
+            };
+            // occurrences from an earlier chunk are in the first one too
+            found.extend(
+                instances
+                    .iter()
+                    .filter(|instance| chunk == first || instance.start >= chunk_start)
+                    .filter(|instance| instance.overlaps(start, end))
+                    .cloned(),
+            );
+        }
+        Ok(found)
+    }
+
+    /// the occurrences that match the time range from start to end, see
+    /// [matches_time_range], sorted by start, a VTODO without DTSTART and
+    /// DUE spans from its CREATED to its COMPLETED, or the whole window
+    pub fn query(&self, start: DateTime, end: DateTime) -> Result<Vec<Instance<'a>>> {
+        // the VTODO semantics include the ends of the window
+        let (before, after) = (start.add_seconds(-1), end.add_seconds(1));
+        let mut found: Vec<Instance<'a>> = self
+            .occurrences
+            .query(before, after)
+            .into_iter()
+            .map(|(_, _, instance)| instance.clone())
+            .collect();
+        if after > self.horizon {
+            for index in 0..self.open.len() {
+                found.extend(self.after_horizon(index, before, after)?);
+            }
+        }
+        found.retain(|instance| matches_time_range(instance, start, end));
+        for todo in &self.undated {
+            let utc = |name: &str| {
+                todo.get_property(name)
+                    .and_then(|line| DateTime::parse(&line.value).ok())
+            };
+            let instance = Instance {
+                component: todo,
+                recurrence_id: None,
+                start: utc("CREATED").unwrap_or(start),
+                end: utc("COMPLETED").unwrap_or(end),
+            };
+            if matches_time_range(&instance, start, end) {
+                found.push(instance);
+            }
+        }
+        found.sort_by_key(|instance| (instance.start, instance.end));
+        Ok(found)
+    }
+
+    /// the alarms that trigger from start to end, sorted by trigger
+    pub fn alarms(&self, start: DateTime, end: DateTime) -> Result<Vec<Alarm<'a>>> {
+        let mut found: Vec<Alarm<'a>> = self
+            .alarms
+            .query(start, end)
+            .into_iter()
+            .map(|(trigger, _, (instance, alarm))| Alarm {
+                instance: instance.clone(),
+                alarm,
+                trigger: *trigger,
+            })
+            .collect();
+        for (index, open) in self.open.iter().enumerate() {
+            if end.add_seconds(open.margin) <= self.horizon {
+                continue;
+            }
+            let window = (
+                start.add_seconds(-open.margin),
+                end.add_seconds(open.margin),
+            );
+            for instance in self.after_horizon(index, window.0, window.1)? {
+                for alarm in instance.component.get_sub_objects("VALARM") {
+                    for trigger in alarm_triggers(&instance, alarm)? {
+                        if start <= trigger && trigger < end {
+                            found.push(Alarm {
+                                instance: instance.clone(),
+                                alarm,
+                                trigger,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        found.sort_by_key(|alarm| (alarm.trigger, alarm.instance.start));
+        Ok(found)
+    }
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:weekly\r
+DTSTART:20240101T100000Z\r
+DTEND:20240101T110000Z\r
+RRULE:FREQ=WEEKLY\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT15M\r
+REPEAT:1\r
+DURATION:PT5M\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:once\r
+DTSTART:20300101T120000Z\r
+END:VEVENT\r
+BEGIN:VTODO\r
+UID:report\r
+DTSTART:20240103T090000Z\r
+DUE:20240105T170000Z\r
+END:VTODO\r
+BEGIN:VTODO\r
+UID:due-only\r
+DUE:20240110T090000Z\r
+END:VTODO\r
+BEGIN:VTODO\r
+UID:someday\r
+END:VTODO\r
+BEGIN:VJOURNAL\r
+UID:notes\r
+DTSTART;VALUE=DATE:20240102\r
+END:VJOURNAL\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn answers_time_range_queries() {
+        let calendars = [FEED.parse().unwrap()];
+        let index = TimeRangeIndex::new(&calendars, DateTime::parse("20240201").unwrap()).unwrap();
+        let query = |start: &str, end: &str| {
+            index
+                .query(
+                    DateTime::parse(start).unwrap(),
+                    DateTime::parse(end).unwrap(),
+                )
+                .unwrap()
+                .into_iter()
+                .map(|instance| {
+                    let uid = &instance.component.get_property("UID").unwrap().value;
+                    format!("{}@{}", uid, instance.start)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            query("20240101T103000", "20240102T120000"),
+            [
+                "weekly@20240101T100000",
+                "someday@20240101T103000",
+                "notes@20240102T000000"
+            ]
+        );
+        // the window ends at the DUE of a VTODO without DTSTART
+        assert_eq!(
+            query("20240104T000000", "20240110T090000"),
+            [
+                "report@20240103T090000",
+                "someday@20240104T000000",
+                "weekly@20240108T100000",
+                "due-only@20240110T090000"
+            ]
+        );
+        // after the horizon the weekly event is expanded on demand, once
+        let expanded = || index.chunks.0.lock().unwrap().len();
+        assert_eq!(expanded(), 0);
+        let after_horizon = [
+            "someday@20291231T000000",
+            "weekly@20291231T100000",
+            "once@20300101T120000",
+        ];
+        assert_eq!(query("20291231T000000", "20300102T000000"), after_horizon);
+        assert_eq!(expanded(), 1);
+        assert_eq!(query("20291231T000000", "20300102T000000"), after_horizon);
+        assert_eq!(expanded(), 1);
+
+        let alarms = |start: &str, end: &str| {
+            index
+                .alarms(
+                    DateTime::parse(start).unwrap(),
+                    DateTime::parse(end).unwrap(),
+                )
+                .unwrap()
+                .into_iter()
+                .map(|alarm| alarm.trigger.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            alarms("20240108T094500", "20240108T100000"),
+            ["20240108T094500", "20240108T095000"]
+        );
+        assert_eq!(
+            alarms("20291231T094000", "20291231T095000"),
+            ["20291231T094500"]
+        );
+    }
+
+    #[test]
+    fn bounds_alarm_repetitions() {
+        let calendar: ICalObject = FEED
+            .replace("REPEAT:1\r", "REPEAT:100000000000\r")
+            .replace("DURATION:PT5M\r", "DURATION:P10000D\r")
+            .parse()
+            .unwrap();
+        let event = &calendar.get_sub_objects("VEVENT").next().unwrap();
+        let alarm = &event.get_sub_objects("VALARM").next().unwrap();
+        assert_eq!(alarm_margin(&[event]), 15 * 60 + MAX_REPEAT * 10000 * 86400);
+        let instance = Instance {
+            component: event,
+            recurrence_id: None,
+            start: DateTime::parse("20240101T100000Z").unwrap(),
+            end: DateTime::parse("20240101T110000Z").unwrap(),
+        };
+        let triggers = alarm_triggers(&instance, alarm).unwrap();
+        assert_eq!(triggers.len() as i64, MAX_REPEAT + 1);
+        assert_eq!(triggers[1].to_string(), "20510519T094500");
+    }
+}
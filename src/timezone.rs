@@ -0,0 +1,280 @@
+// UTC offsets from VTIMEZONE components,
+// see https://icalendar.org/iCalendar-RFC-5545/3-6-5-time-zone-component.html
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use eyre::{eyre, Result};
+
+use crate::datetime::{parse_utc_offset, DateTime};
+use crate::recurrence::{RecurrenceRule, LAST_YEAR};
+use crate::{ContentLine, ICalObject};
+
+/// the STANDARD and DAYLIGHT observances of a VTIMEZONE
+#[derive(Debug, Clone)]
+pub struct TimeZone {
+    observances: Vec<Observance>,
+    onsets: Onsets,
+}
+
+// by UTC year, the last onset before the year and the onsets in it, in UTC
+// with the offset they change to, filled in up to the years that were asked for
+#[derive(Debug, Default)]
+struct Onsets(Mutex<HashMap<i64, Vec<(DateTime, i64)>>>);
+
+impl Clone for Onsets {
+    fn clone(&self) -> Self {
+        let years = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        Onsets(Mutex::new(years.clone()))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Observance {
+    // local time in offset_from
+    start: DateTime,
+    offset_from: i64,
+    offset_to: i64,
+    rules: Vec<RecurrenceRule>,
+    dates: Vec<DateTime>,
+}
+
+impl TimeZone {
+    pub fn from_vtimezone(vtimezone: &ICalObject) -> Result<TimeZone> {
+        let mut observances = Vec::new();
+        for observance in vtimezone.sub_objects.iter() {
+            if !observance.is_type("STANDARD") && !observance.is_type("DAYLIGHT") {
+                continue;
+            }
+            let value = |name: &str| {
+                observance
+                    .get_property(name)
+                    .map(|line| line.value.as_str())
+                    .ok_or_else(|| eyre!("{} without {}", observance.object_type, name))
+            };
+            let offset_from = parse_utc_offset(value("TZOFFSETFROM")?)?;
+            let offset_to = parse_utc_offset(value("TZOFFSETTO")?)?;
+            // UTC values in the rules are made local like DTSTART
+            let local = |value: &str| -> Result<DateTime> {
+                let date_time = DateTime::parse(value)?;
+                Ok(if value.ends_with('Z') {
+                    date_time.add_seconds(offset_from)
+                } else {
+                    date_time
+                })
+            };
+            let mut rules = Vec::new();
+            for line in observance.get_properties("RRULE") {
+                let mut rule: RecurrenceRule = line.value.parse()?;
+                if rule.until_is_utc {
+                    rule.until = rule.until.map(|until| until.add_seconds(offset_from));
+                }
+                rules.push(rule);
+            }
+            let mut dates = Vec::new();
+            for line in observance.get_properties("RDATE") {
+                for value in line.value.split(',') {
+                    dates.push(local(value.split('/').next().unwrap_or(value))?);
+                }
+            }
+            observances.push(Observance {
+                start: local(value("DTSTART")?)?,
+                offset_from,
+                offset_to,
+                rules,
+                dates,
+            });
+        }
+        if observances.is_empty() {
+            return Err(eyre!("VTIMEZONE without STANDARD or DAYLIGHT"));
+        }
+        Ok(TimeZone {
+            observances,
+            onsets: Onsets::default(),
+        })
+    }
+
+    /// the UTC offset in seconds in effect at a UTC time
+    pub fn offset_at(&self, utc: DateTime) -> i64 {
+        let first = self
+            .observances
+            .iter()
+            .min_by_key(|observance| observance.start.add_seconds(-observance.offset_from));
+        let Some(first) = first else {
+            return 0;
+        };
+        // before the first onset, the offset the first observance changes from
+        let first_year = first.start.add_seconds(-first.offset_from).year;
+        let year = utc.year.min(LAST_YEAR + 1);
+        if year < first_year {
+            return first.offset_from;
+        }
+        let mut years = self.onsets.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if !years.contains_key(&year) {
+            let mut from = year;
+            while from > first_year && !years.contains_key(&(from - 1)) {
+                from -= 1;
+            }
+            for year in from..=year {
+                let before = years.get(&(year - 1)).and_then(|onsets| onsets.last());
+                let mut onsets: Vec<(DateTime, i64)> = before.copied().into_iter().collect();
+                for observance in &self.observances {
+                    let offset = observance.offset_to;
+                    onsets.extend(observance.onsets(year).map(|onset| (onset, offset)));
+                }
+                // stable, so that of equal onsets the later observance wins
+                onsets.sort_by_key(|(onset, _)| *onset);
+                years.insert(year, onsets);
+            }
+        }
+        years[&year]
+            .iter()
+            .rev()
+            .find(|(onset, _)| *onset <= utc)
+            .map_or(first.offset_from, |(_, offset)| *offset)
+    }
+
+    pub fn from_utc(&self, utc: DateTime) -> DateTime {
+        utc.add_seconds(self.offset_at(utc))
+    }
+
+    /// the UTC time of a local time, a time that occurs twice is the first
+    /// one and a time in a gap uses the offset before the gap, like RFC 5545
+    /// requires for DATE-TIME values
+    pub fn to_utc(&self, local: DateTime) -> DateTime {
+        let mut offsets: Vec<i64> = self
+            .observances
+            .iter()
+            .flat_map(|observance| [observance.offset_from, observance.offset_to])
+            .collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        offsets
+            .into_iter()
+            .map(|offset| local.add_seconds(-offset))
+            .filter(|utc| self.from_utc(*utc) == local)
+            .min()
+            .unwrap_or_else(|| {
+                // a day earlier is before the gap
+                local.add_seconds(-self.offset_at(local.add_seconds(-86400)))
+            })
+    }
+}
+
+impl Observance {
+    // the onsets in a UTC year, in UTC
+    fn onsets(&self, year: i64) -> impl Iterator<Item = DateTime> + '_ {
+        // local times are at most a day off UTC
+        let since = DateTime::new(year, 1, 1, 0, 0, 0).add_seconds(-86400);
+        let until = DateTime::new(year + 1, 1, 1, 0, 0, 0).add_seconds(86400);
+        let from_rules = self
+            .rules
+            .iter()
+            .flat_map(move |rule| rule.occurrences(self.start).since(since).until(until));
+        std::iter::once(self.start)
+            .chain(from_rules)
+            .chain(self.dates.iter().copied())
+            .map(|onset| onset.add_seconds(-self.offset_from))
+            .filter(move |onset| onset.year == year)
+    }
+}
+
+/// the VTIMEZONEs of a calendar by TZID
+#[derive(Debug, Clone, Default)]
+pub struct TimeZones {
+    zones: HashMap<String, TimeZone>,
+}
+
+impl TimeZones {
+    pub fn from_calendar(calendar: &ICalObject) -> Result<TimeZones> {
+        let mut zones = HashMap::new();
+        for vtimezone in calendar.get_sub_objects("VTIMEZONE") {
+            let tzid = vtimezone
+                .get_property("TZID")
+                .ok_or_else(|| eyre!("VTIMEZONE without TZID"))?;
+            zones.insert(tzid.value.clone(), TimeZone::from_vtimezone(vtimezone)?);
+        }
+        Ok(TimeZones { zones })
+    }
+
+    pub fn get(&self, tzid: &str) -> Option<&TimeZone> {
+        self.zones.get(tzid)
+    }
+
+    /// the zone of the TZID parameter of a line, None if there is no
+    /// parameter or no such VTIMEZONE
+    pub fn zone_of(&self, line: &ContentLine) -> Option<&TimeZone> {
+        let tzid = line.param("TZID")?.values().first()?;
+        self.get(tzid)
+    }
+
+    /// the UTC time of a DATE or DATE-TIME value of a line, the line's value
+    /// or one of its list, floating times and unknown TZIDs are taken as UTC
+    pub fn to_utc(&self, line: &ContentLine, value: &str) -> Result<DateTime> {
+        let local = DateTime::parse(value)?;
+        Ok(match self.zone_of(line) {
+            Some(zone) if !value.ends_with('Z') => zone.to_utc(local),
+            _ => local,
+        })
+    }
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEW_YORK: &str = "BEGIN:VCALENDAR\r
+BEGIN:VTIMEZONE\r
+TZID:America/New_York\r
+BEGIN:DAYLIGHT\r
+DTSTART:20070311T020000\r
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=2SU\r
+TZOFFSETFROM:-0500\r
+TZOFFSETTO:-0400\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+DTSTART:20071104T020000\r
+RRULE:FREQ=YEARLY;BYMONTH=11;BYDAY=1SU\r
+TZOFFSETFROM:-0400\r
+TZOFFSETTO:-0500\r
+END:STANDARD\r
+END:VTIMEZONE\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn converts_local_times() {
+        let calendar: ICalObject = NEW_YORK.parse().unwrap();
+        let zones = TimeZones::from_calendar(&calendar).unwrap();
+        let zone = zones.get("America/New_York").unwrap();
+        let utc = |local: &str| zone.to_utc(DateTime::parse(local).unwrap()).to_string();
+        assert_eq!(utc("20240115T090000"), "20240115T140000");
+        assert_eq!(utc("20240715T090000"), "20240715T130000");
+        // the gap in March and the repeated hour in November
+        assert_eq!(utc("20240310T023000"), "20240310T073000");
+        assert_eq!(utc("20241103T013000"), "20241103T053000");
+        assert_eq!(utc("20241103T020000"), "20241103T070000");
+        // before the first onset
+        assert_eq!(utc("19990101T000000"), "19990101T050000");
+        let back = zone.from_utc(DateTime::parse("20241103T063000").unwrap());
+        assert_eq!(back.to_string(), "20241103T013000");
+
+        let line = ContentLine::new(
+            "DTSTART".to_string(),
+            vec![crate::Param::new(
+                "TZID".to_string(),
+                vec!["America/New_York".to_string()],
+            )],
+            "20240601T120000".to_string(),
+        );
+        assert_eq!(
+            zones.to_utc(&line, &line.value).unwrap().to_string(),
+            "20240601T160000"
+        );
+        assert_eq!(
+            zones.to_utc(&line, "20240601T120000Z").unwrap().to_string(),
+            "20240601T120000"
+        );
+    }
+}
@@ -15,11 +15,15 @@ use std::io::BufRead;
 
 use eyre::Context;
 use eyre::{eyre, Result};
+use memchr::memchr;
 
 #[derive(Debug, Clone)]
 pub struct Unfold<B: BufRead> {
     read: B,
-    last_line: Option<Vec<u8>>,
+    // the next physical line and whether it was terminated by a CRLF
+    last_line: Option<(Vec<u8>, bool)>,
+    require_trailing_crlf: bool,
+    legacy: bool,
 }
 
 impl<B: BufRead> Unfold<B> {
@@ -27,106 +31,136 @@ impl<B: BufRead> Unfold<B> {
         Unfold {
             read,
             last_line: None,
+            require_trailing_crlf: false,
+            legacy: false,
         }
     }
-}
 
-impl<B: BufRead> Iterator for Unfold<B>
-where
-    B: BufRead,
-{
-    type Item = Result<String>;
+    /// if set, a last line that is not terminated by CRLF is an error,
+    /// otherwise it is yielded like any other line (the default)
+    pub fn require_trailing_crlf(mut self, require_trailing_crlf: bool) -> Unfold<B> {
+        self.require_trailing_crlf = require_trailing_crlf;
+        self
+    }
+
+    /// vCard 2.1 compatibility: a line of a quoted-printable value that ends
+    /// with `=` continues on the next line (a soft line break), empty lines
+    /// are skipped and lines that are not UTF-8 are read as ISO-8859-1
+    pub fn legacy(mut self, legacy: bool) -> Unfold<B> {
+        self.legacy = legacy;
+        self
+    }
+
+    /// like [Iterator::next], but also returns the physical lines the
+    /// logical line was unfolded from, including their line breaks
+    pub fn next_with_raw(&mut self) -> Option<Result<(String, String)>> {
+        let mut raw = Vec::new();
+        let line = self.next_line(Some(&mut raw))?;
+        Some(line.and_then(|line| {
+            let raw = String::from_utf8(raw).wrap_err("from_utf8 failed")?;
+            Ok((line, raw))
+        }))
+    }
+
+    // reads a single physical line and removes its CRLF,
+    // returns None on EOF, otherwise the line and whether it had a CRLF
+    fn read_physical_line(&mut self) -> Result<Option<(Vec<u8>, bool)>> {
+        let mut buf = Vec::new();
+        // read until CR
+        if self
+            .read
+            .read_until(b'\r', &mut buf)
+            .wrap_err("read_until \\r failed")?
+            == 0
+        {
+            return Ok(None); // EOF
+        }
+        // if the last byte is not a CR, read_until hit EOF
+        if buf[buf.len() - 1] != b'\r' {
+            if memchr(b'\n', &buf).is_some() {
+                return Err(eyre!(
+                    r"found a LF that is not preceded by a CR
+this may mean, that the file does not have the proper line endings"
+                ));
+            }
+            if self.require_trailing_crlf {
+                return Err(eyre!(
+                    r"last line is not terminated by a CRLF
+the trailing CRLF is missing"
+                ));
+            }
+            return Ok(Some((buf, false)));
+        }
+        // assumption: the next character is a newline
+        let mut newline_buf: [u8; 1] = [0; 1];
+        self.read
+            .read_exact(&mut newline_buf)
+            .wrap_err("read_exact for \\n failed")?;
+        if newline_buf[0] != b'\n' {
+            return Err(eyre!("found a CR that is not followed by a LF"));
+        }
+        // since the line ends correctly, we can remove the CR
+        buf.pop();
+        Ok(Some((buf, true)))
+    }
+
+    // unfolds the next logical line, if `raw` is given,
+    // the physical lines are appended to it
+    fn next_line(&mut self, mut raw: Option<&mut Vec<u8>>) -> Option<Result<String>> {
+        let mut push_raw = |buf: &[u8], terminated: bool| {
+            if let Some(raw) = raw.as_mut() {
+                raw.extend_from_slice(buf);
+                if terminated {
+                    raw.extend_from_slice(b"\r\n");
+                }
+            }
+        };
 
-    fn next(&mut self) -> Option<Self::Item> {
         let mut byte_buf = match self.last_line.take() {
-            Some(buf) => buf,
+            Some((buf, terminated)) => {
+                push_raw(&buf, terminated);
+                buf
+            }
             None => {
-                let mut buf = Vec::new();
-                match self
-                    .read
-                    .read_until(b'\r', &mut buf)
-                    .wrap_err("first read_until \\r failed")
-                {
-                    // read until CR
-                    Ok(0) => return None, // EOF
-                    Ok(_) => (),
+                let buf = match self.read_physical_line() {
+                    Ok(Some((buf, terminated))) => {
+                        push_raw(&buf, terminated);
+                        buf
+                    }
+                    Ok(None) => return None, // EOF
                     Err(e) => return Some(Err(e)),
                 };
-                // assumption: the line does not begin with whitespace
-                // assert that
-                assert!(!buf.is_empty()); // it's not empty
-                assert!(buf[0] != b' '); // it's not a space
-                assert!(buf[0] != b'\t'); // it's not a tab
-
-                // check that the last byte is a CR
-                if buf[buf.len() - 1] != b'\r' {
-                    self.last_line = Some(buf); // main loop deals with this issue too
+                if buf.is_empty() && self.legacy {
+                    return self.next_line(raw);
+                }
+                if buf.is_empty() {
                     return Some(Err(eyre!(
-                        r"last byte of first read_until \r is not a CR
-this means that the parser encounted a first line,
-that is not properly terminated by a CR (followed by a newline),
-this may mean, that the file does not have the proper line endings"
+                        r"empty line
+the ical spec does not allow empty lines"
+                    )));
+                }
+                // the first line can't be a continuation line
+                if buf[0] == b' ' || buf[0] == b'\t' {
+                    return Some(Err(eyre!(
+                        "the first line begins with whitespace, there is nothing to continue"
                     )));
                 }
-                // assumption: the next character is a newline
-                // assert that
-                let mut newline_buf: [u8; 1] = [0; 1];
-                match self
-                    .read
-                    .read_exact(&mut newline_buf)
-                    .wrap_err("first read_exact for \\n failed")
-                {
-                    Ok(_) => (),
-                    Err(e) => return Some(Err(e)),
-                };
-                assert!(newline_buf[0] == b'\n');
-
-                // since the line ends correctly, we can remove the CR
-                buf.pop();
                 buf
             }
         };
 
         loop {
             // now look at the next line
-            let mut next_line_buf = Vec::new();
-            match self
-                .read
-                .read_until(b'\r', &mut next_line_buf)
-                .wrap_err("read_until failed")
-            {
-                // read until CR
-                Ok(0) => return None, // EOF
-                Ok(_) => (),
+            let (next_line_buf, terminated) = match self.read_physical_line() {
+                Ok(Some(line)) => line,
+                // EOF, byte_buf is the final line
+                Ok(None) => return Some(self.decode(byte_buf)),
                 Err(e) => return Some(Err(e)),
             };
-            // check that the last byte is a CR
-            if next_line_buf[next_line_buf.len() - 1] != b'\r' {
-                self.last_line = Some(next_line_buf);
-                return Some(Err(eyre!(
-                    r"last byte of read_until \\r is not a CR
-this means that the parser encounted a line, other than the first line,
-that is not properly terminated by a CR (followed by a newline)
-this may mean, that the file does not have the proper line endings
-or it means, that a trailing CRLF is missing"
-                )));
+            if next_line_buf.is_empty() && self.legacy {
+                push_raw(&next_line_buf, terminated);
+                continue;
             }
-
-            // assumption: the next character is a newline
-            // assert that
-            let mut newline_buf: [u8; 1] = [0; 1];
-            match self
-                .read
-                .read_exact(&mut newline_buf)
-                .wrap_err("read_exact for \\n failed")
-            {
-                Ok(_) => (),
-                Err(e) => return Some(Err(e)),
-            };
-            assert!(newline_buf[0] == b'\n');
-
-            // since the line ends correctly, we can remove the CR
-            next_line_buf.pop();
             // if the next line is empty, we can fail with an error, since empty lines are not allowed
             if next_line_buf.is_empty() {
                 return Some(Err(eyre!(
@@ -134,28 +168,100 @@ or it means, that a trailing CRLF is missing"
 the ical spec does not allow empty lines"
                 )));
             }
+            // a soft line break continues without whitespace, the '=' is removed
+            if self.legacy && byte_buf.ends_with(b"=") && is_quoted_printable(&byte_buf) {
+                push_raw(&next_line_buf, terminated);
+                byte_buf.pop();
+                byte_buf.extend_from_slice(&next_line_buf);
+                continue;
+            }
             // if the line does not begin with whitespace, we are done
             if next_line_buf[0] != b' ' && next_line_buf[0] != b'\t' {
                 // we are done
                 // save the next_line_buf
-                self.last_line = Some(next_line_buf);
+                self.last_line = Some((next_line_buf, terminated));
                 // return the byte_buf
-                let string = String::from_utf8(byte_buf).wrap_err("from_utf8 failed");
-                return Some(string);
+                return Some(self.decode(byte_buf));
             }
 
             // since it begins with whitespace, we need to combine the two lines
             // remove the whitespace from the next line
             // and add into byte_buf
+            push_raw(&next_line_buf, terminated);
             byte_buf.extend_from_slice(&next_line_buf[1..]);
         }
     }
 }
 
+impl<B: BufRead> Unfold<B> {
+    fn decode(&self, bytes: Vec<u8>) -> Result<String> {
+        match String::from_utf8(bytes) {
+            Ok(string) => Ok(string),
+            // every byte is a char in ISO-8859-1
+            Err(e) if self.legacy => Ok(e.into_bytes().into_iter().map(char::from).collect()),
+            Err(e) => Err(e).wrap_err("from_utf8 failed"),
+        }
+    }
+}
+
+// whether the params of a line declare a quoted-printable value
+fn is_quoted_printable(line: &[u8]) -> bool {
+    let params = &line[..memchr(b':', line).unwrap_or(line.len())];
+    params
+        .to_ascii_uppercase()
+        .windows(b"QUOTED-PRINTABLE".len())
+        .any(|window| window == b"QUOTED-PRINTABLE")
+}
+
+impl<B: BufRead> Iterator for Unfold<B>
+where
+    B: BufRead,
+{
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_line(None)
+    }
+}
+
 // tests
 
 #[cfg(test)]
 mod tests {
+    use super::Unfold;
+
+    #[test]
+    fn yields_the_last_line() {
+        let lines = |input: &str, require_trailing_crlf| {
+            Unfold::new(input.as_bytes())
+                .require_trailing_crlf(require_trailing_crlf)
+                .collect::<eyre::Result<Vec<_>>>()
+        };
+        let expected = ["BEGIN:VCALENDAR", "X-LONG:folded line", "END:VCALENDAR"];
+        let input = "BEGIN:VCALENDAR\r\nX-LONG:folded\r\n  line\r\nEND:VCALENDAR";
+        assert_eq!(lines(&format!("{}\r\n", input), true).unwrap(), expected);
+        assert_eq!(lines(input, false).unwrap(), expected);
+        assert!(lines(input, true).is_err());
+        // a folded last line without a trailing CRLF
+        let input = "BEGIN:VCALENDAR\r\nX-LONG:folded\r\n  line";
+        assert_eq!(lines(input, false).unwrap(), expected[..2]);
+        // the raw lines include the folding and the line breaks
+        let mut unfold = Unfold::new("A:1\r\nX-LONG:folded\r\n\t line\r\nEND:X".as_bytes());
+        unfold.next().unwrap().unwrap();
+        assert_eq!(
+            unfold.next_with_raw().unwrap().unwrap(),
+            (
+                "X-LONG:folded line".to_string(),
+                "X-LONG:folded\r\n\t line\r\n".to_string()
+            )
+        );
+        assert_eq!(
+            unfold.next_with_raw().unwrap().unwrap(),
+            ("END:X".to_string(), "END:X".to_string())
+        );
+        // LF line endings are still rejected
+        assert!(lines("BEGIN:VCALENDAR\nEND:VCALENDAR\n", false).is_err());
+    }
 
     #[test]
     fn it_works_on_all_private_test_icals() {
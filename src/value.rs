@@ -0,0 +1,344 @@
+// value types of properties, see https://icalendar.org/iCalendar-RFC-5545/3-3-property-value-data-types.html
+//
+// the parser keeps every value as the raw string from the content line,
+// these helpers determine how that string has to be interpreted
+
+use crate::content_line::ContentLine;
+
+/// with the `serde` feature this is (de)serialized as its name, e.g. `"DATE-TIME"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "SCREAMING-KEBAB-CASE")
+)]
+pub enum ValueType {
+    Binary,
+    Boolean,
+    CalAddress,
+    Date,
+    DateTime,
+    Duration,
+    Float,
+    Integer,
+    Period,
+    Recur,
+    Text,
+    Time,
+    Uri,
+    UtcOffset,
+    /// x-name or iana-token value types, and properties without a known default
+    Unknown,
+}
+
+impl ValueType {
+    /// parses the value of a `VALUE` param, case-insensitively
+    pub fn from_name(name: &str) -> ValueType {
+        match name.to_ascii_uppercase().as_str() {
+            "BINARY" => ValueType::Binary,
+            "BOOLEAN" => ValueType::Boolean,
+            "CAL-ADDRESS" => ValueType::CalAddress,
+            "DATE" => ValueType::Date,
+            "DATE-TIME" => ValueType::DateTime,
+            "DURATION" => ValueType::Duration,
+            "FLOAT" => ValueType::Float,
+            "INTEGER" => ValueType::Integer,
+            "PERIOD" => ValueType::Period,
+            "RECUR" => ValueType::Recur,
+            "TEXT" => ValueType::Text,
+            "TIME" => ValueType::Time,
+            "URI" => ValueType::Uri,
+            "UTC-OFFSET" => ValueType::UtcOffset,
+            _ => ValueType::Unknown,
+        }
+    }
+
+    /// the name as used in the `VALUE` param, `UNKNOWN` for [ValueType::Unknown]
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueType::Binary => "BINARY",
+            ValueType::Boolean => "BOOLEAN",
+            ValueType::CalAddress => "CAL-ADDRESS",
+            ValueType::Date => "DATE",
+            ValueType::DateTime => "DATE-TIME",
+            ValueType::Duration => "DURATION",
+            ValueType::Float => "FLOAT",
+            ValueType::Integer => "INTEGER",
+            ValueType::Period => "PERIOD",
+            ValueType::Recur => "RECUR",
+            ValueType::Text => "TEXT",
+            ValueType::Time => "TIME",
+            ValueType::Uri => "URI",
+            ValueType::UtcOffset => "UTC-OFFSET",
+            ValueType::Unknown => "UNKNOWN",
+        }
+    }
+
+    /// the value type of a property if it has no `VALUE` param,
+    /// see the property definitions in RFC 5545 section 3.7 and 3.8 and RFC 7986
+    pub fn default_for(property_name: &str) -> ValueType {
+        match property_name.to_ascii_uppercase().as_str() {
+            "ACTION" | "CALSCALE" | "CATEGORIES" | "CLASS" | "COLOR" | "COMMENT" | "CONTACT"
+            | "DESCRIPTION" | "LOCATION" | "METHOD" | "NAME" | "PRODID" | "RELATED-TO"
+            | "REQUEST-STATUS" | "RESOURCES" | "STATUS" | "SUMMARY" | "TRANSP" | "TZID"
+            | "TZNAME" | "UID" | "VERSION" => ValueType::Text,
+            "ATTACH" | "CONFERENCE" | "IMAGE" | "SOURCE" | "TZURL" | "URL" => ValueType::Uri,
+            "ATTENDEE" | "ORGANIZER" => ValueType::CalAddress,
+            "COMPLETED" | "CREATED" | "DTEND" | "DTSTAMP" | "DTSTART" | "DUE" | "EXDATE"
+            | "LAST-MODIFIED" | "RDATE" | "RECURRENCE-ID" => ValueType::DateTime,
+            "DURATION" | "REFRESH-INTERVAL" | "TRIGGER" => ValueType::Duration,
+            "FREEBUSY" => ValueType::Period,
+            "GEO" => ValueType::Float,
+            "PERCENT-COMPLETE" | "PRIORITY" | "REPEAT" | "SEQUENCE" => ValueType::Integer,
+            "EXRULE" | "RRULE" => ValueType::Recur,
+            "TZOFFSETFROM" | "TZOFFSETTO" => ValueType::UtcOffset,
+            _ => ValueType::Unknown,
+        }
+    }
+
+    /// the value type of a content line, its `VALUE` param or the default
+    pub fn of(line: &ContentLine) -> ValueType {
+        match line.param("VALUE").and_then(|param| param.values().first()) {
+            Some(value) => ValueType::from_name(value),
+            None => ValueType::default_for(&line.name),
+        }
+    }
+}
+
+/// whether the value of a property is a comma separated list
+pub fn is_multi_valued(property_name: &str) -> bool {
+    ["CATEGORIES", "EXDATE", "FREEBUSY", "RDATE", "RESOURCES"]
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(property_name))
+}
+
+/// whether the value of a property is a ';' separated structure
+pub fn is_structured(property_name: &str) -> bool {
+    ["GEO", "REQUEST-STATUS"]
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(property_name))
+}
+
+/// RECUR rule parts whose values are integers
+pub fn is_integer_recur_part(part_name: &str) -> bool {
+    [
+        "BYHOUR",
+        "BYMINUTE",
+        "BYMONTH",
+        "BYMONTHDAY",
+        "BYSECOND",
+        "BYSETPOS",
+        "BYWEEKNO",
+        "BYYEARDAY",
+        "COUNT",
+        "INTERVAL",
+    ]
+    .iter()
+    .any(|name| name.eq_ignore_ascii_case(part_name))
+}
+
+/// converts a DATE, DATE-TIME, TIME, UTC-OFFSET or PERIOD value
+/// from the basic format of RFC 5545 (`20220101T090000Z`) to the extended
+/// format used by jCal and xCal (`2022-01-01T09:00:00Z`),
+/// other types and malformed values are returned unchanged
+pub fn to_extended(value_type: ValueType, value: &str) -> String {
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    let time = |time: &str| -> Option<String> {
+        let (digits, utc) = match time.strip_suffix('Z') {
+            Some(digits) => (digits, "Z"),
+            None => (time, ""),
+        };
+        if digits.len() != 6 || !is_digits(digits) {
+            return None;
+        }
+        Some(format!(
+            "{}:{}:{}{}",
+            &digits[0..2],
+            &digits[2..4],
+            &digits[4..6],
+            utc
+        ))
+    };
+    let date = |date: &str| -> Option<String> {
+        if date.len() != 8 || !is_digits(date) {
+            return None;
+        }
+        Some(format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8]))
+    };
+    let date_time = |value: &str| -> Option<String> {
+        let (d, t) = value.split_once('T')?;
+        Some(format!("{}T{}", date(d)?, time(t)?))
+    };
+    let converted = match value_type {
+        ValueType::Date => date(value),
+        ValueType::DateTime => date_time(value),
+        ValueType::Time => time(value),
+        ValueType::UtcOffset => value
+            .strip_prefix(['+', '-'])
+            .filter(|digits| (digits.len() == 4 || digits.len() == 6) && is_digits(digits))
+            .map(|digits| {
+                let mut out = format!("{}{}:{}", &value[..1], &digits[0..2], &digits[2..4]);
+                if digits.len() == 6 {
+                    out.push(':');
+                    out.push_str(&digits[4..6]);
+                }
+                out
+            }),
+        ValueType::Period => value.split_once('/').and_then(|(start, end)| {
+            let end = if end.trim_start_matches(['+', '-']).starts_with('P') {
+                end.to_string()
+            } else {
+                date_time(end)?
+            };
+            Some(format!("{}/{}", date_time(start)?, end))
+        }),
+        _ => None,
+    };
+    converted.unwrap_or_else(|| value.to_string())
+}
+
+/// the inverse of [to_extended]
+pub fn from_extended(value_type: ValueType, value: &str) -> String {
+    let strip = |value: &str| value.replace(['-', ':'], "");
+    match value_type {
+        ValueType::Date | ValueType::DateTime | ValueType::Time => strip(value),
+        ValueType::UtcOffset => {
+            // keep the sign
+            match value.strip_prefix(['+', '-']) {
+                Some(rest) => format!("{}{}", &value[..1], rest.replace(':', "")),
+                None => value.replace(':', ""),
+            }
+        }
+        ValueType::Period => match value.split_once('/') {
+            Some((start, end)) if end.trim_start_matches(['+', '-']).starts_with('P') => {
+                format!("{}/{}", strip(start), end)
+            }
+            Some((start, end)) => format!("{}/{}", strip(start), strip(end)),
+            None => value.to_string(),
+        },
+        _ => value.to_string(),
+    }
+}
+
+/// resolves the backslash escapes of a TEXT value
+pub fn unescape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// escapes backslashes, semicolons, commas and newlines of a TEXT value
+pub fn escape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// splits a value at every `separator` that is not escaped by a backslash,
+/// the parts are not unescaped
+pub fn split_unescaped(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            parts.push(&value[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// whether two CAL-ADDRESS values are the same, `mailto:` is case-insensitive
+pub fn same_address(a: &str, b: &str) -> bool {
+    let strip = |address: &str| {
+        let address = address.trim();
+        match address.get(..7) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => address[7..].to_string(),
+            _ => address.to_string(),
+        }
+    };
+    strip(a).eq_ignore_ascii_case(&strip(b))
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_types_and_text_escapes() {
+        let line: ContentLine = "DTSTART;value=date:20220101".parse().unwrap();
+        assert_eq!(ValueType::of(&line), ValueType::Date);
+        let line: ContentLine = "dtstart:20220101T100000".parse().unwrap();
+        assert_eq!(ValueType::of(&line), ValueType::DateTime);
+        assert_eq!(ValueType::default_for("X-WHATEVER"), ValueType::Unknown);
+
+        let value = r"a\, b\; c\\,second\nline";
+        let parts = split_unescaped(value, ',');
+        assert_eq!(parts, [r"a\, b\; c\\", r"second\nline"]);
+        assert_eq!(unescape_text(parts[0]), r"a, b; c\");
+        assert_eq!(unescape_text(parts[1]), "second\nline");
+        assert_eq!(escape_text(&unescape_text(parts[0])), parts[0]);
+
+        for (value_type, basic, extended) in [
+            (ValueType::Date, "20220101", "2022-01-01"),
+            (
+                ValueType::DateTime,
+                "20220101T090000Z",
+                "2022-01-01T09:00:00Z",
+            ),
+            (ValueType::Time, "230000", "23:00:00"),
+            (ValueType::UtcOffset, "-0500", "-05:00"),
+            (ValueType::UtcOffset, "+013045", "+01:30:45"),
+            (
+                ValueType::Period,
+                "19970101T180000Z/PT5H30M",
+                "1997-01-01T18:00:00Z/PT5H30M",
+            ),
+            (
+                ValueType::Period,
+                "19970101T180000Z/19970102T070000Z",
+                "1997-01-01T18:00:00Z/1997-01-02T07:00:00Z",
+            ),
+        ] {
+            assert_eq!(to_extended(value_type, basic), extended);
+            assert_eq!(from_extended(value_type, extended), basic);
+        }
+        assert_eq!(to_extended(ValueType::Date, "garbage"), "garbage");
+        // untrusted input that is not ASCII
+        assert_eq!(to_extended(ValueType::UtcOffset, "é100"), "é100");
+        assert_eq!(from_extended(ValueType::UtcOffset, "é1:00"), "é100");
+        assert!(same_address(
+            "MAILTO:Ana@Example.com",
+            " mailto:ana@example.com"
+        ));
+        assert!(!same_address("mailto:ana@example.com", "ana@example.org"));
+    }
+}
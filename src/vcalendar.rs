@@ -0,0 +1,741 @@
+// vCalendar 1.0, see https://www.imc.org/pdi/vcal-10.txt
+//
+// 1.0 shares the line syntax of vCard 2.1 and is read like it, see
+// [crate::vcard_legacy], to_icalendar then rewrites what RFC 5545 replaced:
+// TZ and DAYLIGHT become a VTIMEZONE, the *ALARM properties VALARM components
+// and the 1.0 RRULE grammar the RFC 5545 one
+
+use std::io::{BufRead, Cursor};
+
+use eyre::{eyre, Result};
+
+use crate::{
+    content_line::{ContentLine, Param},
+    datetime::{format_duration, parse_duration, DateTime},
+    ical_object::{ChildRef, ICalObject},
+    recurrence::WEEKDAYS,
+    unfold::Unfold,
+    value::split_unescaped,
+    vcard_legacy,
+};
+
+/// reads a 1.0 VCALENDAR as it is, see [to_icalendar] for the conversion
+pub fn from_bufread(read: &mut impl BufRead) -> Result<ICalObject> {
+    let mut lines = Unfold::new(read)
+        .legacy(true)
+        .map(|line| line.and_then(|line| vcard_legacy::parse_line(&line)));
+    ICalObject::from_iterator(&mut lines)
+}
+
+pub fn parse(s: &str) -> Result<ICalObject> {
+    from_bufread(&mut Cursor::new(s))
+}
+
+// the UTC offsets given by TZ and DAYLIGHT, in seconds
+struct TimeZone {
+    id: String,
+    standard: i64,
+    // local start, local end and offset of each daylight saving period
+    daylight: Vec<(DateTime, DateTime, i64)>,
+}
+
+impl TimeZone {
+    fn to_utc(&self, local: DateTime) -> DateTime {
+        let offset = self
+            .daylight
+            .iter()
+            .find(|(start, end, _)| *start <= local && local < *end)
+            .map_or(self.standard, |&(_, _, offset)| offset);
+        local.add_seconds(-offset)
+    }
+}
+
+/// converts a 1.0 VCALENDAR to 2.0
+///
+/// floating times are in the time zone of TZ, so they get the TZID of the
+/// generated VTIMEZONE, or are converted to UTC where RFC 5545 requires it
+pub fn to_icalendar(object: &ICalObject) -> Result<ICalObject> {
+    let is_v1 = object
+        .get_property("VERSION")
+        .is_some_and(|line| line.value.trim() == "1.0");
+    if !object.is_type("VCALENDAR") || !is_v1 {
+        return Err(eyre!("not a vCalendar 1.0 object"));
+    }
+    let time_zone = time_zone(object)?;
+    let mut calendar = ICalObject {
+        object_type: object.object_type.clone(),
+        ..Default::default()
+    };
+    let mut sub_objects = Vec::new();
+    for child in object.children() {
+        match child {
+            ChildRef::Property(line) if line.name_eq("VERSION") => {
+                calendar.push_property(ContentLine::plain("VERSION", "2.0".to_string()));
+            }
+            ChildRef::Property(line) if line.name_eq("TZ") || line.name_eq("DAYLIGHT") => {}
+            ChildRef::Property(line) => calendar.push_property(line.clone()),
+            ChildRef::SubObject(sub_object) => sub_objects.push(convert_component(
+                sub_object,
+                time_zone.as_ref().map(|(time_zone, _)| time_zone),
+            )?),
+        }
+    }
+    if calendar.get_property("PRODID").is_none() {
+        calendar.push_property(ContentLine::plain(
+            "PRODID",
+            "-//elikoga-ical-rs//EN".to_string(),
+        ));
+    }
+    if let Some((_, vtimezone)) = time_zone {
+        calendar.push_sub_object(vtimezone);
+    }
+    for sub_object in sub_objects {
+        calendar.push_sub_object(sub_object);
+    }
+    Ok(calendar)
+}
+
+fn object(object_type: &str) -> ICalObject {
+    ICalObject {
+        object_type: object_type.to_string(),
+        ..Default::default()
+    }
+}
+
+// parses an offset like `-05`, `-05:00` or `+0530` into seconds
+fn parse_offset(value: &str) -> Result<i64> {
+    let invalid = || eyre!("invalid UTC offset: {}", value);
+    let value = value.trim();
+    let (sign, rest) = match value.as_bytes().first() {
+        Some(b'-') => (-1, &value[1..]),
+        Some(b'+') => (1, &value[1..]),
+        _ => (1, value),
+    };
+    let digits = rest.replace(':', "");
+    if digits.is_empty() || digits.len() > 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.as_str(), "0"),
+        length => digits.split_at(length - 2),
+    };
+    let (hours, minutes): (i64, i64) = (hours.parse()?, minutes.parse()?);
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok(sign * (hours * 3600 + minutes * 60))
+}
+
+fn format_offset(seconds: i64) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    format!("{}{:02}{:02}", sign, seconds / 3600, seconds % 3600 / 60)
+}
+
+// builds the VTIMEZONE of TZ and the DAYLIGHT periods, whose values are
+// `TRUE;offset;start;end;standard name;daylight name` or `FALSE`
+fn time_zone(calendar: &ICalObject) -> Result<Option<(TimeZone, ICalObject)>> {
+    let Some(line) = calendar.get_property("TZ") else {
+        return Ok(None);
+    };
+    let standard = parse_offset(&line.value)?;
+    let id = format!("UTC{}", format_offset(standard));
+    let mut vtimezone = object("VTIMEZONE");
+    vtimezone.push_property(ContentLine::plain("TZID", id.clone()));
+    vtimezone.push_sub_object(observance(
+        "STANDARD",
+        DateTime::new(1970, 1, 1, 0, 0, 0),
+        standard,
+        standard,
+        None,
+    ));
+    let mut daylight = Vec::new();
+    for line in calendar.get_properties("DAYLIGHT") {
+        let fields = split_unescaped(&line.value, ';');
+        if !fields[0].trim().eq_ignore_ascii_case("TRUE") {
+            continue;
+        }
+        let field = |i: usize| {
+            fields
+                .get(i)
+                .map(|field| field.trim())
+                .filter(|field| !field.is_empty())
+                .ok_or_else(|| eyre!("incomplete DAYLIGHT: {}", line.value))
+        };
+        let offset = parse_offset(field(1)?)?;
+        // UTC times are converted to the local time before the change
+        let local = |value: &str, offset: i64| -> Result<DateTime> {
+            let date_time = DateTime::parse(value)?;
+            Ok(match value.ends_with('Z') {
+                true => date_time.add_seconds(offset),
+                false => date_time,
+            })
+        };
+        let (start, end) = (local(field(2)?, standard)?, local(field(3)?, offset)?);
+        vtimezone.push_sub_object(observance(
+            "DAYLIGHT",
+            start,
+            standard,
+            offset,
+            field(5).ok(),
+        ));
+        vtimezone.push_sub_object(observance("STANDARD", end, offset, standard, field(4).ok()));
+        daylight.push((start, end, offset));
+    }
+    let time_zone = TimeZone {
+        id,
+        standard,
+        daylight,
+    };
+    Ok(Some((time_zone, vtimezone)))
+}
+
+fn observance(
+    object_type: &str,
+    start: DateTime,
+    from: i64,
+    to: i64,
+    name: Option<&str>,
+) -> ICalObject {
+    let mut observance = object(object_type);
+    observance.push_property(ContentLine::plain("DTSTART", start.to_string()));
+    observance.push_property(ContentLine::plain("TZOFFSETFROM", format_offset(from)));
+    observance.push_property(ContentLine::plain("TZOFFSETTO", format_offset(to)));
+    if let Some(name) = name {
+        observance.push_property(ContentLine::plain("TZNAME", name.to_string()));
+    }
+    observance
+}
+
+fn is_floating(value: &str) -> bool {
+    value.contains('T') && !value.ends_with('Z')
+}
+
+fn to_utc(value: &str, time_zone: &TimeZone) -> Result<String> {
+    Ok(format!("{}Z", time_zone.to_utc(DateTime::parse(value)?)))
+}
+
+// 1.0 text is not escaped, but may contain `\;`
+fn escape_text(value: &str) -> String {
+    split_unescaped(value, ';')
+        .iter()
+        .map(|part| split_unescaped(part, ',').join("\\,"))
+        .collect::<Vec<_>>()
+        .join("\\;")
+}
+
+fn convert_component(component: &ICalObject, time_zone: Option<&TimeZone>) -> Result<ICalObject> {
+    let mut converted = object(&component.object_type);
+    let mut alarms = Vec::new();
+    for child in component.children() {
+        match child {
+            ChildRef::SubObject(sub_object) => {
+                converted.push_sub_object(convert_component(sub_object, time_zone)?)
+            }
+            ChildRef::Property(line)
+                if ["AALARM", "DALARM", "MALARM"]
+                    .iter()
+                    .any(|name| line.name_eq(name)) =>
+            {
+                alarms.push(alarm(line, component, time_zone)?)
+            }
+            ChildRef::Property(line) => {
+                if let Some(line) = convert_property(&component.object_type, line, time_zone)? {
+                    converted.push_property(line);
+                }
+            }
+        }
+    }
+    for alarm in alarms {
+        converted.push_sub_object(alarm);
+    }
+    Ok(converted)
+}
+
+fn convert_property(
+    object_type: &str,
+    line: &ContentLine,
+    time_zone: Option<&TimeZone>,
+) -> Result<Option<ContentLine>> {
+    let mut line = line.clone();
+    match line.name.to_ascii_uppercase().as_str() {
+        "DTSTART" | "DTEND" | "DUE" | "RECURRENCE-ID" | "RDATE" | "EXDATE" => {
+            localize(&mut line, time_zone)?;
+        }
+        name @ ("DCREATED" | "CREATED" | "LAST-MODIFIED" | "COMPLETED" | "DTSTAMP") => {
+            if name == "DCREATED" {
+                line.name = "CREATED".to_string();
+            }
+            if let Some(time_zone) = time_zone.filter(|_| is_floating(&line.value)) {
+                line.value = to_utc(line.value.trim(), time_zone)?;
+            }
+        }
+        "RRULE" | "EXRULE" => {
+            // UNTIL must be UTC when DTSTART has a TZID
+            line.value = rrule_to_icalendar(&line.value)?
+                .split(';')
+                .map(|part| match (part.strip_prefix("UNTIL="), time_zone) {
+                    (Some(until), Some(time_zone)) if is_floating(until) => {
+                        Ok(format!("UNTIL={}", to_utc(until, time_zone)?))
+                    }
+                    _ => Ok(part.to_string()),
+                })
+                .collect::<Result<Vec<_>>>()?
+                .join(";");
+        }
+        "CATEGORIES" | "RESOURCES" => {
+            line.value = split_unescaped(&line.value, ';')
+                .iter()
+                .map(|value| split_unescaped(value.trim(), ',').join("\\,"))
+                .collect::<Vec<_>>()
+                .join(",");
+        }
+        "SUMMARY" | "DESCRIPTION" | "LOCATION" => line.value = escape_text(&line.value),
+        "TRANSP" => {
+            if let Ok(transparency) = line.value.trim().parse::<u32>() {
+                line.value = match transparency {
+                    0 => "OPAQUE".to_string(),
+                    _ => "TRANSPARENT".to_string(),
+                };
+            }
+        }
+        "STATUS" => match status(object_type, &line.value) {
+            Some(status) => line.value = status.to_string(),
+            None => return Ok(None),
+        },
+        "ATTENDEE" => line = attendee(&line),
+        // procedure alarms were dropped by RFC 5545
+        "PALARM" => line.name = "X-PALARM".to_string(),
+        _ => {}
+    }
+    Ok(Some(line))
+}
+
+// adds the TZID to floating date-times, if floating and UTC times are mixed,
+// the floating ones become UTC
+fn localize(line: &mut ContentLine, time_zone: Option<&TimeZone>) -> Result<()> {
+    let values: Vec<&str> = line
+        .value
+        .split([';', ','])
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect();
+    if values.is_empty() {
+        return Ok(());
+    }
+    if values.iter().all(|value| !value.contains('T')) && line.param("VALUE").is_none() {
+        line.params
+            .push(Param::new("VALUE".to_string(), vec!["DATE".to_string()]));
+    }
+    line.value = match time_zone {
+        Some(time_zone) if values.iter().all(|value| is_floating(value)) => {
+            line.params
+                .push(Param::new("TZID".to_string(), vec![time_zone.id.clone()]));
+            values.join(",")
+        }
+        Some(time_zone) => values
+            .iter()
+            .map(|value| match is_floating(value) {
+                true => to_utc(value, time_zone),
+                false => Ok(value.to_string()),
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join(","),
+        None => values.join(","),
+    };
+    Ok(())
+}
+
+// 1.0 has one set of STATUS values for all components, RFC 5545 one per component
+fn status(object_type: &str, value: &str) -> Option<&'static str> {
+    let value = value.trim().to_ascii_uppercase();
+    match (object_type.to_ascii_uppercase().as_str(), value.as_str()) {
+        (_, "CANCELLED" | "DECLINED") => Some("CANCELLED"),
+        ("VEVENT", "TENTATIVE" | "NEEDS ACTION" | "NEEDS-ACTION" | "SENT") => Some("TENTATIVE"),
+        ("VEVENT", "CONFIRMED" | "ACCEPTED") => Some("CONFIRMED"),
+        ("VTODO", "NEEDS ACTION" | "NEEDS-ACTION" | "SENT" | "TENTATIVE" | "DELEGATED") => {
+            Some("NEEDS-ACTION")
+        }
+        ("VTODO", "ACCEPTED" | "CONFIRMED" | "IN-PROCESS") => Some("IN-PROCESS"),
+        ("VTODO", "COMPLETED") => Some("COMPLETED"),
+        _ => None,
+    }
+}
+
+// the value is an address like `Jane Doe <jane@example.com>`, ROLE, STATUS,
+// RSVP and EXPECT have different values in 1.0
+fn attendee(line: &ContentLine) -> ContentLine {
+    let (name, address) = match line.value.rsplit_once('<') {
+        Some((name, address)) => (
+            Some(name.trim().trim_matches('"')),
+            address.trim_end().trim_end_matches('>'),
+        ),
+        None => (None, line.value.trim()),
+    };
+    let address = match address.contains(':') {
+        true => address.to_string(),
+        false => format!("mailto:{}", address),
+    };
+    let first_value = |name: &str| {
+        line.param(name)
+            .and_then(|param| param.values().first())
+            .map(|value| value.to_ascii_uppercase())
+    };
+    let mut params = Vec::new();
+    let mut push = |name: &str, value: &str| {
+        params.push(Param::new(name.to_string(), vec![value.to_string()]))
+    };
+    if let Some(name) = name.filter(|name| !name.is_empty()) {
+        push("CN", name);
+    }
+    let (role, expect) = (first_value("ROLE"), first_value("EXPECT"));
+    if role.as_deref() == Some("ORGANIZER") {
+        return ContentLine::new("ORGANIZER".to_string(), params, address);
+    }
+    let role = match (role.as_deref(), expect.as_deref()) {
+        (Some("OWNER"), _) => Some("CHAIR"),
+        (_, Some("FYI")) => Some("NON-PARTICIPANT"),
+        (_, Some("REQUEST")) => Some("OPT-PARTICIPANT"),
+        (None, None) => None,
+        _ => Some("REQ-PARTICIPANT"),
+    };
+    if let Some(role) = role {
+        push("ROLE", role);
+    }
+    let status = first_value("STATUS").and_then(|status| match status.as_str() {
+        "NEEDS ACTION" | "NEEDS-ACTION" | "SENT" => Some("NEEDS-ACTION"),
+        "ACCEPTED" | "CONFIRMED" => Some("ACCEPTED"),
+        "TENTATIVE" => Some("TENTATIVE"),
+        "DECLINED" => Some("DECLINED"),
+        "COMPLETED" => Some("COMPLETED"),
+        "DELEGATED" => Some("DELEGATED"),
+        _ => None,
+    });
+    if let Some(status) = status {
+        push("PARTSTAT", status);
+    }
+    match first_value("RSVP").as_deref() {
+        Some("YES" | "TRUE") => push("RSVP", "TRUE"),
+        Some("NO" | "FALSE") => push("RSVP", "FALSE"),
+        _ => {}
+    }
+    params.extend(
+        line.params
+            .iter()
+            .filter(|param| {
+                !["ROLE", "EXPECT", "STATUS", "RSVP", "TYPE"]
+                    .iter()
+                    .any(|name| param.name_eq(name))
+            })
+            .cloned(),
+    );
+    ContentLine::new(line.name.clone(), params, address)
+}
+
+// the values of DALARM, AALARM and MALARM start with
+// `run time;snooze time;repeat count`, followed by the display string,
+// the audio content or the address and note of the mail
+fn alarm(
+    line: &ContentLine,
+    component: &ICalObject,
+    time_zone: Option<&TimeZone>,
+) -> Result<ICalObject> {
+    let fields = split_unescaped(&line.value, ';');
+    let field = |i: usize| fields.get(i).map_or("", |field| field.trim());
+    let summary = component
+        .get_property("SUMMARY")
+        .map(|line| escape_text(&line.value))
+        .unwrap_or_else(|| "Reminder".to_string());
+    let text = |i: usize| match field(i) {
+        "" => summary.clone(),
+        text => escape_text(text),
+    };
+    let mut alarm = object("VALARM");
+    let action = match line.name.to_ascii_uppercase().as_str() {
+        "DALARM" => "DISPLAY",
+        "AALARM" => "AUDIO",
+        _ => "EMAIL",
+    };
+    alarm.push_property(ContentLine::plain("ACTION", action.to_string()));
+    alarm.push_property(trigger(field(0), component, time_zone)?);
+    match action {
+        "DISPLAY" => alarm.push_property(ContentLine::plain("DESCRIPTION", text(3))),
+        "AUDIO" if !field(3).is_empty() => {
+            alarm.push_property(ContentLine::plain("ATTACH", field(3).to_string()))
+        }
+        "EMAIL" => {
+            alarm.push_property(ContentLine::plain("SUMMARY", summary.clone()));
+            alarm.push_property(ContentLine::plain("DESCRIPTION", text(4)));
+            alarm.push_property(ContentLine::plain(
+                "ATTENDEE",
+                format!("mailto:{}", field(3)),
+            ));
+        }
+        _ => {}
+    }
+    let repeat: u32 = field(2).parse().unwrap_or(0);
+    if repeat > 0 && !field(1).is_empty() {
+        let snooze = format_duration(parse_duration(field(1))?);
+        alarm.push_property(ContentLine::plain("DURATION", snooze));
+        alarm.push_property(ContentLine::plain("REPEAT", repeat.to_string()));
+    }
+    Ok(alarm)
+}
+
+// RFC 5545 wants absolute triggers in UTC, without a TZ a floating run time
+// becomes relative to a floating DTSTART or DUE, otherwise it is taken as UTC
+fn trigger(
+    run_time: &str,
+    component: &ICalObject,
+    time_zone: Option<&TimeZone>,
+) -> Result<ContentLine> {
+    let time = DateTime::parse(run_time)?;
+    let absolute = |time: DateTime| {
+        ContentLine::new(
+            "TRIGGER".to_string(),
+            vec![Param::new(
+                "VALUE".to_string(),
+                vec!["DATE-TIME".to_string()],
+            )],
+            format!("{}Z", time),
+        )
+    };
+    if !is_floating(run_time) {
+        return Ok(absolute(time));
+    }
+    if let Some(time_zone) = time_zone {
+        return Ok(absolute(time_zone.to_utc(time)));
+    }
+    let related = ["DTSTART", "DUE"].iter().find_map(|name| {
+        let line = component.get_property(name)?;
+        let related = DateTime::parse(line.value.trim()).ok()?;
+        is_floating(&line.value).then_some((*name, related))
+    });
+    Ok(match related {
+        Some((name, related)) => {
+            let params = match name {
+                "DUE" => vec![Param::new("RELATED".to_string(), vec!["END".to_string()])],
+                _ => Vec::new(),
+            };
+            let duration = time.timestamp() - related.timestamp();
+            ContentLine::new("TRIGGER".to_string(), params, format_duration(duration))
+        }
+        None => absolute(time),
+    })
+}
+
+// `1+` and `1` count from the start, `1-` from the end and `LD` is the last day
+fn occurrence(word: &str) -> Option<i32> {
+    if word == "LD" {
+        return Some(-1);
+    }
+    let (digits, sign) = match word.strip_suffix('-') {
+        Some(digits) => (digits, -1),
+        None => (word.strip_suffix('+').unwrap_or(word), 1),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<i32>().ok().map(|number| sign * number)
+}
+
+/// converts a 1.0 RRULE like `W1 MO TH #10` or `MP1 1+ MO 1- FR 20241231T000000`
+/// to RFC 5545, without a count or end date a rule repeats twice like in 1.0,
+/// nested rules like `YM1 6 MP1 1+ SU` are not supported
+pub fn rrule_to_icalendar(rule: &str) -> Result<String> {
+    let invalid = || eyre!("invalid vCalendar 1.0 RRULE: {}", rule);
+    let words: Vec<&str> = rule.split_whitespace().collect();
+    let (first, words) = words.split_first().ok_or_else(invalid)?;
+    let split = first
+        .find(|c: char| c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (kind, interval) = first.split_at(split);
+    let interval: u32 = interval.parse().map_err(|_| invalid())?;
+    let (frequency, by) = match kind {
+        "D" => ("DAILY", "BYDAY"),
+        "W" => ("WEEKLY", "BYDAY"),
+        "MP" => ("MONTHLY", "BYDAY"),
+        "MD" => ("MONTHLY", "BYMONTHDAY"),
+        "YM" => ("YEARLY", "BYMONTH"),
+        "YD" => ("YEARLY", "BYYEARDAY"),
+        _ => return Err(invalid()),
+    };
+    let mut end = Some("COUNT=2".to_string());
+    let mut values = Vec::new();
+    let (mut hours, mut minutes) = (Vec::new(), Vec::new());
+    // the occurrences that the following weekdays of MP apply to
+    let mut occurrences = Vec::new();
+    let mut after_weekday = false;
+    for (i, word) in words.iter().enumerate() {
+        let is_last = i + 1 == words.len();
+        if let Some(count) = word.strip_prefix('#') {
+            let count: u32 = count.parse().map_err(|_| invalid())?;
+            end = (count > 0).then(|| format!("COUNT={}", count));
+        } else if is_last && word.len() >= 8 && DateTime::parse(word).is_ok() {
+            end = Some(format!("UNTIL={}", word));
+        } else if WEEKDAYS.contains(word) && kind == "W" {
+            values.push(word.to_string());
+        } else if WEEKDAYS.contains(word) && kind == "MP" {
+            if occurrences.is_empty() {
+                return Err(invalid());
+            }
+            values.extend(
+                occurrences
+                    .iter()
+                    .map(|occurrence| format!("{}{}", occurrence, word)),
+            );
+            after_weekday = true;
+        } else if ["D", "W", "MP", "MD", "YM", "YD"].iter().any(|kind| {
+            word.strip_prefix(kind)
+                .is_some_and(|rest| rest.parse::<u32>().is_ok())
+        }) {
+            return Err(eyre!(
+                "nested vCalendar 1.0 rules are not supported: {}",
+                rule
+            ));
+        } else if kind == "D" && word.len() == 4 && word.bytes().all(|b| b.is_ascii_digit()) {
+            hours.push(word[0..2].parse::<u32>()?);
+            minutes.push(word[2..4].parse::<u32>()?);
+        } else if kind == "MP" {
+            if after_weekday {
+                occurrences.clear();
+                after_weekday = false;
+            }
+            occurrences.push(occurrence(word).ok_or_else(invalid)?);
+        } else if ["MD", "YM", "YD"].contains(&kind) {
+            values.push(occurrence(word).ok_or_else(invalid)?.to_string());
+        } else {
+            return Err(invalid());
+        }
+    }
+    if kind == "MP" && !occurrences.is_empty() && !after_weekday {
+        return Err(invalid());
+    }
+    let mut parts = vec![format!("FREQ={}", frequency)];
+    if interval != 1 {
+        parts.push(format!("INTERVAL={}", interval));
+    }
+    parts.extend(end);
+    // the times of D are approximated, BYHOUR and BYMINUTE combine all pairs
+    for (name, mut list) in [("BYHOUR", hours), ("BYMINUTE", minutes)] {
+        list.sort_unstable();
+        list.dedup();
+        if !list.is_empty() {
+            let list: Vec<String> = list.iter().map(u32::to_string).collect();
+            parts.push(format!("{}={}", name, list.join(",")));
+        }
+    }
+    if !values.is_empty() {
+        parts.push(format!("{}={}", by, values.join(",")));
+    }
+    Ok(parts.join(";"))
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_icalendar() {
+        let input = "BEGIN:VCALENDAR\r
+VERSION:1.0\r
+TZ:-05\r
+DAYLIGHT:TRUE;-04;20240310T020000;20241103T020000;EST;EDT\r
+BEGIN:VEVENT\r
+DTSTART:20240115T090000\r
+DTEND:20240115T100000\r
+SUMMARY;ENCODING=QUOTED-PRINTABLE;CHARSET=ISO-8859-1:Caf=E9, Kuchen=\r
+ und mehr\r
+CATEGORIES:MEETING;WORK\r
+RRULE:W1 MO TH #10\r
+ATTENDEE;ROLE=ATTENDEE;STATUS=NEEDS ACTION;RSVP=YES:Jane Doe <jane@example.com>\r
+DALARM:20240115T085000;PT5M;2;Coffee\r
+AALARM;TYPE=WAVE:20240115T085500;;;file:///bell.wav\r
+STATUS:NEEDS ACTION\r
+TRANSP:0\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let calendar = parse(input).unwrap();
+        assert_eq!(
+            calendar.sub_objects[0]
+                .get_property("SUMMARY")
+                .unwrap()
+                .value,
+            "Café, Kuchen und mehr"
+        );
+        assert_eq!(
+            to_icalendar(&calendar).unwrap().to_string(),
+            "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//elikoga-ical-rs//EN\r
+BEGIN:VTIMEZONE\r
+TZID:UTC-0500\r
+BEGIN:STANDARD\r
+DTSTART:19700101T000000\r
+TZOFFSETFROM:-0500\r
+TZOFFSETTO:-0500\r
+END:STANDARD\r
+BEGIN:DAYLIGHT\r
+DTSTART:20240310T020000\r
+TZOFFSETFROM:-0500\r
+TZOFFSETTO:-0400\r
+TZNAME:EDT\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+DTSTART:20241103T020000\r
+TZOFFSETFROM:-0400\r
+TZOFFSETTO:-0500\r
+TZNAME:EST\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+DTSTART;TZID=UTC-0500:20240115T090000\r
+DTEND;TZID=UTC-0500:20240115T100000\r
+SUMMARY:Café\\, Kuchen und mehr\r
+CATEGORIES:MEETING,WORK\r
+RRULE:FREQ=WEEKLY;COUNT=10;BYDAY=MO,TH\r
+ATTENDEE;CN=Jane Doe;ROLE=REQ-PARTICIPANT;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:m\r
+ ailto:jane@example.com\r
+STATUS:TENTATIVE\r
+TRANSP:OPAQUE\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER;VALUE=DATE-TIME:20240115T135000Z\r
+DESCRIPTION:Coffee\r
+DURATION:PT5M\r
+REPEAT:2\r
+END:VALARM\r
+BEGIN:VALARM\r
+ACTION:AUDIO\r
+TRIGGER;VALUE=DATE-TIME:20240115T135500Z\r
+ATTACH:file:///bell.wav\r
+END:VALARM\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        );
+    }
+
+    #[test]
+    fn converts_rules() {
+        let cases = [
+            ("D2 #5", "FREQ=DAILY;INTERVAL=2;COUNT=5"),
+            ("D1 0800 1200 #0", "FREQ=DAILY;BYHOUR=8,12;BYMINUTE=0"),
+            ("W1 MO TH", "FREQ=WEEKLY;COUNT=2;BYDAY=MO,TH"),
+            (
+                "MP1 1+ 2- MO 1- FR 20241231T000000Z",
+                "FREQ=MONTHLY;UNTIL=20241231T000000Z;BYDAY=1MO,-2MO,-1FR",
+            ),
+            ("MD1 1 LD #12", "FREQ=MONTHLY;COUNT=12;BYMONTHDAY=1,-1"),
+            ("YM1 6 7 #0", "FREQ=YEARLY;BYMONTH=6,7"),
+            ("YD3 100 #1", "FREQ=YEARLY;INTERVAL=3;COUNT=1;BYYEARDAY=100"),
+        ];
+        for (rule, expected) in cases {
+            assert_eq!(rrule_to_icalendar(rule).unwrap(), expected, "{}", rule);
+        }
+        assert!(rrule_to_icalendar("YM1 6 MP1 1+ SU #0").is_err());
+        assert!(rrule_to_icalendar("X1").is_err());
+    }
+}
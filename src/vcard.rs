@@ -0,0 +1,445 @@
+// vCard, see RFC 6350
+//
+// vCards share the folding and content line grammar with iCalendar, so they
+// are parsed into an ICalObject first and then into the typed VCard model,
+// properties without a typed field are kept in VCard::other
+
+use std::{
+    fmt::Display,
+    io::{BufRead, Cursor},
+    str::FromStr,
+};
+
+use eyre::{eyre, Result};
+
+use crate::{
+    content_line::{ContentLine, Param},
+    ical_object::ICalObject,
+    unfold::Unfold,
+    value::{escape_text, split_unescaped, unescape_text},
+    vcard_legacy,
+};
+
+/// a contact
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VCard {
+    pub version: String,
+    /// FN
+    pub formatted_name: Option<String>,
+    /// N
+    pub name: Option<Name>,
+    pub nicknames: Vec<String>,
+    /// ORG, the organization name followed by its units
+    pub organization: Vec<String>,
+    pub title: Option<String>,
+    /// TEL
+    pub telephones: Vec<Entry<String>>,
+    pub emails: Vec<Entry<String>>,
+    /// ADR
+    pub addresses: Vec<Entry<Address>>,
+    pub urls: Vec<Entry<String>>,
+    pub photo: Option<Entry<String>>,
+    /// BDAY
+    pub birthday: Option<String>,
+    pub note: Option<String>,
+    pub uid: Option<String>,
+    /// properties without a typed field, and typed properties whose params
+    /// or repetitions the typed field can not hold
+    pub other: Vec<ContentLine>,
+}
+
+/// the components of N, each of them may have multiple values
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Name {
+    pub family_names: Vec<String>,
+    pub given_names: Vec<String>,
+    pub additional_names: Vec<String>,
+    pub honorific_prefixes: Vec<String>,
+    pub honorific_suffixes: Vec<String>,
+}
+
+/// the components of ADR, each of them may have multiple values
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Address {
+    pub po_box: Vec<String>,
+    pub extended_address: Vec<String>,
+    pub street: Vec<String>,
+    pub locality: Vec<String>,
+    pub region: Vec<String>,
+    pub postal_code: Vec<String>,
+    pub country: Vec<String>,
+}
+
+/// a value of a property that may appear multiple times
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry<T> {
+    pub value: T,
+    /// the values of all TYPE params, like `work` or `cell`
+    pub types: Vec<String>,
+    /// all other params, like PREF
+    pub params: Vec<Param>,
+}
+
+impl<T> Entry<T> {
+    pub fn new(value: T) -> Self {
+        Entry {
+            value,
+            types: Vec::new(),
+            params: Vec::new(),
+        }
+    }
+
+    fn from_line(line: &ContentLine, value: T) -> Self {
+        let mut entry = Entry::new(value);
+        for param in &line.params {
+            if param.name_eq("TYPE") {
+                // 3.0 cards quote lists like TYPE="home,work"
+                for value in param.values() {
+                    entry.types.extend(value.split(',').map(str::to_string));
+                }
+            } else {
+                entry.params.push(param.clone());
+            }
+        }
+        entry
+    }
+
+    fn to_line(&self, name: &str, value: String) -> ContentLine {
+        let mut params = Vec::new();
+        if !self.types.is_empty() {
+            params.push(Param::new("TYPE".to_string(), self.types.clone()));
+        }
+        params.extend(self.params.iter().cloned());
+        ContentLine::new(name.to_string(), params, value)
+    }
+}
+
+impl Default for VCard {
+    fn default() -> Self {
+        VCard {
+            version: "4.0".to_string(),
+            formatted_name: None,
+            name: None,
+            nicknames: Vec::new(),
+            organization: Vec::new(),
+            title: None,
+            telephones: Vec::new(),
+            emails: Vec::new(),
+            addresses: Vec::new(),
+            urls: Vec::new(),
+            photo: None,
+            birthday: None,
+            note: None,
+            uid: None,
+            other: Vec::new(),
+        }
+    }
+}
+
+// a structured value, split at ';' and then at ','
+fn components(value: &str) -> Vec<Vec<String>> {
+    split_unescaped(value, ';')
+        .into_iter()
+        .map(|component| {
+            if component.is_empty() {
+                return Vec::new();
+            }
+            split_unescaped(component, ',')
+                .into_iter()
+                .map(unescape_text)
+                .collect()
+        })
+        .collect()
+}
+
+// the inverse of components
+fn join_components(components: &[&Vec<String>]) -> String {
+    components
+        .iter()
+        .map(|values| {
+            values
+                .iter()
+                .map(|value| escape_text(value))
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+impl VCard {
+    /// reads all vCards of a file
+    pub fn from_bufread_all(read: &mut impl BufRead) -> Result<Vec<VCard>> {
+        let mut lines = Unfold::new(read)
+            .map(|line| line.and_then(|line| line.parse::<ContentLine>()))
+            .peekable();
+        let mut cards = Vec::new();
+        while lines.peek().is_some() {
+            cards.push(VCard::from_ical_object(&ICalObject::from_peekable(
+                &mut lines,
+            )?)?);
+        }
+        Ok(cards)
+    }
+
+    pub fn parse_all(s: &str) -> Result<Vec<VCard>> {
+        VCard::from_bufread_all(&mut Cursor::new(s))
+    }
+
+    /// reads all vCards of a file that may contain 2.1 or 3.0 cards,
+    /// see [crate::vcard_legacy], the cards keep their version
+    pub fn from_bufread_all_legacy(read: &mut impl BufRead) -> Result<Vec<VCard>> {
+        let mut lines = Unfold::new(read)
+            .legacy(true)
+            .map(|line| line.and_then(|line| vcard_legacy::parse_line(&line)))
+            .peekable();
+        let mut cards = Vec::new();
+        while lines.peek().is_some() {
+            cards.push(VCard::from_ical_object(&ICalObject::from_peekable(
+                &mut lines,
+            )?)?);
+        }
+        Ok(cards)
+    }
+
+    pub fn parse_all_legacy(s: &str) -> Result<Vec<VCard>> {
+        VCard::from_bufread_all_legacy(&mut Cursor::new(s))
+    }
+
+    /// converts a 2.1 or 3.0 card to 4.0, see [vcard_legacy::upgrade_to_v4]
+    pub fn to_v4(&self) -> Result<VCard> {
+        let mut object = self.to_ical_object();
+        vcard_legacy::upgrade_to_v4(&mut object);
+        VCard::from_ical_object(&object)
+    }
+
+    pub fn from_ical_object(object: &ICalObject) -> Result<VCard> {
+        if !object.is_type("VCARD") {
+            return Err(eyre!("expected a VCARD, found {}", object.object_type));
+        }
+        if let Some(sub_object) = object.sub_objects.first() {
+            return Err(eyre!(
+                "unexpected component {} in a VCARD",
+                sub_object.object_type
+            ));
+        }
+        let mut card = VCard::default();
+        for line in &object.properties {
+            // properties that a plain field can not hold completely go to other
+            let text = |field: &Option<String>| match field {
+                None if line.params.is_empty() => Some(unescape_text(&line.value)),
+                _ => None,
+            };
+            match line.name.to_ascii_uppercase().as_str() {
+                "VERSION" => card.version = line.value.clone(),
+                "FN" if text(&card.formatted_name).is_some() => {
+                    card.formatted_name = text(&card.formatted_name)
+                }
+                "N" if card.name.is_none() && line.params.is_empty() => {
+                    let mut parts = components(&line.value).into_iter();
+                    let mut next = || parts.next().unwrap_or_default();
+                    card.name = Some(Name {
+                        family_names: next(),
+                        given_names: next(),
+                        additional_names: next(),
+                        honorific_prefixes: next(),
+                        honorific_suffixes: next(),
+                    });
+                }
+                "NICKNAME" if line.params.is_empty() => card.nicknames.extend(
+                    split_unescaped(&line.value, ',')
+                        .into_iter()
+                        .map(unescape_text),
+                ),
+                "ORG" if card.organization.is_empty() && line.params.is_empty() => {
+                    card.organization = split_unescaped(&line.value, ';')
+                        .into_iter()
+                        .map(unescape_text)
+                        .collect();
+                }
+                "TITLE" if text(&card.title).is_some() => card.title = text(&card.title),
+                "TEL" => card
+                    .telephones
+                    .push(Entry::from_line(line, line.value.clone())),
+                "EMAIL" => card
+                    .emails
+                    .push(Entry::from_line(line, unescape_text(&line.value))),
+                "ADR" => {
+                    let mut parts = components(&line.value).into_iter();
+                    let mut next = || parts.next().unwrap_or_default();
+                    let address = Address {
+                        po_box: next(),
+                        extended_address: next(),
+                        street: next(),
+                        locality: next(),
+                        region: next(),
+                        postal_code: next(),
+                        country: next(),
+                    };
+                    card.addresses.push(Entry::from_line(line, address));
+                }
+                "URL" => card.urls.push(Entry::from_line(line, line.value.clone())),
+                "PHOTO" if card.photo.is_none() => {
+                    card.photo = Some(Entry::from_line(line, line.value.clone()))
+                }
+                "BDAY" if text(&card.birthday).is_some() => card.birthday = text(&card.birthday),
+                "NOTE" if text(&card.note).is_some() => card.note = text(&card.note),
+                "UID" if card.uid.is_none() && line.params.is_empty() => {
+                    card.uid = Some(line.value.clone())
+                }
+                _ => card.other.push(line.clone()),
+            }
+        }
+        Ok(card)
+    }
+
+    /// the card as a VCARD object, VERSION first as RFC 6350 requires,
+    /// then the typed fields and then [VCard::other]
+    pub fn to_ical_object(&self) -> ICalObject {
+        let mut object = ICalObject {
+            object_type: "VCARD".to_string(),
+            ..ICalObject::default()
+        };
+        let property =
+            |name: &str, value: String| ContentLine::new(name.to_string(), Vec::new(), value);
+        object.push_property(property("VERSION", self.version.clone()));
+        if let Some(formatted_name) = &self.formatted_name {
+            object.push_property(property("FN", escape_text(formatted_name)));
+        }
+        if let Some(name) = &self.name {
+            object.push_property(property(
+                "N",
+                join_components(&[
+                    &name.family_names,
+                    &name.given_names,
+                    &name.additional_names,
+                    &name.honorific_prefixes,
+                    &name.honorific_suffixes,
+                ]),
+            ));
+        }
+        if !self.nicknames.is_empty() {
+            object.push_property(property("NICKNAME", join_components(&[&self.nicknames])));
+        }
+        if !self.organization.is_empty() {
+            let units: Vec<String> = self
+                .organization
+                .iter()
+                .map(|unit| escape_text(unit))
+                .collect();
+            object.push_property(property("ORG", units.join(";")));
+        }
+        if let Some(title) = &self.title {
+            object.push_property(property("TITLE", escape_text(title)));
+        }
+        for telephone in &self.telephones {
+            object.push_property(telephone.to_line("TEL", telephone.value.clone()));
+        }
+        for email in &self.emails {
+            object.push_property(email.to_line("EMAIL", escape_text(&email.value)));
+        }
+        for address in &self.addresses {
+            let value = &address.value;
+            object.push_property(address.to_line(
+                "ADR",
+                join_components(&[
+                    &value.po_box,
+                    &value.extended_address,
+                    &value.street,
+                    &value.locality,
+                    &value.region,
+                    &value.postal_code,
+                    &value.country,
+                ]),
+            ));
+        }
+        for url in &self.urls {
+            object.push_property(url.to_line("URL", url.value.clone()));
+        }
+        if let Some(photo) = &self.photo {
+            object.push_property(photo.to_line("PHOTO", photo.value.clone()));
+        }
+        if let Some(birthday) = &self.birthday {
+            object.push_property(property("BDAY", escape_text(birthday)));
+        }
+        if let Some(note) = &self.note {
+            object.push_property(property("NOTE", escape_text(note)));
+        }
+        if let Some(uid) = &self.uid {
+            object.push_property(property("UID", uid.clone()));
+        }
+        for line in &self.other {
+            object.push_property(line.clone());
+        }
+        object
+    }
+}
+
+impl FromStr for VCard {
+    type Err = eyre::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        VCard::from_ical_object(&s.parse()?)
+    }
+}
+
+impl Display for VCard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_ical_object())
+    }
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::{Entry, VCard};
+
+    #[test]
+    fn parses_and_serializes_cards() {
+        let input = "BEGIN:VCARD\r
+VERSION:4.0\r
+FN:Simon Perreault\r
+N:Perreault;Simon;;;ing. jr,M.Sc.\r
+ORG:Viagenie;Research\r
+TEL;TYPE=work,voice;PREF=1:tel:+1-418-656-9254;ext=102\r
+EMAIL;TYPE=work:simon.perreault@viagenie.ca\r
+ADR;TYPE=work:;Suite D2-630;2875 Laurier;Quebec;QC;G1V 2M2;Canada\r
+BDAY:--0203\r
+X-CUSTOM:kept\r
+END:VCARD\r
+BEGIN:VCARD\r
+VERSION:4.0\r
+UID:urn:uuid:4fbe8971-0bc3-424c-9c26-36c3e1eff6b1\r
+FN;LANGUAGE=en:Second\\, Card\r
+END:VCARD\r
+";
+        let cards = VCard::parse_all(input).unwrap();
+        assert_eq!(cards.len(), 2);
+        let card = &cards[0];
+        assert_eq!(card.formatted_name.as_deref(), Some("Simon Perreault"));
+        let name = card.name.as_ref().unwrap();
+        assert_eq!(name.family_names, ["Perreault"]);
+        assert_eq!(name.honorific_suffixes, ["ing. jr", "M.Sc."]);
+        assert_eq!(card.organization, ["Viagenie", "Research"]);
+        assert_eq!(card.telephones[0].types, ["work", "voice"]);
+        assert_eq!(card.telephones[0].value, "tel:+1-418-656-9254;ext=102");
+        assert_eq!(card.addresses[0].value.street, ["2875 Laurier"]);
+        assert_eq!(card.addresses[0].value.po_box, Vec::<String>::new());
+        assert_eq!(card.other[0].name, "X-CUSTOM");
+        // a FN with params does not fit the typed field
+        assert_eq!(cards[1].formatted_name, None);
+        assert_eq!(cards[1].other[0].value, "Second\\, Card");
+        let output: String = cards.iter().map(VCard::to_string).collect();
+        assert_eq!(output, input);
+
+        let mut card = VCard {
+            formatted_name: Some("New; Card".to_string()),
+            ..VCard::default()
+        };
+        card.emails.push(Entry::new("new@example.com".to_string()));
+        assert_eq!(
+            card.to_string(),
+            "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:New\\; Card\r\nEMAIL:new@example.com\r\nEND:VCARD\r\n"
+        );
+        assert_eq!(card.to_string().parse::<VCard>().unwrap(), card);
+    }
+}
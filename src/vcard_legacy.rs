@@ -0,0 +1,257 @@
+// vCard 2.1 and 3.0 compatibility
+//
+// 2.1 cards may have params without a name (`TEL;HOME;VOICE:`), quoted-printable
+// values in any CHARSET and base64 blocks, 3.0 cards inline binary values
+// with ENCODING=b, see https://www.imc.org/pdi/vcard-21.txt and RFC 2426
+
+use eyre::{eyre, Result};
+
+use crate::{
+    content_line::{ContentLine, Param},
+    ical_object::ICalObject,
+    value::split_unescaped,
+};
+
+// the values of ENCODING in 2.1, they may appear without the param name
+const ENCODINGS: [&str; 4] = ["7BIT", "8BIT", "BASE64", "QUOTED-PRINTABLE"];
+
+// text properties whose commas are literal in 2.1, but separate values in 4.0
+const TEXT_PROPERTIES: [&str; 7] = ["ADR", "FN", "N", "NOTE", "ORG", "ROLE", "TITLE"];
+
+// properties whose value may be inline binary data
+const BINARY_PROPERTIES: [&str; 4] = ["KEY", "LOGO", "PHOTO", "SOUND"];
+
+/// parses an unfolded 2.1 or 3.0 line, params without a name become TYPE or
+/// ENCODING params and quoted-printable values are decoded with their CHARSET
+pub fn parse_line(line: &str) -> Result<ContentLine> {
+    let (params, value) = line.split_once(':').unwrap_or((line, ""));
+    let mut params = params.split(';');
+    let mut rewritten = params.next().unwrap_or_default().to_string();
+    for param in params {
+        rewritten.push(';');
+        if param.contains('=') {
+            rewritten.push_str(param);
+        } else if ENCODINGS
+            .iter()
+            .any(|encoding| encoding.eq_ignore_ascii_case(param))
+        {
+            rewritten.push_str("ENCODING=");
+            rewritten.push_str(param);
+        } else {
+            rewritten.push_str("TYPE=");
+            rewritten.push_str(param);
+        }
+    }
+    let mut line: ContentLine = format!("{}:{}", rewritten, value).parse()?;
+    decode(&mut line)?;
+    Ok(line)
+}
+
+// decodes a quoted-printable value and removes ENCODING and CHARSET
+fn decode(line: &mut ContentLine) -> Result<()> {
+    let is_quoted_printable = line
+        .param("ENCODING")
+        .and_then(|param| param.values().first())
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("QUOTED-PRINTABLE"));
+    if !is_quoted_printable {
+        return Ok(());
+    }
+    let charset = line
+        .param("CHARSET")
+        .and_then(|param| param.values().first())
+        .cloned()
+        .unwrap_or_else(|| "UTF-8".to_string());
+    let decoded = decode_charset(&decode_quoted_printable(&line.value), &charset)?;
+    // line breaks are the only characters that can not appear in a value
+    line.value = decoded.replace("\r\n", "\\n").replace('\n', "\\n");
+    line.params
+        .retain(|param| !param.name_eq("ENCODING") && !param.name_eq("CHARSET"));
+    Ok(())
+}
+
+/// decodes quoted-printable, see RFC 2045 section 6.7,
+/// invalid escapes are kept as they are
+pub fn decode_quoted_printable(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'=' {
+            // a trailing '=' is a soft line break
+            if i + 1 == bytes.len() {
+                break;
+            }
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+            if let Some(byte) = hex {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+// the characters 0x80 to 0x9F of windows-1252, the others are the same as in ISO-8859-1
+const WINDOWS_1252: [u32; 32] = [
+    0x20AC, 0x81, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039,
+    0x0152, 0x8D, 0x017D, 0x8F, 0x90, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014,
+    0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0x9D, 0x017E, 0x0178,
+];
+
+/// decodes UTF-8, US-ASCII, ISO-8859-1 and windows-1252
+pub fn decode_charset(bytes: &[u8], charset: &str) -> Result<String> {
+    match charset.to_ascii_uppercase().as_str() {
+        "UTF-8" | "UTF8" | "US-ASCII" | "ASCII" => Ok(String::from_utf8(bytes.to_vec())?),
+        "ISO-8859-1" | "LATIN1" => Ok(bytes.iter().map(|&byte| char::from(byte)).collect()),
+        "WINDOWS-1252" | "CP1252" => Ok(bytes
+            .iter()
+            .map(|&byte| match byte {
+                0x80..=0x9F => char::from_u32(WINDOWS_1252[byte as usize - 0x80]).unwrap(),
+                byte => char::from(byte),
+            })
+            .collect()),
+        _ => Err(eyre!("unsupported charset {}", charset)),
+    }
+}
+
+/// converts a decoded 2.1 or 3.0 VCARD to 4.0
+///
+/// TYPE values are lowercased, the PREF type becomes a PREF param,
+/// inline binary values become data URIs and the commas of 2.1 text values are escaped
+pub fn upgrade_to_v4(object: &mut ICalObject) {
+    let is_v21 = object
+        .get_property("VERSION")
+        .is_some_and(|line| line.value.trim() == "2.1");
+    for line in &mut object.properties {
+        if line.name_eq("VERSION") {
+            line.value = "4.0".to_string();
+            continue;
+        }
+        let mut params = Vec::new();
+        let mut types = Vec::new();
+        let mut encoding = None;
+        for param in line.params.drain(..) {
+            if param.name_eq("TYPE") {
+                types.extend(
+                    param
+                        .values()
+                        .iter()
+                        .flat_map(|value| value.split(','))
+                        .map(str::to_ascii_lowercase),
+                );
+            } else if param.name_eq("ENCODING") {
+                encoding = param
+                    .values()
+                    .first()
+                    .map(|value| value.to_ascii_uppercase());
+            } else if param.name_eq("CHARSET") {
+                // the value is UTF-8 by now
+            } else if param.name_eq("VALUE")
+                && param
+                    .values()
+                    .iter()
+                    .any(|value| value.eq_ignore_ascii_case("URL"))
+            {
+                // URIs are the default in 4.0
+            } else {
+                params.push(param);
+            }
+        }
+        if types.iter().any(|value| value == "pref") {
+            types.retain(|value| value != "pref");
+            params.push(Param::new("PREF".to_string(), vec!["1".to_string()]));
+        }
+        // INTERNET is the only kind of EMAIL in 4.0
+        if line.name_eq("EMAIL") {
+            types.retain(|value| value != "internet");
+        }
+        let is_binary = BINARY_PROPERTIES.iter().any(|name| line.name_eq(name));
+        if is_binary && matches!(encoding.as_deref(), Some("B" | "BASE64")) {
+            // the remaining type is the format, like JPEG
+            let media_type = types
+                .pop()
+                .map(|format| match format.as_str() {
+                    "jpeg" | "jpg" => "image/jpeg".to_string(),
+                    "png" => "image/png".to_string(),
+                    "gif" => "image/gif".to_string(),
+                    format if format.contains('/') => format.to_string(),
+                    format => format!("application/{}", format),
+                })
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            let data: String = line.value.chars().filter(|c| !c.is_whitespace()).collect();
+            line.value = format!("data:{};base64,{}", media_type, data);
+        }
+        if is_v21 && TEXT_PROPERTIES.iter().any(|name| line.name_eq(name)) {
+            line.value = split_unescaped(&line.value, ',').join("\\,");
+        }
+        if !types.is_empty() {
+            params.insert(0, Param::new("TYPE".to_string(), types));
+        }
+        line.params = params;
+    }
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use crate::VCard;
+
+    #[test]
+    fn reads_and_upgrades_old_cards() {
+        let input = "BEGIN:VCARD\r
+VERSION:2.1\r
+N;CHARSET=ISO-8859-1;ENCODING=QUOTED-PRINTABLE:M=FCller;J=FCrgen\r
+FN;ENCODING=QUOTED-PRINTABLE;CHARSET=UTF-8:J=C3=BCrgen M=C3=BCller, =\r
+Dr.\r
+NOTE;QUOTED-PRINTABLE:first line=0D=0Asecond line\r
+TEL;CELL;PREF:+49 170 1234567\r
+EMAIL;INTERNET:juergen@example.com\r
+PHOTO;ENCODING=BASE64;TYPE=JPEG:\r
+ /9j/4AAQ\r
+ SkZJRg==\r
+\r
+END:VCARD\r
+BEGIN:VCARD\r
+VERSION:3.0\r
+FN:Anna\r
+EMAIL;TYPE=INTERNET,WORK:anna@example.com\r
+END:VCARD\r
+";
+        let cards = VCard::parse_all_legacy(input).unwrap();
+        assert_eq!(cards.len(), 2);
+        let card = &cards[0];
+        assert_eq!(card.version, "2.1");
+        assert_eq!(card.name.as_ref().unwrap().family_names, ["Müller"]);
+        assert_eq!(card.telephones[0].types, ["CELL", "PREF"]);
+
+        let upgraded: String = cards
+            .iter()
+            .map(|card| card.to_v4().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            upgraded,
+            "BEGIN:VCARD\r
+VERSION:4.0\r
+FN:Jürgen Müller\\, Dr.\r
+N:Müller;Jürgen;;;\r
+TEL;TYPE=cell;PREF=1:+49 170 1234567\r
+EMAIL:juergen@example.com\r
+PHOTO:data:image/jpeg;base64,/9j/4AAQSkZJRg==\r
+NOTE:first line\\nsecond line\r
+END:VCARD\r
+BEGIN:VCARD\r
+VERSION:4.0\r
+FN:Anna\r
+EMAIL;TYPE=work:anna@example.com\r
+END:VCARD\r
+"
+        );
+        // without the legacy mode the soft line break is an error
+        assert!(VCard::parse_all(input).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+// streaming serializer, the io::Write counterpart to Unfold
+//
+// unlike the Display impl of ICalObject, no String is built per line:
+// every content line is formatted into a single reused buffer
+// and folded directly into the underlying writer
+
+use std::{fmt::Write as _, io::Write};
+
+use eyre::{eyre, Result};
+
+use crate::{
+    content_line::ContentLine,
+    fold::{FoldOptions, FoldSegments},
+    ical_object::{ChildRef, ICalObject},
+};
+
+#[derive(Debug)]
+pub struct ICalWriter<W: Write> {
+    write: W,
+    fold_options: FoldOptions,
+    line_buf: String,
+    open_objects: Vec<String>,
+}
+
+impl<W: Write> ICalWriter<W> {
+    pub fn new(write: W) -> ICalWriter<W> {
+        ICalWriter::with_fold_options(write, FoldOptions::default())
+    }
+
+    pub fn with_fold_options(write: W, fold_options: FoldOptions) -> ICalWriter<W> {
+        ICalWriter {
+            write,
+            fold_options,
+            line_buf: String::new(),
+            open_objects: Vec::new(),
+        }
+    }
+
+    /// writes `BEGIN:<object_type>`, the component stays open until [ICalWriter::end]
+    pub fn begin(&mut self, object_type: &str) -> Result<()> {
+        self.line_buf.clear();
+        write!(self.line_buf, "BEGIN:{}", object_type)?;
+        self.write_line_buf()?;
+        self.open_objects.push(object_type.to_string());
+        Ok(())
+    }
+
+    /// writes `END:<object_type>` for the innermost open component
+    pub fn end(&mut self) -> Result<()> {
+        let object_type = self
+            .open_objects
+            .pop()
+            .ok_or(eyre!("end called without an open component"))?;
+        self.line_buf.clear();
+        write!(self.line_buf, "END:{}", object_type)?;
+        self.write_line_buf()
+    }
+
+    pub fn write_content_line(&mut self, line: &ContentLine) -> Result<()> {
+        self.line_buf.clear();
+        write!(self.line_buf, "{}", line)?;
+        self.write_line_buf()
+    }
+
+    /// writes a complete component, including its sub objects
+    pub fn write_object(&mut self, object: &ICalObject) -> Result<()> {
+        self.begin(&object.object_type)?;
+        for child in object.children() {
+            match child {
+                ChildRef::Property(line) => self.write_content_line(line)?,
+                ChildRef::SubObject(sub_object) => self.write_object(sub_object)?,
+            }
+        }
+        self.end()
+    }
+
+    /// the components that have been begun but not yet ended, outermost first
+    pub fn open_objects(&self) -> &[String] {
+        &self.open_objects
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.write.flush()?)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.write
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.write
+    }
+
+    /// flushes and returns the underlying writer,
+    /// fails if there are components that have not been ended
+    pub fn into_inner(mut self) -> Result<W> {
+        if let Some(object_type) = self.open_objects.last() {
+            return Err(eyre!("component {} was not ended", object_type));
+        }
+        self.flush()?;
+        Ok(self.write)
+    }
+
+    fn write_line_buf(&mut self) -> Result<()> {
+        let mut continuation = [0; 4];
+        let continuation = self
+            .fold_options
+            .continuation
+            .as_char()
+            .encode_utf8(&mut continuation);
+        for (i, segment) in
+            FoldSegments::with_options(&self.line_buf, &self.fold_options).enumerate()
+        {
+            if i > 0 {
+                self.write.write_all(b"\r\n")?;
+                self.write.write_all(continuation.as_bytes())?;
+            }
+            self.write.write_all(segment.as_bytes())?;
+        }
+        self.write.write_all(b"\r\n")?;
+        Ok(())
+    }
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::ICalWriter;
+    use crate::{ContentLine, ICalObject};
+
+    #[test]
+    fn streams_the_same_bytes_as_display() {
+        let ical: ICalObject = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:1@example.com\r
+DESCRIPTION;LANGUAGE=de:Ein sehr langer Text mit Umlauten äöü, der auf jeden Fall gefaltet werden muss\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        .parse()
+        .unwrap();
+
+        let mut writer = ICalWriter::new(Vec::new());
+        writer.write_object(&ical).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            ical.to_string()
+        );
+
+        // incremental output
+        let mut writer = ICalWriter::new(Vec::new());
+        writer.begin("VCALENDAR").unwrap();
+        writer.write_content_line(&ical.properties[0]).unwrap();
+        writer.begin("VEVENT").unwrap();
+        for line in &ical.sub_objects[0].properties {
+            writer.write_content_line(line).unwrap();
+        }
+        assert_eq!(writer.open_objects(), ["VCALENDAR", "VEVENT"]);
+        writer.end().unwrap();
+        writer.end().unwrap();
+        assert!(writer.end().is_err());
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            ical.to_string()
+        );
+
+        let mut writer = ICalWriter::new(Vec::new());
+        writer.begin("VCALENDAR").unwrap();
+        writer
+            .write_content_line(&ContentLine::new(
+                "VERSION".to_string(),
+                Vec::new(),
+                "2.0".to_string(),
+            ))
+            .unwrap();
+        assert!(writer.into_inner().is_err());
+    }
+}
@@ -0,0 +1,557 @@
+// xCal, the XML format for iCalendar, see RFC 6321
+//
+// an ICalObject becomes `<vcalendar><properties>...</properties><components>...</components></vcalendar>`,
+// every property becomes `<name><parameters>...</parameters><type>value</type></name>`
+
+use std::fmt::Write;
+
+use eyre::{eyre, Result};
+
+use crate::{
+    content_line::{ContentLine, Param},
+    ical_object::ICalObject,
+    value::{
+        escape_text, from_extended, is_multi_valued, split_unescaped, to_extended, unescape_text,
+        ValueType,
+    },
+    xml::{escape, Element},
+};
+
+pub const NAMESPACE: &str = "urn:ietf:params:xml:ns:icalendar-2.0";
+
+/// converts an object to a complete xCal document
+pub fn to_xcal(object: &ICalObject) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>");
+    write!(out, "<icalendar xmlns=\"{}\">", NAMESPACE).unwrap();
+    write_component(&mut out, object);
+    out.push_str("</icalendar>");
+    out
+}
+
+fn write_component(out: &mut String, object: &ICalObject) {
+    let name = object.object_type.to_ascii_lowercase();
+    write!(out, "<{}>", name).unwrap();
+    if !object.properties.is_empty() {
+        out.push_str("<properties>");
+        for line in &object.properties {
+            write_property(out, line);
+        }
+        out.push_str("</properties>");
+    }
+    if !object.sub_objects.is_empty() {
+        out.push_str("<components>");
+        for sub_object in &object.sub_objects {
+            write_component(out, sub_object);
+        }
+        out.push_str("</components>");
+    }
+    write!(out, "</{}>", name).unwrap();
+}
+
+// value types of parameters, see RFC 6321 section 3.5
+fn param_value_type(param_name: &str) -> &'static str {
+    match param_name.to_ascii_uppercase().as_str() {
+        "ALTREP" | "DIR" => "uri",
+        "DELEGATED-FROM" | "DELEGATED-TO" | "MEMBER" | "SENT-BY" => "cal-address",
+        "RSVP" => "boolean",
+        _ => "text",
+    }
+}
+
+fn write_property(out: &mut String, line: &ContentLine) {
+    let name = line.name.to_ascii_lowercase();
+    let value_type = ValueType::of(line);
+    write!(out, "<{}>", name).unwrap();
+    let params: Vec<&Param> = line
+        .params
+        .iter()
+        .filter(|param| !param.name_eq("VALUE"))
+        .collect();
+    if !params.is_empty() {
+        out.push_str("<parameters>");
+        for param in params {
+            let param_name = param.name().to_ascii_lowercase();
+            let param_type = param_value_type(&param_name);
+            write!(out, "<{}>", param_name).unwrap();
+            for value in param.values() {
+                let value = if param_type == "boolean" {
+                    value.to_ascii_lowercase()
+                } else {
+                    value.clone()
+                };
+                write!(out, "<{0}>{1}</{0}>", param_type, escape(&value)).unwrap();
+            }
+            write!(out, "</{}>", param_name).unwrap();
+        }
+        out.push_str("</parameters>");
+    }
+    let element = |name: &str, text: &str| format!("<{0}>{1}</{0}>", name, escape(text));
+    if line.name_eq("GEO") {
+        let parts = split_unescaped(&line.value, ';');
+        out.push_str(&element("latitude", parts[0]));
+        out.push_str(&element("longitude", parts.get(1).unwrap_or(&"")));
+    } else if line.name_eq("REQUEST-STATUS") {
+        let parts = split_unescaped(&line.value, ';');
+        for (part_name, part) in ["code", "description", "data"].iter().zip(parts) {
+            out.push_str(&element(part_name, &unescape_text(part)));
+        }
+    } else {
+        let values = if is_multi_valued(&line.name) {
+            split_unescaped(&line.value, ',')
+        } else {
+            vec![line.value.as_str()]
+        };
+        for value in values {
+            write_value(out, value_type, value);
+        }
+    }
+    write!(out, "</{}>", name).unwrap();
+}
+
+fn write_value(out: &mut String, value_type: ValueType, value: &str) {
+    let element = |name: &str, text: &str| format!("<{0}>{1}</{0}>", name, escape(text));
+    match value_type {
+        ValueType::Text => out.push_str(&element("text", &unescape_text(value))),
+        ValueType::Boolean => out.push_str(&element("boolean", &value.to_ascii_lowercase())),
+        ValueType::Period => {
+            out.push_str("<period>");
+            let (start, end) = value.split_once('/').unwrap_or((value, ""));
+            out.push_str(&element("start", &to_extended(ValueType::DateTime, start)));
+            if end.trim_start_matches(['+', '-']).starts_with('P') {
+                out.push_str(&element("duration", end));
+            } else {
+                out.push_str(&element("end", &to_extended(ValueType::DateTime, end)));
+            }
+            out.push_str("</period>");
+        }
+        ValueType::Recur => {
+            // every value of a rule part is its own element, see RFC 6321 section 3.6.10
+            out.push_str("<recur>");
+            for part in value.split(';').filter(|part| !part.is_empty()) {
+                let (part_name, values) = part.split_once('=').unwrap_or((part, ""));
+                let part_name = part_name.to_ascii_lowercase();
+                for value in values.split(',') {
+                    let value = if part_name == "until" {
+                        let until_type = if value.contains('T') {
+                            ValueType::DateTime
+                        } else {
+                            ValueType::Date
+                        };
+                        to_extended(until_type, value)
+                    } else {
+                        value.to_string()
+                    };
+                    out.push_str(&element(&part_name, &value));
+                }
+            }
+            out.push_str("</recur>");
+        }
+        value_type => out.push_str(&element(
+            &value_type.name().to_ascii_lowercase(),
+            &to_extended(value_type, value),
+        )),
+    }
+}
+
+/// parses an xCal document containing a single component
+pub fn from_xcal(xml: &str) -> Result<ICalObject> {
+    let root = Element::parse(xml)?;
+    if !root.is("icalendar") {
+        return Err(eyre!("expected an icalendar element, found {}", root.name));
+    }
+    match root.children.as_slice() {
+        [component] => component_from_xcal(component),
+        _ => Err(eyre!(
+            "expected exactly one component, found {}",
+            root.children.len()
+        )),
+    }
+}
+
+fn component_from_xcal(element: &Element) -> Result<ICalObject> {
+    let mut object = ICalObject {
+        object_type: element.name.to_ascii_uppercase(),
+        ..ICalObject::default()
+    };
+    if let Some(properties) = element.child("properties") {
+        for property in &properties.children {
+            object.properties.push(property_from_xcal(property)?);
+        }
+    }
+    if let Some(components) = element.child("components") {
+        for component in &components.children {
+            object.sub_objects.push(component_from_xcal(component)?);
+        }
+    }
+    Ok(object)
+}
+
+fn property_from_xcal(element: &Element) -> Result<ContentLine> {
+    let name = element.name.to_ascii_uppercase();
+    let mut params = Vec::new();
+    if let Some(parameters) = element.child("parameters") {
+        for param in &parameters.children {
+            let values = param
+                .children
+                .iter()
+                .map(|value| {
+                    if value.is("boolean") {
+                        value.text.to_ascii_uppercase()
+                    } else {
+                        value.text.clone()
+                    }
+                })
+                .collect();
+            params.push(Param::new(param.name.to_ascii_uppercase(), values));
+        }
+    }
+    let values: Vec<&Element> = element
+        .children
+        .iter()
+        .filter(|child| !child.is("parameters"))
+        .collect();
+    let first = values
+        .first()
+        .ok_or(eyre!("property {} has no value", name))?;
+
+    let value = if name == "GEO" {
+        let part = |part_name: &str| {
+            element
+                .child(part_name)
+                .map(|part| part.text.clone())
+                .ok_or(eyre!("GEO without {}", part_name))
+        };
+        format!("{};{}", part("latitude")?, part("longitude")?)
+    } else if name == "REQUEST-STATUS" {
+        values
+            .iter()
+            .map(|part| escape_text(&part.text))
+            .collect::<Vec<_>>()
+            .join(";")
+    } else {
+        let value_type = ValueType::from_name(&first.name);
+        if value_type != ValueType::default_for(&name) && !first.is("unknown") {
+            params.push(Param::new(
+                "VALUE".to_string(),
+                vec![first.name.to_ascii_uppercase()],
+            ));
+        }
+        values
+            .iter()
+            .map(|value| value_from_xcal(value_type, value))
+            .collect::<Result<Vec<_>>>()?
+            .join(",")
+    };
+    Ok(ContentLine::new(name, params, value))
+}
+
+fn value_from_xcal(value_type: ValueType, element: &Element) -> Result<String> {
+    Ok(match value_type {
+        ValueType::Text => escape_text(&element.text),
+        ValueType::Boolean => element.text.to_ascii_uppercase(),
+        ValueType::Period => {
+            let start = element
+                .child("start")
+                .ok_or(eyre!("period without start"))?;
+            let start = from_extended(ValueType::DateTime, &start.text);
+            match (element.child("end"), element.child("duration")) {
+                (Some(end), _) => format!(
+                    "{}/{}",
+                    start,
+                    from_extended(ValueType::DateTime, &end.text)
+                ),
+                (None, Some(duration)) => format!("{}/{}", start, duration.text),
+                (None, None) => return Err(eyre!("period without end or duration")),
+            }
+        }
+        ValueType::Recur => {
+            // consecutive elements with the same name are the values of one rule part
+            let mut parts: Vec<(String, Vec<String>)> = Vec::new();
+            for part in &element.children {
+                let part_name = part.name.to_ascii_uppercase();
+                let value = if part_name == "UNTIL" {
+                    from_extended(ValueType::DateTime, &part.text)
+                } else {
+                    part.text.clone()
+                };
+                match parts.last_mut() {
+                    Some((last_name, values)) if *last_name == part_name => values.push(value),
+                    _ => parts.push((part_name, vec![value])),
+                }
+            }
+            parts
+                .into_iter()
+                .map(|(part_name, values)| format!("{}={}", part_name, values.join(",")))
+                .collect::<Vec<_>>()
+                .join(";")
+        }
+        value_type => from_extended(value_type, &element.text),
+    })
+}
+
+// tests
+#[cfg(test)]
+mod tests {
+    use super::{from_xcal, to_xcal};
+    use crate::ICalObject;
+
+    #[test]
+    fn round_trips_the_rfc_examples() {
+        // RFC 6321 appendix B.1
+        let ical: ICalObject = "BEGIN:VCALENDAR\r
+CALSCALE:GREGORIAN\r
+PRODID:-//Example Inc.//Example Calendar//EN\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+DTSTAMP:20080205T191224Z\r
+DTSTART;VALUE=DATE:20081006\r
+SUMMARY:Planning meeting\r
+UID:4088E990AD89CB3DBB484909\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        .parse()
+        .unwrap();
+        let xcal = r#"<?xml version="1.0" encoding="utf-8"?>
+<icalendar xmlns="urn:ietf:params:xml:ns:icalendar-2.0">
+ <vcalendar>
+  <properties>
+   <calscale><text>GREGORIAN</text></calscale>
+   <prodid>
+    <text>-//Example Inc.//Example Calendar//EN</text>
+   </prodid>
+   <version><text>2.0</text></version>
+  </properties>
+  <components>
+   <vevent>
+    <properties>
+     <dtstamp>
+       <date-time>2008-02-05T19:12:24Z</date-time>
+     </dtstamp>
+     <dtstart><date>2008-10-06</date></dtstart>
+     <summary>
+      <text>Planning meeting</text>
+     </summary>
+     <uid>
+      <text>4088E990AD89CB3DBB484909</text>
+     </uid>
+    </properties>
+   </vevent>
+  </components>
+ </vcalendar>
+</icalendar>
+"#;
+        assert_eq!(from_xcal(xcal).unwrap(), ical);
+        assert_eq!(
+            to_xcal(&ical),
+            xcal.lines().map(str::trim).collect::<String>()
+        );
+
+        // RFC 6321 appendix B.2
+        let ical: ICalObject = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//Example Inc.//Example Client//EN\r
+BEGIN:VTIMEZONE\r
+LAST-MODIFIED:20040110T032845Z\r
+TZID:US/Eastern\r
+BEGIN:DAYLIGHT\r
+DTSTART:20000404T020000\r
+RRULE:FREQ=YEARLY;BYDAY=1SU;BYMONTH=4\r
+TZNAME:EDT\r
+TZOFFSETFROM:-0500\r
+TZOFFSETTO:-0400\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+DTSTART:20001026T020000\r
+RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=10\r
+TZNAME:EST\r
+TZOFFSETFROM:-0400\r
+TZOFFSETTO:-0500\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+DTSTAMP:20060206T001121Z\r
+DTSTART;TZID=US/Eastern:20060102T120000\r
+DURATION:PT1H\r
+RRULE:FREQ=DAILY;COUNT=5\r
+RDATE;TZID=US/Eastern;VALUE=PERIOD:20060102T150000/PT2H\r
+SUMMARY:Event #2\r
+DESCRIPTION:We are having a meeting all this week at 12 pm fo\r
+ r one hour\\, with an additional meeting on the first day 2 h\r
+ ours long.\\nPlease bring your own lunch for the 12 pm meetin\r
+ gs.\r
+UID:00959BC664CA650E933C892C@example.com\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTAMP:20060206T001121Z\r
+DTSTART;TZID=US/Eastern:20060104T140000\r
+DURATION:PT1H\r
+RECURRENCE-ID;TZID=US/Eastern:20060104T120000\r
+SUMMARY:Event #2 bis\r
+UID:00959BC664CA650E933C892C@example.com\r
+END:VEVENT\r
+END:VCALENDAR\r
+"
+        .parse()
+        .unwrap();
+        let xcal = r#"<?xml version="1.0" encoding="UTF-8"?>
+<icalendar xmlns="urn:ietf:params:xml:ns:icalendar-2.0">
+  <vcalendar>
+    <properties>
+      <prodid>
+        <text>-//Example Inc.//Example Client//EN</text>
+      </prodid>
+      <version>
+        <text>2.0</text>
+      </version>
+    </properties>
+    <components>
+      <vtimezone>
+        <properties>
+          <last-modified>
+            <date-time>2004-01-10T03:28:45Z</date-time>
+          </last-modified>
+          <tzid><text>US/Eastern</text></tzid>
+        </properties>
+        <components>
+          <daylight>
+            <properties>
+              <dtstart>
+                <date-time>2000-04-04T02:00:00</date-time>
+              </dtstart>
+              <rrule>
+                <recur>
+                  <freq>YEARLY</freq>
+                  <byday>1SU</byday>
+                  <bymonth>4</bymonth>
+                </recur>
+              </rrule>
+              <tzname>
+                <text>EDT</text>
+              </tzname>
+              <tzoffsetfrom>
+                <utc-offset>-05:00</utc-offset>
+              </tzoffsetfrom>
+              <tzoffsetto>
+                <utc-offset>-04:00</utc-offset>
+              </tzoffsetto>
+            </properties>
+          </daylight>
+          <standard>
+            <properties>
+              <dtstart>
+                <date-time>2000-10-26T02:00:00</date-time>
+              </dtstart>
+              <rrule>
+                <recur>
+                  <freq>YEARLY</freq>
+                  <byday>-1SU</byday>
+                  <bymonth>10</bymonth>
+                </recur>
+              </rrule>
+              <tzname>
+                <text>EST</text>
+              </tzname>
+              <tzoffsetfrom>
+                <utc-offset>-04:00</utc-offset>
+              </tzoffsetfrom>
+              <tzoffsetto>
+                <utc-offset>-05:00</utc-offset>
+              </tzoffsetto>
+            </properties>
+          </standard>
+        </components>
+      </vtimezone>
+      <vevent>
+        <properties>
+          <dtstamp>
+            <date-time>2006-02-06T00:11:21Z</date-time>
+          </dtstamp>
+          <dtstart>
+            <parameters>
+              <tzid><text>US/Eastern</text></tzid>
+            </parameters>
+            <date-time>2006-01-02T12:00:00</date-time>
+          </dtstart>
+          <duration>
+            <duration>PT1H</duration>
+          </duration>
+          <rrule>
+            <recur>
+              <freq>DAILY</freq>
+              <count>5</count>
+            </recur>
+          </rrule>
+          <rdate>
+            <parameters>
+              <tzid><text>US/Eastern</text></tzid>
+            </parameters>
+            <period>
+              <start>2006-01-02T15:00:00</start>
+              <duration>PT2H</duration>
+            </period>
+          </rdate>
+          <summary>
+            <text>Event #2</text>
+          </summary>
+          <description>
+            <text>We are having a meeting all this week at 12pm for one hour, with an additional meeting on the first day 2 hours long.
+Please bring your own lunch for the 12 pm meetings.</text>
+          </description>
+          <uid>
+            <text>00959BC664CA650E933C892C@example.com</text>
+          </uid>
+        </properties>
+      </vevent>
+      <vevent>
+        <properties>
+          <dtstamp>
+            <date-time>2006-02-06T00:11:21Z</date-time>
+          </dtstamp>
+          <dtstart>
+            <parameters>
+              <tzid><text>US/Eastern</text></tzid>
+            </parameters>
+            <date-time>2006-01-04T14:00:00</date-time>
+          </dtstart>
+          <duration><duration>PT1H</duration></duration>
+          <recurrence-id>
+            <parameters>
+              <tzid><text>US/Eastern</text></tzid>
+            </parameters>
+            <date-time>2006-01-04T12:00:00</date-time>
+          </recurrence-id>
+          <summary>
+            <text>Event #2 bis</text>
+          </summary>
+          <uid>
+            <text>00959BC664CA650E933C892C@example.com</text>
+          </uid>
+        </properties>
+      </vevent>
+    </components>
+  </vcalendar>
+</icalendar>
+"#;
+        assert_eq!(from_xcal(&to_xcal(&ical)).unwrap(), ical);
+        let parsed = from_xcal(xcal).unwrap();
+        let body = |xml: &str| {
+            let root = &xml[xml.find("<icalendar").unwrap()..];
+            root.lines().map(str::trim).collect::<String>()
+        };
+        assert_eq!(body(&to_xcal(&parsed)), body(xcal));
+        assert_eq!(parsed.to_string().parse::<ICalObject>().unwrap(), parsed);
+        // the two versions of the RFC differ in the order of VERSION and
+        // PRODID and in the "12 pm" of the description, not in the components
+        assert_eq!(parsed.sub_objects[0], ical.sub_objects[0]);
+        assert_eq!(parsed.sub_objects[2], ical.sub_objects[2]);
+        let description = |object: &ICalObject| {
+            object.sub_objects[1]
+                .get_property("DESCRIPTION")
+                .unwrap()
+                .value
+                .replace("12pm", "12 pm")
+        };
+        assert_eq!(description(&parsed), description(&ical));
+    }
+}
@@ -0,0 +1,89 @@
+// a minimal element tree on top of quick-xml,
+// shared by the XML based formats (xCal, CalDAV)
+//
+// namespaces are not resolved, elements and attributes are identified by
+// their local name, which is unambiguous for the documents we read
+
+use eyre::{eyre, Result};
+use quick_xml::{events::Event, Reader};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// the text content, only kept for elements without child elements
+    pub text: String,
+}
+
+impl Element {
+    /// parses a document and returns its root element
+    pub fn parse(xml: &str) -> Result<Element> {
+        let mut reader = Reader::from_str(xml);
+        reader.expand_empty_elements(true);
+        let mut stack: Vec<Element> = Vec::new();
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => {
+                    let mut element = Element {
+                        name: String::from_utf8(start.local_name().as_ref().to_vec())?,
+                        ..Element::default()
+                    };
+                    for attribute in start.attributes() {
+                        let attribute = attribute?;
+                        element.attributes.push((
+                            String::from_utf8(attribute.key.local_name().as_ref().to_vec())?,
+                            attribute.unescape_value()?.into_owned(),
+                        ));
+                    }
+                    stack.push(element);
+                }
+                Event::End(_) => {
+                    let mut element = stack.pop().ok_or(eyre!("unexpected end tag"))?;
+                    if !element.children.is_empty() {
+                        element.text.clear();
+                    }
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                Event::Text(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&text.unescape()?);
+                    }
+                }
+                Event::CData(data) => {
+                    if let Some(element) = stack.last_mut() {
+                        element
+                            .text
+                            .push_str(std::str::from_utf8(&data.into_inner())?);
+                    }
+                }
+                Event::Eof => return Err(eyre!("unexpected end of document")),
+                _ => (),
+            }
+        }
+    }
+
+    pub fn is(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.is(name))
+    }
+
+    #[cfg(feature = "caldav")]
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// escapes text and attribute values
+pub(crate) fn escape(text: &str) -> std::borrow::Cow<'_, str> {
+    quick_xml::escape::escape(text)
+}